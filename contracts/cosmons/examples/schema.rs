@@ -4,7 +4,11 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use cosmons::msg::{HandleMsg, InitMsg, MinterResponse, QueryMsg};
+use cosmons::msg::{
+    BattlesResponse, ChallengesResponse, ChampionsResponse, EvolutionChainResponse, HandleMsg,
+    InitMsg, LineageResponse, MinterResponse, QueryMsg, RankingResponse, RatingHistoryResponse,
+    SeasonResponse, StaminaResponse, TokenLevelsResponse,
+};
 use cw721::{
     ApprovedForAllResponse, ContractInfoResponse, NumTokensResponse, OwnerOfResponse,
     TokensResponse,
@@ -27,4 +31,14 @@ fn main() {
     export_schema(&schema_for!(NumTokensResponse), &out_dir);
     export_schema(&schema_for!(OwnerOfResponse), &out_dir);
     export_schema(&schema_for!(TokensResponse), &out_dir);
+    export_schema(&schema_for!(TokenLevelsResponse), &out_dir);
+    export_schema(&schema_for!(StaminaResponse), &out_dir);
+    export_schema(&schema_for!(ChallengesResponse), &out_dir);
+    export_schema(&schema_for!(BattlesResponse), &out_dir);
+    export_schema(&schema_for!(SeasonResponse), &out_dir);
+    export_schema(&schema_for!(ChampionsResponse), &out_dir);
+    export_schema(&schema_for!(RankingResponse), &out_dir);
+    export_schema(&schema_for!(RatingHistoryResponse), &out_dir);
+    export_schema(&schema_for!(LineageResponse), &out_dir);
+    export_schema(&schema_for!(EvolutionChainResponse), &out_dir);
 }
@@ -1,8 +1,8 @@
 use crate::package::AllNftInfoResponse;
-use crate::package::NftInfoResponse;
+use crate::package::{Element, NftInfoResponse, Stats};
 use cosmwasm_std::{
     attr, to_binary, Api, Binary, BlockInfo, Env, Extern, HandleResponse, HumanAddr, InitResponse,
-    MessageInfo, Order, Querier, StdError, StdResult, Storage, KV,
+    MessageInfo, Order, Querier, QueryRequest, StdError, StdResult, Storage, WasmQuery, KV,
 };
 use cw721::Cw721ReceiveMsg;
 
@@ -14,11 +14,23 @@ use cw721::{
 };
 
 use crate::error::ContractError;
-use crate::msg::{HandleMsg, InitMsg, MintMsg, MinterResponse, QueryMsg};
+use crate::msg::{
+    BattleResponse, BattlesResponse, ChallengeResponse, ChallengesResponse, ChampionsResponse,
+    EvolutionChainResponse, EvolutionResponse, HandleMsg, InitMsg, LineageResponse, MintMsg,
+    MinterResponse, QueryMsg, RankingResponse, RatingHistoryResponse, RatingResponse,
+    RentalQueryMsg, SeasonResponse, StaminaResponse, TokenLevel, TokenLevelsResponse,
+    UserOfResponse,
+};
 use crate::state::{
-    increment_tokens, num_tokens, tokens, Approval, TokenInfo, CONTRACT_INFO, MINTER, OPERATORS,
+    battles, challenges, current_season, increment_battles, increment_challenges, increment_tokens,
+    level_key, num_tokens, ranking_key, rating_key, ratings, season_ranking, tokens,
+    tokens_by_level, Approval, Battle, Breeding, Challenge, Evolution, Rating, Season, Stamina,
+    TokenInfo, BREEDING, CONTRACT_INFO, EVOLUTIONS, MINTER, OPERATORS, PARENTS, SEASON, SEASONS,
+    STAMINA,
 };
-use cw_storage_plus::Bound;
+use cw_storage_plus::{Bound, MultiIndex};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:cosmons";
@@ -54,6 +66,59 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             attacker_id,
             defender_id,
         } => handle_battle_monster(deps, env, info, attacker_id, defender_id),
+        HandleMsg::Challenge {
+            attacker_id,
+            defender_id,
+            expires,
+            commitment,
+        } => handle_challenge(
+            deps,
+            env,
+            info,
+            attacker_id,
+            defender_id,
+            expires,
+            commitment,
+        ),
+        HandleMsg::AcceptChallenge {
+            challenge_id,
+            commitment,
+        } => handle_accept_challenge(deps, env, info, challenge_id, commitment),
+        HandleMsg::DeclineChallenge { challenge_id } => {
+            handle_decline_challenge(deps, env, info, challenge_id)
+        }
+        HandleMsg::RevealSecret {
+            challenge_id,
+            secret,
+        } => handle_reveal_secret(deps, env, challenge_id, secret),
+        HandleMsg::ClaimForfeit { challenge_id } => handle_claim_forfeit(deps, env, challenge_id),
+        HandleMsg::Breed {
+            parent_a,
+            parent_b,
+            token_id,
+        } => handle_breed(deps, env, info, parent_a, parent_b, token_id),
+        HandleMsg::AddEvolution {
+            species,
+            level,
+            evolved_name,
+            evolved_image,
+            boosts,
+        } => handle_add_evolution(
+            deps,
+            env,
+            info,
+            species,
+            Evolution {
+                level,
+                evolved_name,
+                evolved_image,
+                boosts,
+            },
+        ),
+        HandleMsg::Evolve { token_id } => handle_evolve(deps, env, info, token_id),
+        HandleMsg::RolloverSeason { end_height } => {
+            handle_rollover_season(deps, env, info, end_height)
+        }
         HandleMsg::Approve {
             spender,
             token_id,
@@ -78,207 +143,1086 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     }
 }
 
+/// Blocks both sides of an accepted challenge have to reveal their secrets in
+const REVEAL_BLOCKS: u64 = 100;
+/// Most stamina a monster can have, every battle costs one
+const MAX_STAMINA: u64 = 5;
+/// Blocks it takes to regenerate one stamina
+const STAMINA_REGEN_BLOCKS: u64 = 100;
+/// Blocks a monster has to rest after a battle
+const BATTLE_COOLDOWN_BLOCKS: u64 = 10;
+
 pub fn handle_battle_monster<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: Env,
-    _info: MessageInfo,
+    env: Env,
+    info: MessageInfo,
     attacker_id: String,
     defender_id: String,
 ) -> Result<HandleResponse, ContractError> {
-    let mut info_attacker_id = tokens().load(&deps.storage, &attacker_id)?;
-    let mut info_defender_id = tokens().load(&deps.storage, &defender_id)?;
-    if info_attacker_id.level >= info_defender_id.level {
-        info_attacker_id.level += 2;
-        info_defender_id.level += 1;
-    } else {
-        info_attacker_id.level += 1;
-        info_defender_id.level += 2;
+    if attacker_id == defender_id {
+        return Err(ContractError::SelfBattle {});
     }
-    tokens().save(&mut deps.storage, &attacker_id, &info_attacker_id)?;
-    tokens().save(&mut deps.storage, &defender_id, &info_defender_id)?;
+    let info_attacker_id = tokens().load(&deps.storage, &attacker_id)?;
+    let info_defender_id = tokens().load(&deps.storage, &defender_id)?;
+    check_can_battle_directly(deps, &env, &info, &attacker_id, &info_attacker_id)?;
+    check_can_battle_directly(deps, &env, &info, &defender_id, &info_defender_id)?;
+    check_rested(&deps.storage, &attacker_id, env.block.height)?;
+    check_rested(&deps.storage, &defender_id, env.block.height)?;
+
+    // the sender controls both monsters and nobody else has a stake in the outcome, so block
+    // data is random enough
+    let mut seed = vec![];
+    seed.extend_from_slice(&env.block.height.to_be_bytes());
+    seed.extend_from_slice(&env.block.time.to_be_bytes());
+    seed.extend_from_slice(env.block.chain_id.as_bytes());
+    seed.extend_from_slice(attacker_id.as_bytes());
+    seed.extend_from_slice(defender_id.as_bytes());
+    let (battle_id, winner) = battle(
+        &mut deps.storage,
+        env.block.height,
+        &seed,
+        &attacker_id,
+        info_attacker_id,
+        &defender_id,
+        info_defender_id,
+    )?;
+
     Ok(HandleResponse {
         messages: vec![],
         attributes: vec![
             attr("action", "battle_monster"),
-            attr("attacker_id", attacker_id),
-            attr("defender_id", defender_id),
+            attr("attacker_id", &attacker_id),
+            attr("defender_id", &defender_id),
+            attr("winner", winner),
+            attr("battle_id", battle_id),
         ],
         data: None,
     })
 }
 
-pub fn handle_mint<S: Storage, A: Api, Q: Querier>(
+/// Rounds after which a battle goes to the monster with more of its hp left
+const MAX_ROUNDS: u64 = 50;
+
+/// battle fights two monsters, levels up the winner and records the battle unrated.
+/// Both monsters spend stamina on it. Returns the battle id and the winner's id
+fn battle<S: Storage>(
+    storage: &mut S,
+    height: u64,
+    seed: &[u8],
+    attacker_id: &str,
+    info_attacker_id: TokenInfo,
+    defender_id: &str,
+    info_defender_id: TokenInfo,
+) -> StdResult<(u64, String)> {
+    exhaust(storage, attacker_id, height)?;
+    exhaust(storage, defender_id, height)?;
+    let attacker_wins = fight(seed, &info_attacker_id, &info_defender_id);
+    settle(
+        storage,
+        height,
+        attacker_id,
+        info_attacker_id,
+        defender_id,
+        info_defender_id,
+        attacker_wins,
+        false,
+        false,
+    )
+}
+
+/// settle levels up the winner of a battle and adds it to the battle history. Only ranked
+/// battles move the ratings. Returns the battle id and the winner's id
+#[allow(clippy::too_many_arguments)]
+fn settle<S: Storage>(
+    storage: &mut S,
+    height: u64,
+    attacker_id: &str,
+    info_attacker_id: TokenInfo,
+    defender_id: &str,
+    info_defender_id: TokenInfo,
+    attacker_wins: bool,
+    forfeit: bool,
+    ranked: bool,
+) -> StdResult<(u64, String)> {
+    let mut record = Battle {
+        attacker_id: attacker_id.to_string(),
+        defender_id: defender_id.to_string(),
+        attacker_owner: info_attacker_id.owner.clone(),
+        defender_owner: info_defender_id.owner.clone(),
+        attacker_level_before: info_attacker_id.level,
+        attacker_level_after: info_attacker_id.level,
+        defender_level_before: info_defender_id.level,
+        defender_level_after: info_defender_id.level,
+        winner: String::new(),
+        height,
+        forfeit,
+    };
+    if attacker_wins {
+        record.attacker_level_after =
+            level_up(storage, attacker_id, info_attacker_id, &info_defender_id)?;
+        record.winner = attacker_id.to_string();
+        if ranked {
+            rate(storage, height, attacker_id, defender_id)?;
+        }
+    } else {
+        record.defender_level_after =
+            level_up(storage, defender_id, info_defender_id, &info_attacker_id)?;
+        record.winner = defender_id.to_string();
+        if ranked {
+            rate(storage, height, defender_id, attacker_id)?;
+        }
+    }
+
+    let battle_id = increment_battles(storage)?;
+    battles().save(storage, &battle_id.to_be_bytes(), &record)?;
+    Ok((battle_id, record.winner))
+}
+
+/// fight simulates a battle turn by turn, the faster monster striking first.
+/// Returns whether the attacker won
+fn fight(seed: &[u8], attacker: &TokenInfo, defender: &TokenInfo) -> bool {
+    let attacker_stats = stats_at_level(&attacker.stats, attacker.level);
+    let defender_stats = stats_at_level(&defender.stats, defender.level);
+    let mut attacker_hp = attacker_stats.hp;
+    let mut defender_hp = defender_stats.hp;
+    let attacker_first = attacker_stats.speed >= defender_stats.speed;
+
+    for round in 0..MAX_ROUNDS {
+        for &attacker_strikes in &[attacker_first, !attacker_first] {
+            let roll = damage_roll(seed, round, attacker_strikes);
+            if attacker_strikes {
+                let dealt = damage(
+                    &attacker_stats,
+                    attacker.element,
+                    &defender_stats,
+                    defender.element,
+                    roll,
+                );
+                defender_hp = defender_hp.saturating_sub(dealt);
+                if defender_hp == 0 {
+                    return true;
+                }
+            } else {
+                let dealt = damage(
+                    &defender_stats,
+                    defender.element,
+                    &attacker_stats,
+                    attacker.element,
+                    roll,
+                );
+                attacker_hp = attacker_hp.saturating_sub(dealt);
+                if attacker_hp == 0 {
+                    return false;
+                }
+            }
+        }
+    }
+    // compare the shares of hp left
+    attacker_hp as u128 * defender_stats.hp as u128
+        > defender_hp as u128 * attacker_stats.hp as u128
+}
+
+/// damage_roll derives a roll between 85 and 100 percent for a strike from the seed
+fn damage_roll(seed: &[u8], round: u64, attacker_strikes: bool) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(seed);
+    hasher.update(round.to_be_bytes());
+    hasher.update([attacker_strikes as u8]);
+    let mut head = [0u8; 8];
+    head.copy_from_slice(&hasher.finalize()[..8]);
+    85 + u64::from_be_bytes(head) % 16
+}
+
+/// damage of a single strike, at least one
+fn damage(
+    attacker: &Stats,
+    attacking: Element,
+    defender: &Stats,
+    defending: Element,
+    roll: u64,
+) -> u64 {
+    let raw = attacker
+        .attack
+        .saturating_mul(effectiveness(attacking, defending))
+        / 100
+        * roll
+        / 100;
+    raw.saturating_sub(defender.defense / 2).max(1)
+}
+
+/// effectiveness of an attack in percent: fire burns grass, grass drinks water,
+/// water douses fire and electricity shocks water
+fn effectiveness(attacking: Element, defending: Element) -> u64 {
+    use Element::*;
+    match (attacking, defending) {
+        (Fire, Grass) | (Grass, Water) | (Water, Fire) | (Electric, Water) => 200,
+        (Fire, Water) | (Grass, Fire) | (Water, Grass) | (Electric, Grass) => 50,
+        (Fire, Fire) | (Water, Water) | (Grass, Grass) | (Electric, Electric) => 50,
+        _ => 100,
+    }
+}
+
+/// stats_at_level grows the level 1 stats by every level gained since
+fn stats_at_level(stats: &Stats, level: u64) -> Stats {
+    let gained = level.saturating_sub(1);
+    Stats {
+        hp: stats.hp.saturating_add(gained.saturating_mul(3)),
+        attack: stats.attack.saturating_add(gained.saturating_mul(2)),
+        defense: stats.defense.saturating_add(gained.saturating_mul(2)),
+        speed: stats.speed.saturating_add(gained),
+    }
+}
+
+/// rest_state returns the stamina of a monster at the given height and the blocks left
+/// until it may battle again. Stamina regenerated towards the next point is lost in a battle
+fn rest_state<S: Storage>(storage: &S, token_id: &str, height: u64) -> StdResult<(u64, u64)> {
+    let state = match STAMINA.may_load(storage, token_id)? {
+        Some(stamina) => {
+            let rested = height.saturating_sub(stamina.last_battle_height);
+            let regenerated = stamina.stamina + rested / STAMINA_REGEN_BLOCKS;
+            (
+                regenerated.min(MAX_STAMINA),
+                BATTLE_COOLDOWN_BLOCKS.saturating_sub(rested),
+            )
+        }
+        None => (MAX_STAMINA, 0),
+    };
+    Ok(state)
+}
+
+/// check_rested rejects monsters that are out of stamina or battled too recently
+fn check_rested<S: Storage>(storage: &S, token_id: &str, height: u64) -> Result<(), ContractError> {
+    let (stamina, cooldown) = rest_state(storage, token_id, height)?;
+    if stamina == 0 || cooldown > 0 {
+        return Err(ContractError::Exhausted {
+            token_id: token_id.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// exhaust spends one stamina of a monster on a battle at the given height
+fn exhaust<S: Storage>(storage: &mut S, token_id: &str, height: u64) -> StdResult<()> {
+    let (stamina, _) = rest_state(storage, token_id, height)?;
+    let stamina = Stamina {
+        last_battle_height: height,
+        stamina: stamina.saturating_sub(1),
+    };
+    STAMINA.save(storage, token_id, &stamina)
+}
+
+/// level_up rewards the winner of a battle with a level, and another one for beating a
+/// higher level monster. The loser doesn't level up. Returns the winner's new level
+fn level_up<S: Storage>(
+    storage: &mut S,
+    winner_id: &str,
+    mut info_winner: TokenInfo,
+    info_loser: &TokenInfo,
+) -> StdResult<u64> {
+    info_winner.level += if info_loser.level > info_winner.level {
+        2
+    } else {
+        1
+    };
+    tokens().save(storage, winner_id, &info_winner)?;
+    Ok(info_winner.level)
+}
+
+/// Times a monster can breed in its life
+const MAX_BREEDS: u64 = 3;
+/// Blocks a monster has to rest after breeding
+const BREED_COOLDOWN_BLOCKS: u64 = 1000;
+
+pub fn handle_breed<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
-    msg: MintMsg,
+    parent_a: String,
+    parent_b: String,
+    token_id: String,
 ) -> Result<HandleResponse, ContractError> {
-    let minter = MINTER.load(&deps.storage)?;
-    let sender_raw = deps.api.canonical_address(&info.sender)?;
+    if parent_a == parent_b {
+        return Err(ContractError::SelfBreeding {});
+    }
+    let info_a = tokens().load(&deps.storage, &parent_a)?;
+    let info_b = tokens().load(&deps.storage, &parent_b)?;
+    check_can_send(deps, &env, &info, &info_a)?;
+    check_can_send(deps, &env, &info, &info_b)?;
+    // the child goes to the owner of the parents, an operator of two owners can't pick one
+    if info_a.owner != info_b.owner {
+        return Err(ContractError::DifferentOwners {});
+    }
+    let mut breedings = vec![];
+    for parent in &[&parent_a, &parent_b] {
+        let breeding = BREEDING
+            .may_load(&deps.storage, parent)?
+            .unwrap_or_default();
+        if breeding.breed_count >= MAX_BREEDS {
+            return Err(ContractError::BreedLimit {
+                token_id: parent.to_string(),
+            });
+        }
+        if breed_cooldown(&breeding, env.block.height) > 0 {
+            return Err(ContractError::BreedingCooldown {
+                token_id: parent.to_string(),
+            });
+        }
+        breedings.push(breeding);
+    }
 
-    if sender_raw != minter {
-        return Err(ContractError::Unauthorized {});
+    // the seed only mixes block data with the parents, so the sender can work out the child for
+    // the coming blocks and the parent order, and breed when they like the result. The token id
+    // is left out at least, so it can't be ground offline for the best stats
+    let mut seed = Sha256::new();
+    seed.update(env.block.height.to_be_bytes());
+    seed.update(env.block.time.to_be_bytes());
+    seed.update(env.block.chain_id.as_bytes());
+    for (parent, breeding) in [&parent_a, &parent_b].iter().zip(&breedings) {
+        seed.update(parent.as_bytes());
+        seed.update(breeding.breed_count.to_be_bytes());
     }
+    let seed = seed.finalize();
 
-    // create the token
-    let token = TokenInfo {
-        owner: deps.api.canonical_address(&msg.owner)?,
+    let child = TokenInfo {
+        owner: info_a.owner.clone(),
         approvals: vec![],
-        name: msg.name,
-        level: msg.level,
-        description: msg.description.unwrap_or_default(),
-        image: msg.image,
+        name: child_name(seed[0], &info_a.name, &info_b.name),
+        // the child is of the species of the parent its name starts with
+        species: Some(
+            if seed[0] < 128 {
+                info_a.species()
+            } else {
+                info_b.species()
+            }
+            .to_string(),
+        ),
+        level: 1,
+        element: if seed[1] < 128 {
+            info_a.element
+        } else {
+            info_b.element
+        },
+        stats: Stats {
+            hp: inherit(info_a.stats.hp, info_b.stats.hp, seed[2]),
+            attack: inherit(info_a.stats.attack, info_b.stats.attack, seed[3]),
+            defense: inherit(info_a.stats.defense, info_b.stats.defense, seed[4]),
+            speed: inherit(info_a.stats.speed, info_b.stats.speed, seed[5]),
+        },
+        description: String::new(),
+        image: None,
     };
-    tokens().update(&mut deps.storage, &msg.token_id, |old| match old {
+    tokens().update(&mut deps.storage, &token_id, |old| match old {
         Some(_) => Err(ContractError::Claimed {}),
-        None => Ok(token),
+        None => Ok(child),
     })?;
-
     increment_tokens(&mut deps.storage)?;
+    PARENTS.save(
+        &mut deps.storage,
+        &token_id,
+        &(parent_a.clone(), parent_b.clone()),
+    )?;
+    for (parent, mut breeding) in [&parent_a, &parent_b].iter().zip(breedings) {
+        breeding.breed_count += 1;
+        breeding.last_breed_height = Some(env.block.height);
+        BREEDING.save(&mut deps.storage, parent, &breeding)?;
+    }
 
     Ok(HandleResponse {
         messages: vec![],
         attributes: vec![
-            attr("action", "mint"),
-            attr("minter", info.sender),
-            attr("token_id", msg.token_id),
+            attr("action", "breed"),
+            attr("parent_a", parent_a),
+            attr("parent_b", parent_b),
+            attr("token_id", token_id),
         ],
         data: None,
     })
 }
 
-pub fn handle_transfer_nft<S: Storage, A: Api, Q: Querier>(
+/// Blocks left until a monster may breed again
+fn breed_cooldown(breeding: &Breeding, height: u64) -> u64 {
+    match breeding.last_breed_height {
+        Some(last) => (last + BREED_COOLDOWN_BLOCKS).saturating_sub(height),
+        None => 0,
+    }
+}
+
+/// child_name joins the front half of one parent's name with the back half of the other's
+fn child_name(roll: u8, name_a: &str, name_b: &str) -> String {
+    let (front, back) = if roll < 128 {
+        (name_a, name_b)
+    } else {
+        (name_b, name_a)
+    };
+    let front_len = front.chars().count() - front.chars().count() / 2;
+    let back_len = back.chars().count() - back.chars().count() / 2;
+    front
+        .chars()
+        .take(front_len)
+        .chain(back.chars().skip(back_len))
+        .collect()
+}
+
+/// inherit averages a stat of both parents and varies it by up to 2 either way
+fn inherit(stat_a: u64, stat_b: u64, roll: u8) -> u64 {
+    ((stat_a + stat_b) / 2 + (roll % 5) as u64)
+        .saturating_sub(2)
+        .max(1)
+}
+
+pub fn handle_add_evolution<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    env: Env,
+    _env: Env,
     info: MessageInfo,
-    recipient: HumanAddr,
-    token_id: String,
+    species: String,
+    evolution: Evolution,
 ) -> Result<HandleResponse, ContractError> {
-    _transfer_nft(deps, &env, &info, &recipient, &token_id)?;
+    let minter = MINTER.load(&deps.storage)?;
+    let sender_raw = deps.api.canonical_address(&info.sender)?;
+    if sender_raw != minter {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // evolution chains have to end, so the chain can't lead back to the species
+    let mut next = Some(evolution.evolved_name.clone());
+    while let Some(name) = next {
+        if name == species {
+            return Err(ContractError::CircularEvolution {
+                species,
+                evolved_name: evolution.evolved_name,
+            });
+        }
+        next = EVOLUTIONS
+            .may_load(&deps.storage, &name)?
+            .map(|e| e.evolved_name);
+    }
+    EVOLUTIONS.save(&mut deps.storage, &species, &evolution)?;
 
     Ok(HandleResponse {
         messages: vec![],
         attributes: vec![
-            attr("action", "transfer_nft"),
-            attr("sender", info.sender),
-            attr("recipient", recipient),
-            attr("token_id", token_id),
+            attr("action", "add_evolution"),
+            attr("species", species),
+            attr("evolved_name", evolution.evolved_name),
         ],
         data: None,
     })
 }
 
-pub fn handle_send_nft<S: Storage, A: Api, Q: Querier>(
+pub fn handle_evolve<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    env: Env,
+    _env: Env,
     info: MessageInfo,
-    contract: HumanAddr,
     token_id: String,
-    msg: Option<Binary>,
 ) -> Result<HandleResponse, ContractError> {
-    // Transfer token
-    _transfer_nft(deps, &env, &info, &contract, &token_id)?;
+    let mut token = tokens().load(&deps.storage, &token_id)?;
+    let sender_raw = deps.api.canonical_address(&info.sender)?;
+    if token.owner != sender_raw {
+        return Err(ContractError::Unauthorized {});
+    }
+    let species = token.species().to_string();
+    let evolution = EVOLUTIONS
+        .may_load(&deps.storage, &species)?
+        .ok_or_else(|| ContractError::NoEvolution {
+            species: species.clone(),
+        })?;
+    if token.level < evolution.level {
+        return Err(ContractError::LevelTooLow {
+            level: evolution.level,
+        });
+    }
 
-    let send = Cw721ReceiveMsg {
-        sender: info.sender.clone(),
-        token_id: token_id.clone(),
-        msg,
+    token.species = Some(evolution.evolved_name.clone());
+    token.name = evolution.evolved_name;
+    if evolution.evolved_image.is_some() {
+        token.image = evolution.evolved_image;
+    }
+    token.stats = Stats {
+        hp: token.stats.hp.saturating_add(evolution.boosts.hp),
+        attack: token.stats.attack.saturating_add(evolution.boosts.attack),
+        defense: token.stats.defense.saturating_add(evolution.boosts.defense),
+        speed: token.stats.speed.saturating_add(evolution.boosts.speed),
     };
+    tokens().save(&mut deps.storage, &token_id, &token)?;
 
-    // Send message
     Ok(HandleResponse {
-        messages: vec![send.into_cosmos_msg(contract.clone())?],
+        messages: vec![],
         attributes: vec![
-            attr("action", "send_nft"),
-            attr("sender", info.sender),
-            attr("recipient", contract),
+            attr("action", "evolve"),
             attr("token_id", token_id),
+            attr("species", species),
+            attr("evolved_name", token.name),
         ],
         data: None,
     })
 }
 
-pub fn _transfer_nft<S: Storage, A: Api, Q: Querier>(
+/// Rating of monsters in their first ranked season
+const INITIAL_RATING: u64 = 1200;
+/// Most rating points a single battle can move
+const RATING_K: u64 = 32;
+/// Expected score per mille of the better rated side, by rating difference in steps of 50.
+/// Contracts can't use floats, so the ELO curve is interpolated between these
+const EXPECTED_SCORES: [u64; 17] = [
+    500, 571, 640, 703, 760, 808, 849, 882, 909, 930, 947, 960, 969, 977, 983, 987, 990,
+];
+
+/// rate moves the ELO ratings of both sides of a battle in the current season.
+/// Once the season's block range is over, battles are unrated until it is rolled over
+fn rate<S: Storage>(
+    storage: &mut S,
+    height: u64,
+    winner_id: &str,
+    loser_id: &str,
+) -> StdResult<()> {
+    let season = current_season(storage)?;
+    if matches!(season.end_height, Some(end) if height >= end) {
+        return Ok(());
+    }
+    let mut winner = season_rating(storage, season.season, winner_id)?;
+    let mut loser = season_rating(storage, season.season, loser_id)?;
+    let gain = RATING_K * (1000 - expected_score(winner.rating, loser.rating)) / 1000;
+    winner.rating += gain;
+    winner.wins += 1;
+    loser.rating = loser.rating.saturating_sub(gain);
+    loser.losses += 1;
+    ratings().save(storage, &rating_key(season.season, winner_id), &winner)?;
+    ratings().save(storage, &rating_key(season.season, loser_id), &loser)
+}
+
+/// season_rating loads the rating of a monster in a season. Monsters new to the season start
+/// halfway between their last rating and the initial one
+fn season_rating<S: Storage>(storage: &S, season: u64, token_id: &str) -> StdResult<Rating> {
+    if let Some(rating) = ratings().may_load(storage, &rating_key(season, token_id))? {
+        return Ok(rating);
+    }
+    let ratings = ratings::<S>();
+    let last = ratings
+        .idx
+        .token
+        .items(storage, token_id.as_bytes(), None, None, Order::Descending)
+        .next()
+        .transpose()?;
+    let rating = match last {
+        Some((_, last)) => (last.rating + INITIAL_RATING) / 2,
+        None => INITIAL_RATING,
+    };
+    Ok(Rating {
+        token_id: token_id.to_string(),
+        season,
+        rating,
+        wins: 0,
+        losses: 0,
+    })
+}
+
+/// expected_score is the score per mille a monster is expected to make against the opponent
+fn expected_score(rating: u64, opponent: u64) -> u64 {
+    let diff = rating.max(opponent) - rating.min(opponent);
+    let expected = if diff >= 800 {
+        EXPECTED_SCORES[16]
+    } else {
+        let step = (diff / 50) as usize;
+        let (low, high) = (EXPECTED_SCORES[step], EXPECTED_SCORES[step + 1]);
+        low + (high - low) * (diff % 50) / 50
+    };
+    if rating >= opponent {
+        expected
+    } else {
+        1000 - expected
+    }
+}
+
+pub fn handle_rollover_season<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    env: &Env,
-    info: &MessageInfo,
-    recipient: &HumanAddr,
-    token_id: &str,
-) -> Result<TokenInfo, ContractError> {
-    let mut token = tokens().load(&deps.storage, &token_id)?;
-    // ensure we have permissions
-    check_can_send(&deps, env, info, &token)?;
-    // set owner and remove existing approvals
-    token.owner = deps.api.canonical_address(recipient)?;
-    token.approvals = vec![];
-    tokens().save(&mut deps.storage, &token_id, &token)?;
-    Ok(token)
+    env: Env,
+    info: MessageInfo,
+    end_height: Option<u64>,
+) -> Result<HandleResponse, ContractError> {
+    let minter = MINTER.load(&deps.storage)?;
+    let sender_raw = deps.api.canonical_address(&info.sender)?;
+    if sender_raw != minter {
+        return Err(ContractError::Unauthorized {});
+    }
+    let mut season = current_season(&deps.storage)?;
+    if let Some(end_height) = season.end_height {
+        if env.block.height < end_height {
+            return Err(ContractError::SeasonNotOver { end_height });
+        }
+    }
+    if matches!(end_height, Some(end) if end <= env.block.height) {
+        return Err(ContractError::InvalidSeasonEnd {});
+    }
+
+    // the ratings stay where they are, the next season resets them as monsters battle
+    let champion = season_ranking(&deps.storage, season.season, None)
+        .next()
+        .transpose()?;
+    season.end_height = season.end_height.or(Some(env.block.height));
+    season.champion_rating = champion.as_ref().map(|(rating, _)| *rating);
+    season.champion = champion.map(|(_, token_id)| token_id);
+    SEASONS.save(&mut deps.storage, &season.season.to_be_bytes(), &season)?;
+    let next = Season {
+        season: season.season + 1,
+        start_height: env.block.height,
+        end_height,
+        champion: None,
+        champion_rating: None,
+    };
+    SEASON.save(&mut deps.storage, &next)?;
+
+    let mut attributes = vec![
+        attr("action", "rollover_season"),
+        attr("season", next.season),
+    ];
+    if let Some(champion) = season.champion {
+        attributes.push(attr("champion", champion));
+    }
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes,
+        data: None,
+    })
 }
 
-pub fn handle_approve<S: Storage, A: Api, Q: Querier>(
+pub fn handle_challenge<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     info: MessageInfo,
-    spender: HumanAddr,
-    token_id: String,
+    attacker_id: String,
+    defender_id: String,
     expires: Option<Expiration>,
+    commitment: Binary,
 ) -> Result<HandleResponse, ContractError> {
-    _update_approvals(deps, &env, &info, &spender, &token_id, true, expires)?;
+    if attacker_id == defender_id {
+        return Err(ContractError::SelfBattle {});
+    }
+    let info_attacker_id = tokens().load(&deps.storage, &attacker_id)?;
+    check_can_battle(deps, &env, &info, &attacker_id, &info_attacker_id)?;
+    check_rested(&deps.storage, &attacker_id, env.block.height)?;
+    let info_defender_id = tokens().load(&deps.storage, &defender_id)?;
+
+    // reject expired challenges as invalid
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::ChallengeExpired {});
+    }
+
+    let challenger = deps.api.canonical_address(&info.sender)?;
+    let challenge_id = increment_challenges(&mut deps.storage)?;
+    let challenge = Challenge {
+        attacker_id: attacker_id.clone(),
+        defender_id: defender_id.clone(),
+        challenger,
+        defender: info_defender_id.owner,
+        expires,
+        challenger_commitment: commitment,
+        defender_commitment: None,
+        challenger_secret: None,
+        defender_secret: None,
+        reveal_deadline: None,
+    };
+    challenges().save(&mut deps.storage, &challenge_id.to_be_bytes(), &challenge)?;
 
     Ok(HandleResponse {
         messages: vec![],
         attributes: vec![
-            attr("action", "approve"),
+            attr("action", "challenge"),
+            attr("challenge_id", challenge_id),
             attr("sender", info.sender),
-            attr("spender", spender),
-            attr("token_id", token_id),
+            attr("attacker_id", attacker_id),
+            attr("defender_id", defender_id),
         ],
         data: None,
     })
 }
 
-pub fn handle_revoke<S: Storage, A: Api, Q: Querier>(
+pub fn handle_accept_challenge<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     info: MessageInfo,
-    spender: HumanAddr,
-    token_id: String,
+    challenge_id: u64,
+    commitment: Binary,
 ) -> Result<HandleResponse, ContractError> {
-    _update_approvals(deps, &env, &info, &spender, &token_id, false, None)?;
+    let key = challenge_id.to_be_bytes();
+    let mut challenge = challenges::<S>().load(&deps.storage, &key)?;
+    if challenge.reveal_deadline.is_some() {
+        return Err(ContractError::ChallengeAccepted {});
+    }
+    if challenge.expires.is_expired(&env.block) {
+        return Err(ContractError::ChallengeExpired {});
+    }
+    let info_defender_id = tokens().load(&deps.storage, &challenge.defender_id)?;
+    check_can_battle(deps, &env, &info, &challenge.defender_id, &info_defender_id)?;
+    let info_attacker_id = tokens().load(&deps.storage, &challenge.attacker_id)?;
+    check_challenger(deps, &env, &challenge, &info_attacker_id)?;
+    check_rested(&deps.storage, &challenge.attacker_id, env.block.height)?;
+    check_rested(&deps.storage, &challenge.defender_id, env.block.height)?;
+    // copying the challenger's commitment would let the defender reveal its secret as well
+    if commitment == challenge.challenger_commitment {
+        return Err(ContractError::InvalidCommitment {});
+    }
+    // the battle is paid for now, so accepted challenges can't pile up into more battles
+    // than the monsters have stamina for
+    exhaust(&mut deps.storage, &challenge.attacker_id, env.block.height)?;
+    exhaust(&mut deps.storage, &challenge.defender_id, env.block.height)?;
+
+    let reveal_deadline = env.block.height + REVEAL_BLOCKS;
+    challenge.defender = deps.api.canonical_address(&info.sender)?;
+    challenge.defender_commitment = Some(commitment);
+    challenge.reveal_deadline = Some(reveal_deadline);
+    challenges().save(&mut deps.storage, &key, &challenge)?;
 
     Ok(HandleResponse {
         messages: vec![],
         attributes: vec![
-            attr("action", "revoke"),
+            attr("action", "accept_challenge"),
+            attr("challenge_id", challenge_id),
             attr("sender", info.sender),
-            attr("spender", spender),
-            attr("token_id", token_id),
+            attr("reveal_deadline", reveal_deadline),
         ],
         data: None,
     })
 }
 
-pub fn _update_approvals<S: Storage, A: Api, Q: Querier>(
+pub fn handle_decline_challenge<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    env: &Env,
-    info: &MessageInfo,
-    spender: &HumanAddr,
-    token_id: &str,
-    // if add == false, remove. if add == true, remove then set with this expiration
-    add: bool,
-    expires: Option<Expiration>,
-) -> Result<TokenInfo, ContractError> {
-    let mut token = tokens().load(&deps.storage, &token_id)?;
-    // ensure we have permissions
-    check_can_approve(&deps, env, info, &token)?;
-
-    // update the approval list (remove any for the same spender before adding)
-    let spender_raw = deps.api.canonical_address(&spender)?;
-    token.approvals = token
-        .approvals
-        .into_iter()
-        .filter(|apr| apr.spender != spender_raw)
-        .collect();
+    env: Env,
+    info: MessageInfo,
+    challenge_id: u64,
+) -> Result<HandleResponse, ContractError> {
+    let key = challenge_id.to_be_bytes();
+    let challenge = challenges::<S>().load(&deps.storage, &key)?;
+    if challenge.reveal_deadline.is_some() {
+        return Err(ContractError::ChallengeAccepted {});
+    }
+    // the challenger cancels, and anyone may clear out an expired challenge
+    let sender_raw = deps.api.canonical_address(&info.sender)?;
+    if sender_raw != challenge.challenger && !challenge.expires.is_expired(&env.block) {
+        let info_defender_id = tokens().load(&deps.storage, &challenge.defender_id)?;
+        check_can_battle(deps, &env, &info, &challenge.defender_id, &info_defender_id)?;
+    }
+    challenges::<S>().remove(&mut deps.storage, &key)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "decline_challenge"),
+            attr("challenge_id", challenge_id),
+            attr("sender", info.sender),
+        ],
+        data: None,
+    })
+}
+
+pub fn handle_reveal_secret<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    challenge_id: u64,
+    secret: String,
+) -> Result<HandleResponse, ContractError> {
+    let key = challenge_id.to_be_bytes();
+    let mut challenge = challenges::<S>().load(&deps.storage, &key)?;
+    let reveal_deadline = challenge
+        .reveal_deadline
+        .ok_or(ContractError::ChallengeNotAccepted {})?;
+    if env.block.height > reveal_deadline {
+        return Err(ContractError::ChallengeExpired {});
+    }
+
+    // knowing the secret is enough, so anybody may reveal it
+    let hash = Sha256::digest(secret.as_bytes());
+    let matches = |commitment: &Binary| hash[..] == commitment.as_slice()[..];
+    if challenge.challenger_secret.is_none() && matches(&challenge.challenger_commitment) {
+        challenge.challenger_secret = Some(secret);
+    } else if challenge.defender_secret.is_none()
+        && matches!(challenge.defender_commitment, Some(ref c) if matches(c))
+    {
+        challenge.defender_secret = Some(secret);
+    } else {
+        return Err(ContractError::InvalidSecret {});
+    }
+
+    let mut attributes = vec![
+        attr("action", "reveal_secret"),
+        attr("challenge_id", challenge_id),
+    ];
+    match (&challenge.challenger_secret, &challenge.defender_secret) {
+        (Some(challenger_secret), Some(defender_secret)) => {
+            // neither side could predict the other's secret when committing
+            let mut seed = vec![];
+            seed.extend_from_slice(challenger_secret.as_bytes());
+            seed.extend_from_slice(defender_secret.as_bytes());
+            seed.extend_from_slice(&key);
+
+            let info_attacker_id = tokens().load(&deps.storage, &challenge.attacker_id)?;
+            let info_defender_id = tokens().load(&deps.storage, &challenge.defender_id)?;
+            challenges::<S>().remove(&mut deps.storage, &key)?;
+            if check_challenger(deps, &env, &challenge, &info_attacker_id).is_err()
+                || check_defender(deps, &env, &challenge, &info_defender_id).is_err()
+            {
+                attributes.push(attr("voided", true));
+                return Ok(HandleResponse {
+                    messages: vec![],
+                    attributes,
+                    data: None,
+                });
+            }
+            // both monsters spent their stamina when the challenge was accepted
+            let attacker_wins = fight(&seed, &info_attacker_id, &info_defender_id);
+            let (battle_id, winner) = settle(
+                &mut deps.storage,
+                env.block.height,
+                &challenge.attacker_id,
+                info_attacker_id,
+                &challenge.defender_id,
+                info_defender_id,
+                attacker_wins,
+                false,
+                challenge.is_ranked(),
+            )?;
+            attributes.push(attr("winner", winner));
+            attributes.push(attr("battle_id", battle_id));
+        }
+        _ => challenges().save(&mut deps.storage, &key, &challenge)?,
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes,
+        data: None,
+    })
+}
+
+pub fn handle_claim_forfeit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    challenge_id: u64,
+) -> Result<HandleResponse, ContractError> {
+    let key = challenge_id.to_be_bytes();
+    let challenge = challenges::<S>().load(&deps.storage, &key)?;
+    let reveal_deadline = challenge
+        .reveal_deadline
+        .ok_or(ContractError::ChallengeNotAccepted {})?;
+    if env.block.height <= reveal_deadline {
+        return Err(ContractError::RevealPending {});
+    }
+    challenges::<S>().remove(&mut deps.storage, &key)?;
+
+    let mut attributes = vec![
+        attr("action", "claim_forfeit"),
+        attr("challenge_id", challenge_id),
+    ];
+    // the side that revealed wins, the other one doesn't level up at all
+    let attacker_wins = match (&challenge.challenger_secret, &challenge.defender_secret) {
+        (Some(_), None) => Some(true),
+        (None, Some(_)) => Some(false),
+        _ => None,
+    };
+    let info_attacker_id = tokens().load(&deps.storage, &challenge.attacker_id)?;
+    let info_defender_id = tokens().load(&deps.storage, &challenge.defender_id)?;
+    if check_challenger(deps, &env, &challenge, &info_attacker_id).is_err()
+        || check_defender(deps, &env, &challenge, &info_defender_id).is_err()
+    {
+        attributes.push(attr("voided", true));
+    } else if let Some(attacker_wins) = attacker_wins {
+        let (battle_id, winner) = settle(
+            &mut deps.storage,
+            env.block.height,
+            &challenge.attacker_id,
+            info_attacker_id,
+            &challenge.defender_id,
+            info_defender_id,
+            attacker_wins,
+            true,
+            challenge.is_ranked(),
+        )?;
+        attributes.push(attr("winner", winner));
+        attributes.push(attr("battle_id", battle_id));
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes,
+        data: None,
+    })
+}
+
+pub fn handle_mint<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    info: MessageInfo,
+    msg: MintMsg,
+) -> Result<HandleResponse, ContractError> {
+    let minter = MINTER.load(&deps.storage)?;
+    let sender_raw = deps.api.canonical_address(&info.sender)?;
+
+    if sender_raw != minter {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // create the token
+    let token = TokenInfo {
+        owner: deps.api.canonical_address(&msg.owner)?,
+        approvals: vec![],
+        species: Some(msg.name.clone()),
+        name: msg.name,
+        level: msg.level,
+        element: msg.element.unwrap_or_default(),
+        stats: msg.stats.unwrap_or_default(),
+        description: msg.description.unwrap_or_default(),
+        image: msg.image,
+    };
+    tokens().update(&mut deps.storage, &msg.token_id, |old| match old {
+        Some(_) => Err(ContractError::Claimed {}),
+        None => Ok(token),
+    })?;
+
+    increment_tokens(&mut deps.storage)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "mint"),
+            attr("minter", info.sender),
+            attr("token_id", msg.token_id),
+        ],
+        data: None,
+    })
+}
+
+pub fn handle_transfer_nft<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    recipient: HumanAddr,
+    token_id: String,
+) -> Result<HandleResponse, ContractError> {
+    _transfer_nft(deps, &env, &info, &recipient, &token_id)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "transfer_nft"),
+            attr("sender", info.sender),
+            attr("recipient", recipient),
+            attr("token_id", token_id),
+        ],
+        data: None,
+    })
+}
+
+pub fn handle_send_nft<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    contract: HumanAddr,
+    token_id: String,
+    msg: Option<Binary>,
+) -> Result<HandleResponse, ContractError> {
+    // Transfer token
+    _transfer_nft(deps, &env, &info, &contract, &token_id)?;
+
+    let send = Cw721ReceiveMsg {
+        sender: info.sender.clone(),
+        token_id: token_id.clone(),
+        msg,
+    };
+
+    // Send message
+    Ok(HandleResponse {
+        messages: vec![send.into_cosmos_msg(contract.clone())?],
+        attributes: vec![
+            attr("action", "send_nft"),
+            attr("sender", info.sender),
+            attr("recipient", contract),
+            attr("token_id", token_id),
+        ],
+        data: None,
+    })
+}
+
+pub fn _transfer_nft<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    info: &MessageInfo,
+    recipient: &HumanAddr,
+    token_id: &str,
+) -> Result<TokenInfo, ContractError> {
+    let mut token = tokens().load(&deps.storage, &token_id)?;
+    // ensure we have permissions
+    check_can_send(&deps, env, info, &token)?;
+    // set owner and remove existing approvals
+    token.owner = deps.api.canonical_address(recipient)?;
+    token.approvals = vec![];
+    tokens().save(&mut deps.storage, &token_id, &token)?;
+    Ok(token)
+}
+
+pub fn handle_approve<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    spender: HumanAddr,
+    token_id: String,
+    expires: Option<Expiration>,
+) -> Result<HandleResponse, ContractError> {
+    _update_approvals(deps, &env, &info, &spender, &token_id, true, expires)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "approve"),
+            attr("sender", info.sender),
+            attr("spender", spender),
+            attr("token_id", token_id),
+        ],
+        data: None,
+    })
+}
+
+pub fn handle_revoke<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    spender: HumanAddr,
+    token_id: String,
+) -> Result<HandleResponse, ContractError> {
+    _update_approvals(deps, &env, &info, &spender, &token_id, false, None)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "revoke"),
+            attr("sender", info.sender),
+            attr("spender", spender),
+            attr("token_id", token_id),
+        ],
+        data: None,
+    })
+}
+
+pub fn _update_approvals<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    info: &MessageInfo,
+    spender: &HumanAddr,
+    token_id: &str,
+    // if add == false, remove. if add == true, remove then set with this expiration
+    add: bool,
+    expires: Option<Expiration>,
+) -> Result<TokenInfo, ContractError> {
+    let mut token = tokens().load(&deps.storage, &token_id)?;
+    // ensure we have permissions
+    check_can_approve(&deps, env, info, &token)?;
+
+    // update the approval list (remove any for the same spender before adding)
+    let spender_raw = deps.api.canonical_address(&spender)?;
+    token.approvals = token
+        .approvals
+        .into_iter()
+        .filter(|apr| apr.spender != spender_raw)
+        .collect();
 
     // only difference between approve and revoke
     if add {
@@ -375,6 +1319,89 @@ fn check_can_approve<S: Storage, A: Api, Q: Querier>(
     }
 }
 
+/// returns true iff the sender can send the monster into battle, which besides whoever can
+/// send it is the user it is rented out to by its owner
+fn check_can_battle<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    info: &MessageInfo,
+    token_id: &str,
+    token: &TokenInfo,
+) -> Result<(), ContractError> {
+    if check_can_send(deps, env, info, token).is_ok() {
+        return Ok(());
+    }
+
+    // only a contract renting the monster out answers this, for any other owner the query fails
+    let msg = RentalQueryMsg::UserOf {
+        contract_addr: env.contract.address.clone(),
+        token_id: token_id.to_string(),
+    };
+    let request: QueryRequest<_> = WasmQuery::Smart {
+        contract_addr: deps.api.human_address(&token.owner)?,
+        msg: to_binary(&msg)?,
+    }
+    .into();
+    match deps.querier.query::<UserOfResponse>(&request) {
+        Ok(UserOfResponse {
+            user: Some(user),
+            expires,
+        }) if user == info.sender && !matches!(expires, Some(e) if e.is_expired(&env.block)) => {
+            Ok(())
+        }
+        _ => Err(ContractError::Unauthorized {}),
+    }
+}
+
+/// check_can_battle_directly only lets whoever may send a monster battle it without a challenge.
+/// The sender picks the block of a direct battle, so a renter could pick one that favours their
+/// own monster over the rented one, renters have to challenge instead
+fn check_can_battle_directly<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    info: &MessageInfo,
+    token_id: &str,
+    token: &TokenInfo,
+) -> Result<(), ContractError> {
+    if check_can_send(deps, env, info, token).is_ok() {
+        return Ok(());
+    }
+    check_can_battle(deps, env, info, token_id, token)?;
+    Err(ContractError::ChallengeRequired {
+        token_id: token_id.to_string(),
+    })
+}
+
+/// check_challenger makes sure the challenger may still send the attacker into battle, it may
+/// have changed hands since the challenge was issued
+fn check_challenger<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    challenge: &Challenge,
+    attacker: &TokenInfo,
+) -> Result<(), ContractError> {
+    let info = MessageInfo {
+        sender: deps.api.human_address(&challenge.challenger)?,
+        sent_funds: vec![],
+    };
+    check_can_battle(deps, env, &info, &challenge.attacker_id, attacker)
+}
+
+/// check_defender makes sure whoever accepted the challenge may still send the defender into
+/// battle, it may have changed hands or its rental may have ended since
+fn check_defender<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    challenge: &Challenge,
+    defender: &TokenInfo,
+) -> Result<(), ContractError> {
+    let info = MessageInfo {
+        sender: deps.api.human_address(&challenge.defender)?,
+        sent_funds: vec![],
+    };
+    check_can_battle(deps, env, &info, &challenge.defender_id, defender)
+}
+
 /// returns true iff the sender can transfer ownership of the token
 fn check_can_send<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
@@ -460,7 +1487,83 @@ pub fn query<S: Storage, A: Api, Q: Querier>(
         QueryMsg::AllTokens { start_after, limit } => {
             to_binary(&query_all_tokens(deps, start_after, limit)?)
         }
-    }
+        QueryMsg::TopByLevel { start_after, limit } => {
+            to_binary(&query_top_by_level(deps, start_after, limit)?)
+        }
+        QueryMsg::TokensByLevelRange {
+            min,
+            max,
+            start_after,
+            limit,
+        } => to_binary(&query_tokens_by_level_range(
+            deps,
+            min,
+            max,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::Stamina { token_id } => to_binary(&query_stamina(deps, env, token_id)?),
+        QueryMsg::ChallengesByChallenger {
+            challenger,
+            start_after,
+            limit,
+        } => {
+            let challenges = challenges::<S>();
+            let index = &challenges.idx.challenger;
+            to_binary(&query_challenges(
+                deps,
+                env,
+                index,
+                challenger,
+                start_after,
+                limit,
+            )?)
+        }
+        QueryMsg::ChallengesByDefender {
+            defender,
+            start_after,
+            limit,
+        } => {
+            let challenges = challenges::<S>();
+            let index = &challenges.idx.defender;
+            to_binary(&query_challenges(
+                deps,
+                env,
+                index,
+                defender,
+                start_after,
+                limit,
+            )?)
+        }
+        QueryMsg::Lineage { token_id } => to_binary(&query_lineage(deps, env, token_id)?),
+        QueryMsg::EvolutionChain { species } => to_binary(&query_evolution_chain(deps, species)?),
+        QueryMsg::Season {} => to_binary(&query_season(deps)?),
+        QueryMsg::Ranking {
+            season,
+            start_after,
+            limit,
+        } => to_binary(&query_ranking(deps, season, start_after, limit)?),
+        QueryMsg::RatingHistory {
+            token_id,
+            start_after,
+            limit,
+        } => to_binary(&query_rating_history(deps, token_id, start_after, limit)?),
+        QueryMsg::Champions { start_after, limit } => {
+            to_binary(&query_champions(deps, start_after, limit)?)
+        }
+        QueryMsg::BattleHistory {
+            token_id,
+            owner,
+            start_after,
+            limit,
+        } => to_binary(&query_battle_history(
+            deps,
+            token_id,
+            owner,
+            start_after,
+            limit,
+        )?),
+    }
 }
 
 fn query_minter<S: Storage, A: Api, Q: Querier>(
@@ -490,8 +1593,10 @@ fn query_nft_info<S: Storage, A: Api, Q: Querier>(
 ) -> StdResult<NftInfoResponse> {
     let info = tokens().load(&deps.storage, &token_id)?;
     Ok(NftInfoResponse {
+        stats: stats_at_level(&info.stats, info.level),
         name: info.name,
         level: info.level,
+        element: info.element,
         description: info.description,
         image: info.image,
     })
@@ -580,6 +1685,360 @@ fn query_all_tokens<S: Storage, A: Api, Q: Querier>(
     Ok(TokensResponse { tokens: tokens? })
 }
 
+fn query_top_by_level<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<TokenLevelsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let end = match start_after {
+        Some(token_id) => Some(Bound::exclusive(level_cursor(deps, &token_id)?)),
+        None => None,
+    };
+
+    let tokens: StdResult<Vec<_>> = tokens_by_level(&deps.storage, None, end, Order::Descending)
+        .take(limit)
+        .map(|item| item.map(|(level, token_id)| TokenLevel { token_id, level }))
+        .collect();
+    Ok(TokenLevelsResponse { tokens: tokens? })
+}
+
+fn query_tokens_by_level_range<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    min: u64,
+    max: u64,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<TokenLevelsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let min_key = level_key(min, "");
+    let start = match start_after {
+        Some(token_id) => {
+            let cursor = level_cursor(deps, &token_id)?;
+            if cursor >= min_key {
+                Bound::exclusive(cursor)
+            } else {
+                Bound::inclusive(min_key)
+            }
+        }
+        None => Bound::inclusive(min_key),
+    };
+    // the keys of max end with the token id, so stop before the next level
+    let end = max
+        .checked_add(1)
+        .map(|level| Bound::exclusive(level_key(level, "")));
+
+    let tokens: StdResult<Vec<_>> =
+        tokens_by_level(&deps.storage, Some(start), end, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(level, token_id)| TokenLevel { token_id, level }))
+            .collect();
+    Ok(TokenLevelsResponse { tokens: tokens? })
+}
+
+/// level_cursor is where a token currently is in the level index, so pages continue after it
+fn level_cursor<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    token_id: &str,
+) -> StdResult<Vec<u8>> {
+    let info = tokens::<S>().load(&deps.storage, token_id)?;
+    Ok(level_key(info.level, token_id))
+}
+
+fn query_stamina<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    token_id: String,
+) -> StdResult<StaminaResponse> {
+    // fail for unknown monsters rather than reporting them rested
+    tokens::<S>().load(&deps.storage, &token_id)?;
+    let (stamina, cooldown) = rest_state(&deps.storage, &token_id, env.block.height)?;
+    let last_battle_height = STAMINA
+        .may_load(&deps.storage, &token_id)?
+        .map(|stamina| stamina.last_battle_height);
+    Ok(StaminaResponse {
+        stamina,
+        max_stamina: MAX_STAMINA,
+        cooldown,
+        last_battle_height,
+    })
+}
+
+/// query_challenges lists the pending challenges of an address in the given index,
+/// leaving out expired ones which were not accepted
+fn query_challenges<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    index: &MultiIndex<S, Challenge>,
+    addr: HumanAddr,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ChallengesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|id| Bound::exclusive(id.to_be_bytes().to_vec()));
+
+    let addr_raw = deps.api.canonical_address(&addr)?;
+    let res: StdResult<Vec<_>> = index
+        .items(&deps.storage, &addr_raw, start, None, Order::Ascending)
+        .filter(|r| match r {
+            Ok((_, c)) => c.reveal_deadline.is_some() || !c.expires.is_expired(&env.block),
+            Err(_) => true,
+        })
+        .take(limit)
+        .map(|item| item.and_then(|(k, challenge)| humanize_challenge(deps.api, &k, challenge)))
+        .collect();
+    Ok(ChallengesResponse { challenges: res? })
+}
+
+fn humanize_challenge<A: Api>(
+    api: A,
+    key: &[u8],
+    challenge: Challenge,
+) -> StdResult<ChallengeResponse> {
+    let mut id = [0u8; 8];
+    id.copy_from_slice(key);
+    Ok(ChallengeResponse {
+        challenge_id: u64::from_be_bytes(id),
+        attacker_id: challenge.attacker_id,
+        defender_id: challenge.defender_id,
+        challenger: api.human_address(&challenge.challenger)?,
+        defender: api.human_address(&challenge.defender)?,
+        expires: challenge.expires,
+        reveal_deadline: challenge.reveal_deadline,
+        challenger_revealed: challenge.challenger_secret.is_some(),
+        defender_revealed: challenge.defender_secret.is_some(),
+    })
+}
+
+fn query_lineage<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    token_id: String,
+) -> StdResult<LineageResponse> {
+    tokens::<S>().load(&deps.storage, &token_id)?;
+    let parents = match PARENTS.may_load(&deps.storage, &token_id)? {
+        Some((parent_a, parent_b)) => vec![parent_a, parent_b],
+        None => vec![],
+    };
+    let breeding = BREEDING
+        .may_load(&deps.storage, &token_id)?
+        .unwrap_or_default();
+    Ok(LineageResponse {
+        parents,
+        breed_count: breeding.breed_count,
+        max_breeds: MAX_BREEDS,
+        breed_cooldown: breed_cooldown(&breeding, env.block.height),
+    })
+}
+
+fn query_evolution_chain<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    species: String,
+) -> StdResult<EvolutionChainResponse> {
+    // chains never lead back, AddEvolution makes sure of that
+    let mut evolutions = vec![];
+    let mut next = Some(species);
+    while let Some(species) = next {
+        next = match EVOLUTIONS.may_load(&deps.storage, &species)? {
+            Some(evolution) => {
+                let evolved_name = evolution.evolved_name.clone();
+                evolutions.push(EvolutionResponse {
+                    species,
+                    level: evolution.level,
+                    evolved_name: evolution.evolved_name,
+                    evolved_image: evolution.evolved_image,
+                    boosts: evolution.boosts,
+                });
+                Some(evolved_name)
+            }
+            None => None,
+        };
+    }
+    Ok(EvolutionChainResponse { evolutions })
+}
+
+fn query_season<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<SeasonResponse> {
+    Ok(season_response(current_season(&deps.storage)?))
+}
+
+fn query_ranking<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    season: Option<u64>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<RankingResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let season = match season {
+        Some(season) => season,
+        None => current_season(&deps.storage)?.season,
+    };
+    let end = match start_after {
+        Some(token_id) => {
+            let rating = ratings::<S>().load(&deps.storage, &rating_key(season, &token_id))?;
+            Some(Bound::exclusive(ranking_key(
+                season,
+                rating.rating,
+                &token_id,
+            )))
+        }
+        None => None,
+    };
+
+    let ratings: StdResult<Vec<_>> = season_ranking(&deps.storage, season, end)
+        .take(limit)
+        .map(|item| {
+            let (_, token_id) = item?;
+            let rating = ratings::<S>().load(&deps.storage, &rating_key(season, &token_id))?;
+            Ok(rating_response(rating))
+        })
+        .collect();
+    Ok(RankingResponse {
+        season,
+        ratings: ratings?,
+    })
+}
+
+fn query_rating_history<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    token_id: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<RatingHistoryResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|season| Bound::exclusive(rating_key(season, &token_id)));
+
+    let ratings = ratings::<S>();
+    let res: StdResult<Vec<_>> = ratings
+        .idx
+        .token
+        .items(
+            &deps.storage,
+            token_id.as_bytes(),
+            start,
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(_, rating)| rating_response(rating)))
+        .collect();
+    Ok(RatingHistoryResponse { ratings: res? })
+}
+
+fn query_champions<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ChampionsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|season| Bound::exclusive(season.to_be_bytes().to_vec()));
+
+    let seasons: StdResult<Vec<_>> = SEASONS
+        .range(&deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, season)| season_response(season)))
+        .collect();
+    Ok(ChampionsResponse { seasons: seasons? })
+}
+
+fn season_response(season: Season) -> SeasonResponse {
+    SeasonResponse {
+        season: season.season,
+        start_height: season.start_height,
+        end_height: season.end_height,
+        champion: season.champion,
+        champion_rating: season.champion_rating,
+    }
+}
+
+fn rating_response(rating: Rating) -> RatingResponse {
+    RatingResponse {
+        token_id: rating.token_id,
+        season: rating.season,
+        rating: rating.rating,
+        wins: rating.wins,
+        losses: rating.losses,
+    }
+}
+
+/// query_battle_history lists the battles of a monster or an owner, on either side of them
+fn query_battle_history<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    token_id: Option<String>,
+    owner: Option<HumanAddr>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<BattlesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = || start_after.map(|id| Bound::exclusive(id.to_be_bytes().to_vec()));
+
+    let battles = battles::<S>();
+    let (attacker_index, defender_index, idx) = match (token_id, owner) {
+        (Some(_), Some(_)) => {
+            return Err(StdError::generic_err(
+                "Battle history is either by token_id or by owner",
+            ))
+        }
+        (Some(token_id), None) => (
+            &battles.idx.attacker,
+            &battles.idx.defender,
+            token_id.into_bytes(),
+        ),
+        (None, Some(owner)) => (
+            &battles.idx.attacker_owner,
+            &battles.idx.defender_owner,
+            deps.api.canonical_address(&owner)?.to_vec(),
+        ),
+        (None, None) => {
+            let res: StdResult<Vec<_>> = battles
+                .range(&deps.storage, start(), None, Order::Ascending)
+                .take(limit)
+                .map(|item| item.and_then(|(k, battle)| humanize_battle(deps.api, &k, battle)))
+                .collect();
+            return Ok(BattlesResponse { battles: res? });
+        }
+    };
+
+    // the first battles on either side are enough to find the first ones overall,
+    // both sides may match the same battle
+    let mut merged = BTreeMap::new();
+    for index in &[attacker_index, defender_index] {
+        for item in index
+            .items(&deps.storage, &idx, start(), None, Order::Ascending)
+            .take(limit)
+        {
+            let (k, battle) = item?;
+            merged.insert(k, battle);
+        }
+    }
+    let res: StdResult<Vec<_>> = merged
+        .into_iter()
+        .take(limit)
+        .map(|(k, battle)| humanize_battle(deps.api, &k, battle))
+        .collect();
+    Ok(BattlesResponse { battles: res? })
+}
+
+fn humanize_battle<A: Api>(api: A, key: &[u8], battle: Battle) -> StdResult<BattleResponse> {
+    let mut id = [0u8; 8];
+    id.copy_from_slice(key);
+    Ok(BattleResponse {
+        battle_id: u64::from_be_bytes(id),
+        attacker_id: battle.attacker_id,
+        defender_id: battle.defender_id,
+        attacker_owner: api.human_address(&battle.attacker_owner)?,
+        defender_owner: api.human_address(&battle.defender_owner)?,
+        attacker_level_before: battle.attacker_level_before,
+        attacker_level_after: battle.attacker_level_after,
+        defender_level_before: battle.defender_level_before,
+        defender_level_after: battle.defender_level_after,
+        winner: battle.winner,
+        height: battle.height,
+        forfeit: battle.forfeit,
+    })
+}
+
 fn query_all_nft_info<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
     env: Env,
@@ -593,8 +2052,10 @@ fn query_all_nft_info<S: Storage, A: Api, Q: Querier>(
             approvals: humanize_approvals(deps.api, &env.block, &info, include_expired)?,
         },
         info: NftInfoResponse {
+            stats: stats_at_level(&info.stats, info.level),
             name: info.name,
             level: info.level,
+            element: info.element,
             description: info.description,
             image: info.image,
         },
@@ -622,13 +2083,16 @@ fn humanize_approval<A: Api>(api: A, approval: &Approval) -> StdResult<cw721::Ap
 
 #[cfg(test)]
 mod tests {
-    use cosmwasm_std::from_binary;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::CosmosMsg;
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    };
     use cosmwasm_std::WasmMsg;
+    use cosmwasm_std::{from_binary, from_slice, Empty, QuerierResult, SystemError, SystemResult};
+    use cosmwasm_std::{CanonicalAddr, CosmosMsg};
 
     use super::*;
     use cw721::ApprovedForAllResponse;
+    use cw_storage_plus::{index_int, Index};
 
     const MINTER: &str = "merlin";
     const CONTRACT_NAME: &str = "Magic Power";
@@ -694,6 +2158,8 @@ mod tests {
             owner: "medusa".into(),
             name: name.clone(),
             level: 1,
+            element: None,
+            stats: None,
             description: Some(description.clone()),
             image: None,
         });
@@ -724,6 +2190,8 @@ mod tests {
             NftInfoResponse {
                 name: name.clone(),
                 level: 1,
+                element: Element::Normal,
+                stats: Stats::default(),
                 description: description.clone(),
                 image: None,
             }
@@ -745,6 +2213,8 @@ mod tests {
             owner: "hercules".into(),
             name: "copy cat".into(),
             level: 1,
+            element: None,
+            stats: None,
             description: None,
             image: None,
         });
@@ -760,6 +2230,36 @@ mod tests {
         let tokens = query_all_tokens(&deps, None, None).unwrap();
         assert_eq!(1, tokens.tokens.len());
         assert_eq!(vec![token_id], tokens.tokens);
+
+        // element and stats are set at mint, the stats grow with the level
+        let mint_msg = HandleMsg::Mint(MintMsg {
+            token_id: "blaze".into(),
+            owner: "hercules".into(),
+            name: "Blaze".into(),
+            level: 2,
+            element: Some(Element::Fire),
+            stats: Some(Stats {
+                hp: 15,
+                attack: 9,
+                defense: 3,
+                speed: 8,
+            }),
+            description: None,
+            image: None,
+        });
+        let allowed = mock_info(MINTER, &[]);
+        handle(&mut deps, mock_env(), allowed, mint_msg).unwrap();
+        let info = query_all_nft_info(&deps, mock_env(), "blaze".into(), false).unwrap();
+        assert_eq!(Element::Fire, info.info.element);
+        assert_eq!(
+            Stats {
+                hp: 18,
+                attack: 11,
+                defense: 5,
+                speed: 9,
+            },
+            info.info.stats
+        );
     }
 
     #[test]
@@ -777,6 +2277,8 @@ mod tests {
             owner: "venus".into(),
             name: name.clone(),
             level: 1,
+            element: None,
+            stats: None,
             description: Some(description.clone()),
             image: None,
         });
@@ -836,6 +2338,8 @@ mod tests {
             token_id: token_id.clone(),
             owner: "venus".into(),
             level: 1,
+            element: None,
+            stats: None,
             name: name.clone(),
             description: Some(description.clone()),
             image: None,
@@ -892,6 +2396,1432 @@ mod tests {
         );
     }
 
+    #[test]
+    fn battling_monsters() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let minter = mock_info(MINTER, &[]);
+        let monsters = &[
+            ("attacker", "venus"),
+            ("sparring", "venus"),
+            ("defender", "mars"),
+        ];
+        for (token_id, owner) in monsters {
+            let mint_msg = HandleMsg::Mint(MintMsg {
+                token_id: token_id.to_string(),
+                owner: HumanAddr::from(*owner),
+                name: token_id.to_string(),
+                level: 1,
+                element: None,
+                stats: None,
+                description: None,
+                image: None,
+            });
+            handle(&mut deps, mock_env(), minter.clone(), mint_msg).unwrap();
+        }
+
+        let battle_msg = HandleMsg::BattleMonster {
+            attacker_id: "attacker".into(),
+            defender_id: "sparring".into(),
+        };
+
+        // random cannot battle with somebody else's monster
+        let random = mock_info("random", &[]);
+        let err = handle(&mut deps, mock_env(), random, battle_msg.clone()).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        // and monsters of different owners have to be challenged
+        let foreign_battle = HandleMsg::BattleMonster {
+            attacker_id: "attacker".into(),
+            defender_id: "defender".into(),
+        };
+        for owner in &["venus", "mars"] {
+            let info = mock_info(*owner, &[]);
+            let err = handle(&mut deps, mock_env(), info, foreign_battle.clone()).unwrap_err();
+            match err {
+                ContractError::Unauthorized {} => {}
+                e => panic!("unexpected error: {}", e),
+            }
+        }
+
+        // a monster cannot battle itself
+        let venus = mock_info("venus", &[]);
+        let self_battle = HandleMsg::BattleMonster {
+            attacker_id: "attacker".into(),
+            defender_id: "attacker".into(),
+        };
+        let err = handle(&mut deps, mock_env(), venus.clone(), self_battle).unwrap_err();
+        match err {
+            ContractError::SelfBattle {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // the owner can, only the winner levels up
+        let res = handle(&mut deps, mock_env(), venus.clone(), battle_msg.clone()).unwrap();
+        assert_eq!("winner", res.attributes[3].key);
+        let levels = |deps: &Extern<_, _, _>| {
+            let attacker = query_nft_info(deps, "attacker".into()).unwrap();
+            let sparring = query_nft_info(deps, "sparring".into()).unwrap();
+            attacker.level + sparring.level
+        };
+        assert_eq!(3, levels(&deps));
+
+        // and so can an approved spender of both
+        for token_id in &["attacker", "sparring"] {
+            let approve_msg = HandleMsg::Approve {
+                spender: "trainer".into(),
+                token_id: token_id.to_string(),
+                expires: None,
+            };
+            handle(&mut deps, mock_env(), venus.clone(), approve_msg).unwrap();
+        }
+        let trainer = mock_info("trainer", &[]);
+        // once the monsters rested
+        let mut env = mock_env();
+        env.block.height += BATTLE_COOLDOWN_BLOCKS;
+        handle(&mut deps, env.clone(), trainer, battle_msg.clone()).unwrap();
+
+        // or an operator
+        let approve_all_msg = HandleMsg::ApproveAll {
+            operator: "operator".into(),
+            expires: None,
+        };
+        handle(&mut deps, mock_env(), venus, approve_all_msg).unwrap();
+        let operator = mock_info("operator", &[]);
+        env.block.height += BATTLE_COOLDOWN_BLOCKS;
+        handle(&mut deps, env, operator, battle_msg).unwrap();
+        // beating a higher level monster is worth another level
+        let total = levels(&deps);
+        assert!((5..=7).contains(&total), "{}", total);
+    }
+
+    #[test]
+    fn combat() {
+        let monster = |level: u64, element: Element| TokenInfo {
+            owner: CanonicalAddr::default(),
+            approvals: vec![],
+            name: "monster".into(),
+            species: None,
+            level,
+            element,
+            stats: Stats::default(),
+            description: "".into(),
+            image: None,
+        };
+        let wins = |attacker: &TokenInfo, defender: &TokenInfo| {
+            (0..1000u32)
+                .filter(|i| fight(&i.to_be_bytes(), attacker, defender))
+                .count()
+        };
+
+        // stats grow with every level
+        let stats = stats_at_level(&Stats::default(), 3);
+        assert_eq!(
+            Stats {
+                hp: 26,
+                attack: 10,
+                defense: 8,
+                speed: 7,
+            },
+            stats
+        );
+
+        // even monsters have a chance either way
+        let even = wins(&monster(5, Element::Normal), &monster(5, Element::Normal));
+        assert!(even > 100 && even < 900, "{}", even);
+        // higher levels hit harder
+        let stronger = wins(&monster(10, Element::Normal), &monster(2, Element::Normal));
+        assert!(stronger > 900, "{}", stronger);
+        // and so do effective types
+        assert_eq!(200, effectiveness(Element::Water, Element::Fire));
+        assert_eq!(50, effectiveness(Element::Fire, Element::Water));
+        assert_eq!(100, effectiveness(Element::Normal, Element::Fire));
+        let effective = wins(&monster(5, Element::Water), &monster(5, Element::Fire));
+        assert!(effective > 900, "{}", effective);
+        let ineffective = wins(&monster(5, Element::Fire), &monster(5, Element::Water));
+        assert!(ineffective < 100, "{}", ineffective);
+    }
+
+    fn commit(secret: &str) -> Binary {
+        Binary::from(Sha256::digest(secret.as_bytes()).to_vec())
+    }
+
+    /// RentalQuerier answers UserOf like a marketplace renting out all of its monsters
+    /// to the same user, and passes everything else on to the MockQuerier
+    struct RentalQuerier {
+        base: MockQuerier,
+        market: HumanAddr,
+        user: HumanAddr,
+    }
+
+    impl Querier for RentalQuerier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = from_slice(bin_request).unwrap();
+            match &request {
+                QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg })
+                    if *contract_addr == self.market =>
+                {
+                    let RentalQueryMsg::UserOf { .. } = from_binary(msg).unwrap();
+                    let res = UserOfResponse {
+                        user: Some(self.user.clone()),
+                        expires: Some(Expiration::AtHeight(mock_env().block.height + 1)),
+                    };
+                    SystemResult::Ok(to_binary(&res).into())
+                }
+                QueryRequest::Wasm(_) => SystemResult::Err(SystemError::UnsupportedRequest {
+                    kind: String::from("wasm"),
+                }),
+                _ => self.base.handle_query(&request),
+            }
+        }
+    }
+
+    #[test]
+    fn renting_monsters() {
+        let mut deps = Extern {
+            storage: MockStorage::default(),
+            api: MockApi::default(),
+            querier: RentalQuerier {
+                base: MockQuerier::new(&[]),
+                market: HumanAddr::from("market"),
+                user: HumanAddr::from("renter"),
+            },
+        };
+        setup_contract(&mut deps);
+
+        let minter = mock_info(MINTER, &[]);
+        let monsters = &[
+            ("rented", "market"),
+            ("owned", "renter"),
+            ("foreign", "venus"),
+        ];
+        for (token_id, owner) in monsters {
+            let mint_msg = HandleMsg::Mint(MintMsg {
+                token_id: token_id.to_string(),
+                owner: HumanAddr::from(*owner),
+                name: token_id.to_string(),
+                level: 1,
+                element: None,
+                stats: None,
+                description: None,
+                image: None,
+            });
+            handle(&mut deps, mock_env(), minter.clone(), mint_msg).unwrap();
+        }
+
+        // the renter can't battle the rented monster against their own one directly
+        let battle_msg = HandleMsg::BattleMonster {
+            attacker_id: "rented".into(),
+            defender_id: "owned".into(),
+        };
+        let renter = mock_info("renter", &[]);
+        let err = handle(&mut deps, mock_env(), renter.clone(), battle_msg.clone()).unwrap_err();
+        match err {
+            ContractError::ChallengeRequired { token_id } => assert_eq!("rented", token_id),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // but challenges with it
+        let challenge_msg = HandleMsg::Challenge {
+            attacker_id: "rented".into(),
+            defender_id: "foreign".into(),
+            expires: None,
+            commitment: commit("renter secret"),
+        };
+        let res = handle(&mut deps, mock_env(), renter.clone(), challenge_msg).unwrap();
+        assert_eq!(attr("action", "challenge"), res.attributes[0]);
+
+        // nobody else can, and the rental expires
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("random", &[]),
+            battle_msg.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let mut expired = mock_env();
+        expired.block.height += BATTLE_COOLDOWN_BLOCKS + 1;
+        let err = handle(&mut deps, expired, renter.clone(), battle_msg).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // renting does not give the right to transfer the monster
+        let transfer_msg = HandleMsg::TransferNft {
+            recipient: HumanAddr::from("renter"),
+            token_id: "rented".into(),
+        };
+        let err = handle(&mut deps, mock_env(), renter.clone(), transfer_msg).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // owners that do not rent out their monsters are not asked about a user
+        let battle_msg = HandleMsg::BattleMonster {
+            attacker_id: "owned".into(),
+            defender_id: "foreign".into(),
+        };
+        let err = handle(&mut deps, mock_env(), renter, battle_msg).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn resting_monsters() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let minter = mock_info(MINTER, &[]);
+        for token_id in &["attacker", "defender"] {
+            let mint_msg = HandleMsg::Mint(MintMsg {
+                token_id: token_id.to_string(),
+                owner: "venus".into(),
+                name: token_id.to_string(),
+                level: 1,
+                element: None,
+                stats: None,
+                description: None,
+                image: None,
+            });
+            handle(&mut deps, mock_env(), minter.clone(), mint_msg).unwrap();
+        }
+        let battle_msg = HandleMsg::BattleMonster {
+            attacker_id: "attacker".into(),
+            defender_id: "defender".into(),
+        };
+        let stamina = |deps: &Extern<_, _, _>, env: Env| -> StaminaResponse {
+            let query_msg = QueryMsg::Stamina {
+                token_id: "defender".into(),
+            };
+            from_binary(&query(deps, env, query_msg).unwrap()).unwrap()
+        };
+
+        // fresh monsters are rested
+        let mut env = mock_env();
+        let res = stamina(&deps, env.clone());
+        assert_eq!(
+            StaminaResponse {
+                stamina: MAX_STAMINA,
+                max_stamina: MAX_STAMINA,
+                cooldown: 0,
+                last_battle_height: None,
+            },
+            res
+        );
+
+        // a battle costs stamina and the monsters can't battle again right away
+        let venus = mock_info("venus", &[]);
+        handle(&mut deps, env.clone(), venus.clone(), battle_msg.clone()).unwrap();
+        let err = handle(&mut deps, env.clone(), venus.clone(), battle_msg.clone()).unwrap_err();
+        match err {
+            ContractError::Exhausted { token_id } => assert_eq!("attacker", token_id),
+            e => panic!("unexpected error: {}", e),
+        }
+        let res = stamina(&deps, env.clone());
+        assert_eq!(MAX_STAMINA - 1, res.stamina);
+        assert_eq!(BATTLE_COOLDOWN_BLOCKS, res.cooldown);
+        assert_eq!(Some(env.block.height), res.last_battle_height);
+
+        // after the cooldown they can, until they run out of stamina
+        for _ in 1..MAX_STAMINA {
+            env.block.height += BATTLE_COOLDOWN_BLOCKS;
+            handle(&mut deps, env.clone(), venus.clone(), battle_msg.clone()).unwrap();
+        }
+        env.block.height += BATTLE_COOLDOWN_BLOCKS;
+        let res = stamina(&deps, env.clone());
+        assert_eq!(0, res.stamina);
+        assert_eq!(0, res.cooldown);
+        let err = handle(&mut deps, env.clone(), venus.clone(), battle_msg.clone()).unwrap_err();
+        match err {
+            ContractError::Exhausted { .. } => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        // exhausted monsters can't be challenged into battle either
+        let challenge_msg = HandleMsg::Challenge {
+            attacker_id: "attacker".into(),
+            defender_id: "defender".into(),
+            expires: None,
+            commitment: commit("secret"),
+        };
+        let err = handle(&mut deps, env.clone(), venus.clone(), challenge_msg).unwrap_err();
+        match err {
+            ContractError::Exhausted { .. } => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // stamina regenerates over time
+        env.block.height += STAMINA_REGEN_BLOCKS;
+        let res = stamina(&deps, env.clone());
+        assert_eq!(1, res.stamina);
+        handle(&mut deps, env, venus, battle_msg).unwrap();
+    }
+
+    #[test]
+    fn resting_between_challenges() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let minter = mock_info(MINTER, &[]);
+        for (token_id, owner) in &[("attacker", "venus"), ("defender", "mars")] {
+            let mint_msg = HandleMsg::Mint(MintMsg {
+                token_id: token_id.to_string(),
+                owner: HumanAddr::from(*owner),
+                name: token_id.to_string(),
+                level: 1,
+                element: None,
+                stats: None,
+                description: None,
+                image: None,
+            });
+            handle(&mut deps, mock_env(), minter.clone(), mint_msg).unwrap();
+        }
+        let venus = mock_info("venus", &[]);
+        let mars = mock_info("mars", &[]);
+        for _ in 0..3 {
+            let challenge_msg = HandleMsg::Challenge {
+                attacker_id: "attacker".into(),
+                defender_id: "defender".into(),
+                expires: None,
+                commitment: commit("venus secret"),
+            };
+            handle(&mut deps, mock_env(), venus.clone(), challenge_msg).unwrap();
+        }
+        let accept = |challenge_id: u64| HandleMsg::AcceptChallenge {
+            challenge_id,
+            commitment: commit("mars secret"),
+        };
+
+        // accepting a challenge spends the stamina, so the next one has to wait for the cooldown
+        let mut env = mock_env();
+        handle(&mut deps, env.clone(), mars.clone(), accept(1)).unwrap();
+        let err = handle(&mut deps, env.clone(), mars.clone(), accept(2)).unwrap_err();
+        match err {
+            ContractError::Exhausted { token_id } => assert_eq!("attacker", token_id),
+            e => panic!("unexpected error: {}", e),
+        }
+        env.block.height += BATTLE_COOLDOWN_BLOCKS;
+        handle(&mut deps, env.clone(), mars.clone(), accept(2)).unwrap();
+
+        // revealing both in one block fights both battles without spending stamina again
+        env.block.height += 1;
+        for challenge_id in 1..=2 {
+            for secret in &["venus secret", "mars secret"] {
+                let reveal_msg = HandleMsg::RevealSecret {
+                    challenge_id,
+                    secret: secret.to_string(),
+                };
+                handle(&mut deps, env.clone(), mars.clone(), reveal_msg).unwrap();
+            }
+        }
+        let res = query_battle_history(&deps, None, None, None, None).unwrap();
+        assert_eq!(2, res.battles.len());
+        for token_id in &["attacker", "defender"] {
+            let query_msg = QueryMsg::Stamina {
+                token_id: token_id.to_string(),
+            };
+            let res: StaminaResponse =
+                from_binary(&query(&deps, env.clone(), query_msg).unwrap()).unwrap();
+            assert_eq!(MAX_STAMINA - 2, res.stamina);
+        }
+
+        // the third one still has to wait
+        let err = handle(&mut deps, env, mars, accept(3)).unwrap_err();
+        match err {
+            ContractError::Exhausted { .. } => {}
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn challenging_monsters() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let minter = mock_info(MINTER, &[]);
+        for (token_id, owner) in &[("attacker", "venus"), ("defender", "mars")] {
+            let mint_msg = HandleMsg::Mint(MintMsg {
+                token_id: token_id.to_string(),
+                owner: HumanAddr::from(*owner),
+                name: token_id.to_string(),
+                level: 1,
+                element: None,
+                stats: None,
+                description: None,
+                image: None,
+            });
+            handle(&mut deps, mock_env(), minter.clone(), mint_msg).unwrap();
+        }
+
+        let challenge_msg = |expires: Option<Expiration>| HandleMsg::Challenge {
+            attacker_id: "attacker".into(),
+            defender_id: "defender".into(),
+            expires,
+            commitment: commit("venus secret"),
+        };
+
+        // only the attacker's side can challenge
+        let mars = mock_info("mars", &[]);
+        let err = handle(&mut deps, mock_env(), mars.clone(), challenge_msg(None)).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let venus = mock_info("venus", &[]);
+        handle(&mut deps, mock_env(), venus.clone(), challenge_msg(None)).unwrap();
+        let expires = Expiration::AtHeight(mock_env().block.height + 10);
+        handle(
+            &mut deps,
+            mock_env(),
+            venus.clone(),
+            challenge_msg(Some(expires)),
+        )
+        .unwrap();
+
+        // both challenges are pending for the challenger and the defender
+        let expected = ChallengeResponse {
+            challenge_id: 1,
+            attacker_id: "attacker".into(),
+            defender_id: "defender".into(),
+            challenger: "venus".into(),
+            defender: "mars".into(),
+            expires: Expiration::Never {},
+            reveal_deadline: None,
+            challenger_revealed: false,
+            defender_revealed: false,
+        };
+        let query_msg = QueryMsg::ChallengesByDefender {
+            defender: "mars".into(),
+            start_after: None,
+            limit: None,
+        };
+        let res: ChallengesResponse =
+            from_binary(&query(&deps, mock_env(), query_msg.clone()).unwrap()).unwrap();
+        assert_eq!(2, res.challenges.len());
+        assert_eq!(expected, res.challenges[0]);
+        let by_challenger = QueryMsg::ChallengesByChallenger {
+            challenger: "venus".into(),
+            start_after: Some(1),
+            limit: None,
+        };
+        let res: ChallengesResponse =
+            from_binary(&query(&deps, mock_env(), by_challenger).unwrap()).unwrap();
+        assert_eq!(1, res.challenges.len());
+        assert_eq!(2, res.challenges[0].challenge_id);
+
+        // nothing happens until the defender's owner accepts
+        let info = query_nft_info(&deps, "defender".into()).unwrap();
+        assert_eq!(1, info.level);
+        let accept_msg = HandleMsg::AcceptChallenge {
+            challenge_id: 1,
+            commitment: commit("mars secret"),
+        };
+        let err = handle(&mut deps, mock_env(), venus.clone(), accept_msg.clone()).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        // the defender can't copy the challenger's commitment
+        let copied_msg = HandleMsg::AcceptChallenge {
+            challenge_id: 1,
+            commitment: commit("venus secret"),
+        };
+        let err = handle(&mut deps, mock_env(), mars.clone(), copied_msg).unwrap_err();
+        match err {
+            ContractError::InvalidCommitment {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        handle(&mut deps, mock_env(), mars.clone(), accept_msg.clone()).unwrap();
+        let err = handle(&mut deps, mock_env(), mars.clone(), accept_msg).unwrap_err();
+        match err {
+            ContractError::ChallengeAccepted {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // the battle is fought once both secrets are revealed
+        let reveal = |secret: &str| HandleMsg::RevealSecret {
+            challenge_id: 1,
+            secret: secret.into(),
+        };
+        let anyone = mock_info("anyone", &[]);
+        let err = handle(&mut deps, mock_env(), anyone.clone(), reveal("guess")).unwrap_err();
+        match err {
+            ContractError::InvalidSecret {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        handle(&mut deps, mock_env(), anyone.clone(), reveal("mars secret")).unwrap();
+        let info = query_nft_info(&deps, "defender".into()).unwrap();
+        assert_eq!(1, info.level);
+        let res = handle(
+            &mut deps,
+            mock_env(),
+            anyone.clone(),
+            reveal("venus secret"),
+        )
+        .unwrap();
+        assert_eq!("winner", res.attributes[2].key);
+        let attacker = query_nft_info(&deps, "attacker".into()).unwrap();
+        let defender = query_nft_info(&deps, "defender".into()).unwrap();
+        assert_eq!(3, attacker.level + defender.level);
+
+        // a challenge is only fought once
+        handle(
+            &mut deps,
+            mock_env(),
+            anyone.clone(),
+            reveal("venus secret"),
+        )
+        .unwrap_err();
+
+        // expired challenges are ignored
+        let mut late_env = mock_env();
+        late_env.block.height += 10;
+        let res: ChallengesResponse =
+            from_binary(&query(&deps, late_env.clone(), query_msg.clone()).unwrap()).unwrap();
+        assert_eq!(0, res.challenges.len());
+        let accept_msg = HandleMsg::AcceptChallenge {
+            challenge_id: 2,
+            commitment: commit("mars secret"),
+        };
+        let err = handle(&mut deps, late_env, mars.clone(), accept_msg).unwrap_err();
+        match err {
+            ContractError::ChallengeExpired {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // and can be declined
+        let decline_msg = HandleMsg::DeclineChallenge { challenge_id: 2 };
+        handle(&mut deps, mock_env(), mars.clone(), decline_msg).unwrap();
+        let res: ChallengesResponse =
+            from_binary(&query(&deps, mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(0, res.challenges.len());
+
+        // the challenger can cancel a challenge too
+        let mut env = mock_env();
+        env.block.height += BATTLE_COOLDOWN_BLOCKS;
+        handle(&mut deps, env.clone(), venus.clone(), challenge_msg(None)).unwrap();
+        let decline_msg = HandleMsg::DeclineChallenge { challenge_id: 3 };
+        let err = handle(&mut deps, env.clone(), anyone.clone(), decline_msg.clone()).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        handle(&mut deps, env.clone(), venus.clone(), decline_msg).unwrap();
+
+        // expired challenges can be cleared by anyone, otherwise they stay but are ignored
+        let expires = Expiration::AtHeight(env.block.height + 5);
+        for _ in 0..2 {
+            handle(
+                &mut deps,
+                env.clone(),
+                venus.clone(),
+                challenge_msg(Some(expires)),
+            )
+            .unwrap();
+        }
+        env.block.height += 5;
+        let decline_msg = HandleMsg::DeclineChallenge { challenge_id: 4 };
+        handle(&mut deps, env.clone(), anyone.clone(), decline_msg).unwrap();
+        handle(&mut deps, env.clone(), venus.clone(), challenge_msg(None)).unwrap();
+        let accept_msg = HandleMsg::AcceptChallenge {
+            challenge_id: 5,
+            commitment: commit("mars secret"),
+        };
+        let err = handle(&mut deps, env.clone(), mars.clone(), accept_msg).unwrap_err();
+        match err {
+            ContractError::ChallengeExpired {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let pending = |id: u64| {
+            challenges::<MockStorage>()
+                .may_load(&deps.storage, &id.to_be_bytes())
+                .unwrap()
+                .is_some()
+        };
+        assert!(!pending(4));
+        assert!(pending(5));
+        assert!(pending(6));
+
+        // a challenge is void once the challenger no longer has the attacker
+        let accept_msg = HandleMsg::AcceptChallenge {
+            challenge_id: 6,
+            commitment: commit("mars secret"),
+        };
+        handle(&mut deps, env.clone(), mars.clone(), accept_msg).unwrap();
+        let transfer_msg = HandleMsg::TransferNft {
+            recipient: "jupiter".into(),
+            token_id: "attacker".into(),
+        };
+        handle(&mut deps, env.clone(), venus, transfer_msg).unwrap();
+        let before = query_battle_history(&deps, None, None, None, None).unwrap();
+        let mut res = HandleResponse::default();
+        for secret in &["venus secret", "mars secret"] {
+            let reveal_msg = HandleMsg::RevealSecret {
+                challenge_id: 6,
+                secret: secret.to_string(),
+            };
+            res = handle(&mut deps, env.clone(), anyone.clone(), reveal_msg).unwrap();
+        }
+        assert_eq!(attr("voided", true), res.attributes[2]);
+        let after = query_battle_history(&deps, None, None, None, None).unwrap();
+        assert_eq!(before, after);
+
+        // and so it is once whoever accepted no longer has the defender
+        env.block.height += BATTLE_COOLDOWN_BLOCKS;
+        let challenge_msg = HandleMsg::Challenge {
+            attacker_id: "attacker".into(),
+            defender_id: "defender".into(),
+            expires: None,
+            commitment: commit("jupiter secret"),
+        };
+        let jupiter = mock_info("jupiter", &[]);
+        handle(&mut deps, env.clone(), jupiter, challenge_msg).unwrap();
+        let accept_msg = HandleMsg::AcceptChallenge {
+            challenge_id: 7,
+            commitment: commit("mars secret"),
+        };
+        handle(&mut deps, env.clone(), mars.clone(), accept_msg).unwrap();
+        let transfer_msg = HandleMsg::TransferNft {
+            recipient: "saturn".into(),
+            token_id: "defender".into(),
+        };
+        handle(&mut deps, env.clone(), mars, transfer_msg).unwrap();
+        for secret in &["jupiter secret", "mars secret"] {
+            let reveal_msg = HandleMsg::RevealSecret {
+                challenge_id: 7,
+                secret: secret.to_string(),
+            };
+            res = handle(&mut deps, env.clone(), anyone.clone(), reveal_msg).unwrap();
+        }
+        assert_eq!(attr("voided", true), res.attributes[2]);
+        let after = query_battle_history(&deps, None, None, None, None).unwrap();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn forfeiting_challenges() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let minter = mock_info(MINTER, &[]);
+        for (token_id, owner) in &[("attacker", "venus"), ("defender", "mars")] {
+            let mint_msg = HandleMsg::Mint(MintMsg {
+                token_id: token_id.to_string(),
+                owner: HumanAddr::from(*owner),
+                name: token_id.to_string(),
+                level: 1,
+                element: None,
+                stats: None,
+                description: None,
+                image: None,
+            });
+            handle(&mut deps, mock_env(), minter.clone(), mint_msg).unwrap();
+        }
+        for challenge_id in 1..=2 {
+            // the second challenge waits for the monsters to rest from accepting the first
+            let mut env = mock_env();
+            env.block.height += (challenge_id - 1) * BATTLE_COOLDOWN_BLOCKS;
+            let challenge_msg = HandleMsg::Challenge {
+                attacker_id: "attacker".into(),
+                defender_id: "defender".into(),
+                expires: None,
+                commitment: commit("venus secret"),
+            };
+            handle(
+                &mut deps,
+                env.clone(),
+                mock_info("venus", &[]),
+                challenge_msg,
+            )
+            .unwrap();
+            let accept_msg = HandleMsg::AcceptChallenge {
+                challenge_id,
+                commitment: commit("mars secret"),
+            };
+            handle(&mut deps, env, mock_info("mars", &[]), accept_msg).unwrap();
+        }
+
+        // only the challenger reveals the first challenge
+        let reveal_msg = HandleMsg::RevealSecret {
+            challenge_id: 1,
+            secret: "venus secret".into(),
+        };
+        handle(&mut deps, mock_env(), mock_info("venus", &[]), reveal_msg).unwrap();
+
+        // the defender still has time to reveal
+        let forfeit_msg = HandleMsg::ClaimForfeit { challenge_id: 1 };
+        let anyone = mock_info("anyone", &[]);
+        let err = handle(&mut deps, mock_env(), anyone.clone(), forfeit_msg.clone()).unwrap_err();
+        match err {
+            ContractError::RevealPending {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // but not after the reveal period, the attacker wins by forfeit
+        let mut late_env = mock_env();
+        late_env.block.height += REVEAL_BLOCKS + 1;
+        let reveal_msg = HandleMsg::RevealSecret {
+            challenge_id: 1,
+            secret: "mars secret".into(),
+        };
+        let err = handle(&mut deps, late_env.clone(), anyone.clone(), reveal_msg).unwrap_err();
+        match err {
+            ContractError::ChallengeExpired {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let res = handle(&mut deps, late_env.clone(), anyone.clone(), forfeit_msg).unwrap();
+        assert_eq!(attr("winner", "attacker"), res.attributes[2]);
+        let info = query_nft_info(&deps, "attacker".into()).unwrap();
+        assert_eq!(2, info.level);
+        let info = query_nft_info(&deps, "defender".into()).unwrap();
+        assert_eq!(1, info.level);
+
+        // if neither side reveals, the challenge is just dropped
+        late_env.block.height += BATTLE_COOLDOWN_BLOCKS;
+        let forfeit_msg = HandleMsg::ClaimForfeit { challenge_id: 2 };
+        let res = handle(&mut deps, late_env, anyone, forfeit_msg).unwrap();
+        assert_eq!(2, res.attributes.len());
+        let info = query_nft_info(&deps, "attacker".into()).unwrap();
+        assert_eq!(2, info.level);
+        let query_msg = QueryMsg::ChallengesByChallenger {
+            challenger: "venus".into(),
+            start_after: None,
+            limit: None,
+        };
+        let res: ChallengesResponse =
+            from_binary(&query(&deps, mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(0, res.challenges.len());
+
+        // only the forfeit made it into the battle history
+        let res = query_battle_history(&deps, None, None, None, None).unwrap();
+        assert_eq!(1, res.battles.len());
+        assert!(res.battles[0].forfeit);
+        assert_eq!("attacker", res.battles[0].winner);
+    }
+
+    #[test]
+    fn battle_history() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let minter = mock_info(MINTER, &[]);
+        for (token_id, owner) in &[("a", "venus"), ("b", "venus"), ("c", "mars")] {
+            let mint_msg = HandleMsg::Mint(MintMsg {
+                token_id: token_id.to_string(),
+                owner: HumanAddr::from(*owner),
+                name: token_id.to_string(),
+                level: 1,
+                element: None,
+                stats: None,
+                description: None,
+                image: None,
+            });
+            handle(&mut deps, mock_env(), minter.clone(), mint_msg).unwrap();
+        }
+        // venus may send mars' monster into battle too
+        let approve_all_msg = HandleMsg::ApproveAll {
+            operator: "venus".into(),
+            expires: None,
+        };
+        handle(
+            &mut deps,
+            mock_env(),
+            mock_info("mars", &[]),
+            approve_all_msg,
+        )
+        .unwrap();
+
+        let venus = mock_info("venus", &[]);
+        let battle_msg = HandleMsg::BattleMonster {
+            attacker_id: "a".into(),
+            defender_id: "b".into(),
+        };
+        let res = handle(&mut deps, mock_env(), venus.clone(), battle_msg).unwrap();
+        assert_eq!(attr("battle_id", 1), res.attributes[4]);
+        let mut env = mock_env();
+        env.block.height += BATTLE_COOLDOWN_BLOCKS;
+        let battle_msg = HandleMsg::BattleMonster {
+            attacker_id: "c".into(),
+            defender_id: "b".into(),
+        };
+        handle(&mut deps, env.clone(), venus, battle_msg).unwrap();
+
+        // both battles are recorded with the levels around them
+        let res = query_battle_history(&deps, None, None, None, None).unwrap();
+        let ids: Vec<_> = res.battles.iter().map(|b| b.battle_id).collect();
+        assert_eq!(vec![1, 2], ids);
+        let second = &res.battles[1];
+        assert_eq!("c", second.attacker_id);
+        assert_eq!(HumanAddr::from("mars"), second.attacker_owner);
+        assert_eq!(HumanAddr::from("venus"), second.defender_owner);
+        assert_eq!(env.block.height, second.height);
+        assert!(!second.forfeit);
+        let winner = query_nft_info(&deps, second.winner.clone()).unwrap();
+        if second.winner == "c" {
+            assert_eq!(1, second.attacker_level_before);
+            assert_eq!(winner.level, second.attacker_level_after);
+            assert_eq!(second.defender_level_before, second.defender_level_after);
+        } else {
+            assert_eq!(
+                second.defender_level_before,
+                second.defender_level_after - 1
+            );
+            assert_eq!(winner.level, second.defender_level_after);
+            assert_eq!(1, second.attacker_level_after);
+        }
+
+        // monsters on either side find their battles
+        let res = query_battle_history(&deps, Some("a".into()), None, None, None).unwrap();
+        assert_eq!(1, res.battles.len());
+        let res = query_battle_history(&deps, Some("b".into()), None, None, None).unwrap();
+        assert_eq!(2, res.battles.len());
+
+        // owners too, even when they own both sides
+        let res = query_battle_history(&deps, None, Some("mars".into()), None, None).unwrap();
+        let ids: Vec<_> = res.battles.iter().map(|b| b.battle_id).collect();
+        assert_eq!(vec![2], ids);
+        let res = query_battle_history(&deps, None, Some("venus".into()), None, Some(1)).unwrap();
+        let ids: Vec<_> = res.battles.iter().map(|b| b.battle_id).collect();
+        assert_eq!(vec![1], ids);
+        let res = query_battle_history(&deps, None, Some("venus".into()), Some(1), None).unwrap();
+        let ids: Vec<_> = res.battles.iter().map(|b| b.battle_id).collect();
+        assert_eq!(vec![2], ids);
+
+        // but not both at once
+        query_battle_history(&deps, Some("a".into()), Some("venus".into()), None, None)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn leaderboard() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let minter = mock_info(MINTER, &[]);
+        for (token_id, level) in &[("a", 3), ("b", 1), ("c", 5), ("d", 3), ("e", 2)] {
+            let mint_msg = HandleMsg::Mint(MintMsg {
+                token_id: token_id.to_string(),
+                owner: "venus".into(),
+                name: token_id.to_string(),
+                level: *level,
+                element: None,
+                stats: None,
+                description: None,
+                image: None,
+            });
+            handle(&mut deps, mock_env(), minter.clone(), mint_msg).unwrap();
+        }
+        let ids = |res: TokenLevelsResponse| -> Vec<String> {
+            res.tokens.into_iter().map(|t| t.token_id).collect()
+        };
+
+        let res = query_top_by_level(&deps, None, Some(3)).unwrap();
+        assert_eq!(5, res.tokens[0].level);
+        assert_eq!(vec!["c", "d", "a"], ids(res));
+        let res = query_top_by_level(&deps, Some("a".into()), None).unwrap();
+        assert_eq!(vec!["e", "b"], ids(res));
+
+        let res = query_tokens_by_level_range(&deps, 2, 3, None, None).unwrap();
+        assert_eq!(vec!["e", "a", "d"], ids(res));
+        let res = query_tokens_by_level_range(&deps, 2, 3, Some("a".into()), None).unwrap();
+        assert_eq!(vec!["d"], ids(res));
+        // a cursor below the range starts at its beginning
+        let res = query_tokens_by_level_range(&deps, 3, 5, Some("b".into()), Some(1)).unwrap();
+        assert_eq!(vec!["a"], ids(res));
+        let res = query_tokens_by_level_range(&deps, 6, u64::MAX, None, None).unwrap();
+        assert_eq!(0, res.tokens.len());
+
+        // levels gained in battle move monsters up the board
+        let battle_msg = HandleMsg::BattleMonster {
+            attacker_id: "b".into(),
+            defender_id: "e".into(),
+        };
+        handle(&mut deps, mock_env(), mock_info("venus", &[]), battle_msg).unwrap();
+        let res = query_top_by_level(&deps, None, None).unwrap();
+        assert_eq!(5, res.tokens.len());
+        for token in res.tokens {
+            let info = query_nft_info(&deps, token.token_id).unwrap();
+            assert_eq!(info.level, token.level);
+        }
+
+        // the board reads the same entries the level index lists per level
+        let pks: Vec<_> = tokens::<MockStorage>()
+            .idx
+            .level
+            .pks(
+                &deps.storage,
+                &index_int(3u64),
+                None,
+                None,
+                Order::Ascending,
+            )
+            .collect();
+        assert!(pks.contains(&b"a".to_vec()));
+
+        // corrupt token ids are reported rather than mangled
+        let token = tokens().load(&deps.storage, "a").unwrap();
+        tokens::<MockStorage>()
+            .idx
+            .level
+            .save(&mut deps.storage, &[0xff], &token)
+            .unwrap();
+        let err = query_top_by_level(&deps, None, None).unwrap_err();
+        match err {
+            StdError::InvalidUtf8 { .. } => {}
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn ranked_seasons() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let minter = mock_info(MINTER, &[]);
+        for (token_id, owner) in &[("a", "venus"), ("b", "mars"), ("c", "venus")] {
+            let mint_msg = HandleMsg::Mint(MintMsg {
+                token_id: token_id.to_string(),
+                owner: HumanAddr::from(*owner),
+                name: token_id.to_string(),
+                level: 1,
+                element: None,
+                stats: None,
+                description: None,
+                image: None,
+            });
+            handle(&mut deps, mock_env(), minter.clone(), mint_msg).unwrap();
+        }
+        let season = query_season(&deps).unwrap();
+        assert_eq!(1, season.season);
+        assert_eq!(None, season.end_height);
+
+        // battles between monsters of the same owner aren't rated
+        let venus = mock_info("venus", &[]);
+        let battle_msg = HandleMsg::BattleMonster {
+            attacker_id: "a".into(),
+            defender_id: "c".into(),
+        };
+        handle(&mut deps, mock_env(), venus.clone(), battle_msg).unwrap();
+        let res = query_ranking(&deps, None, None, None).unwrap();
+        assert!(res.ratings.is_empty());
+        let challenge_msg = HandleMsg::Challenge {
+            attacker_id: "c".into(),
+            defender_id: "a".into(),
+            expires: None,
+            commitment: commit("first secret"),
+        };
+        let mut env = mock_env();
+        env.block.height += BATTLE_COOLDOWN_BLOCKS;
+        handle(&mut deps, env.clone(), venus.clone(), challenge_msg).unwrap();
+        let accept_msg = HandleMsg::AcceptChallenge {
+            challenge_id: 1,
+            commitment: commit("second secret"),
+        };
+        handle(&mut deps, env.clone(), venus.clone(), accept_msg).unwrap();
+        for secret in &["first secret", "second secret"] {
+            let reveal_msg = HandleMsg::RevealSecret {
+                challenge_id: 1,
+                secret: secret.to_string(),
+            };
+            handle(&mut deps, env.clone(), venus.clone(), reveal_msg).unwrap();
+        }
+        let res = query_ranking(&deps, None, None, None).unwrap();
+        assert!(res.ratings.is_empty());
+
+        // challenges between owners are, even monsters move by half the k factor
+        let mut challenge_id = 1;
+        let mut ranked_battle = |deps: &mut Extern<_, _, _>, env: Env| -> String {
+            challenge_id += 1;
+            let challenge_msg = HandleMsg::Challenge {
+                attacker_id: "a".into(),
+                defender_id: "b".into(),
+                expires: None,
+                commitment: commit("venus secret"),
+            };
+            handle(deps, env.clone(), mock_info("venus", &[]), challenge_msg).unwrap();
+            let accept_msg = HandleMsg::AcceptChallenge {
+                challenge_id,
+                commitment: commit("mars secret"),
+            };
+            handle(deps, env.clone(), mock_info("mars", &[]), accept_msg).unwrap();
+            let mut res = HandleResponse::default();
+            for secret in &["venus secret", "mars secret"] {
+                let reveal_msg = HandleMsg::RevealSecret {
+                    challenge_id,
+                    secret: secret.to_string(),
+                };
+                res = handle(deps, env.clone(), mock_info("mars", &[]), reveal_msg).unwrap();
+            }
+            res.attributes[2].value.clone()
+        };
+        let mut env = mock_env();
+        env.block.height += 2 * BATTLE_COOLDOWN_BLOCKS;
+        let winner = ranked_battle(&mut deps, env);
+        let res = query_ranking(&deps, None, None, None).unwrap();
+        assert_eq!(1, res.season);
+        assert_eq!(winner, res.ratings[0].token_id);
+        assert_eq!(INITIAL_RATING + 16, res.ratings[0].rating);
+        assert_eq!(1, res.ratings[0].wins);
+        assert_eq!(INITIAL_RATING - 16, res.ratings[1].rating);
+        assert_eq!(1, res.ratings[1].losses);
+        let res = query_ranking(&deps, None, Some(winner.clone()), None).unwrap();
+        assert_eq!(1, res.ratings.len());
+        assert_ne!(winner, res.ratings[0].token_id);
+
+        // only the minter rolls seasons over, into the future
+        let height = mock_env().block.height;
+        let rollover_msg = HandleMsg::RolloverSeason {
+            end_height: Some(height + 100),
+        };
+        let err = handle(&mut deps, mock_env(), venus.clone(), rollover_msg.clone()).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let past_msg = HandleMsg::RolloverSeason {
+            end_height: Some(height),
+        };
+        let err = handle(&mut deps, mock_env(), minter.clone(), past_msg).unwrap_err();
+        match err {
+            ContractError::InvalidSeasonEnd {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let res = handle(&mut deps, mock_env(), minter.clone(), rollover_msg.clone()).unwrap();
+        assert_eq!(attr("champion", &winner), res.attributes[2]);
+        let err = handle(&mut deps, mock_env(), minter.clone(), rollover_msg).unwrap_err();
+        match err {
+            ContractError::SeasonNotOver { end_height } => assert_eq!(height + 100, end_height),
+            e => panic!("unexpected error: {}", e),
+        }
+        let res = query_champions(&deps, None, None).unwrap();
+        assert_eq!(1, res.seasons.len());
+        assert_eq!(Some(winner.clone()), res.seasons[0].champion);
+        assert_eq!(Some(INITIAL_RATING + 16), res.seasons[0].champion_rating);
+        assert_eq!(Some(height), res.seasons[0].end_height);
+        let season = query_season(&deps).unwrap();
+        assert_eq!(2, season.season);
+        assert_eq!(Some(height + 100), season.end_height);
+
+        // ratings start halfway back to the initial one in the new season
+        let mut env = mock_env();
+        env.block.height += 3 * BATTLE_COOLDOWN_BLOCKS;
+        ranked_battle(&mut deps, env);
+        let res = query_ranking(&deps, None, None, None).unwrap();
+        assert_eq!(2, res.season);
+        let total: u64 = res.ratings.iter().map(|r| r.rating).sum();
+        assert_eq!(2 * INITIAL_RATING, total);
+        let history = query_rating_history(&deps, winner.clone(), None, None).unwrap();
+        let seasons: Vec<_> = history.ratings.iter().map(|r| r.season).collect();
+        assert_eq!(vec![1, 2], seasons);
+        let change = history.ratings[1].rating as i64 - (INITIAL_RATING + 8) as i64;
+        assert!(change.abs() < RATING_K as i64);
+        let history = query_rating_history(&deps, winner, Some(1), None).unwrap();
+        assert_eq!(1, history.ratings.len());
+
+        // battles after the season's end aren't rated
+        let mut env = mock_env();
+        env.block.height += 200;
+        ranked_battle(&mut deps, env);
+        let after = query_ranking(&deps, None, None, None).unwrap();
+        assert_eq!(res, after);
+
+        assert_eq!(500, expected_score(1200, 1200));
+        assert_eq!(535, expected_score(1225, 1200));
+        assert_eq!(360, expected_score(1200, 1300));
+        assert_eq!(990, expected_score(2400, 1200));
+    }
+
+    #[test]
+    fn breeding_monsters() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let minter = mock_info(MINTER, &[]);
+        let parents = &[
+            ("pikachu", "Pikachu", Element::Electric),
+            ("bulba", "Bulbasaur", Element::Grass),
+        ];
+        let mint_parents = |deps: &mut Extern<MockStorage, MockApi, MockQuerier>| {
+            for (token_id, name, element) in parents {
+                let mint_msg = HandleMsg::Mint(MintMsg {
+                    token_id: token_id.to_string(),
+                    owner: "venus".into(),
+                    name: name.to_string(),
+                    level: 4,
+                    element: Some(*element),
+                    stats: None,
+                    description: None,
+                    image: None,
+                });
+                handle(deps, mock_env(), minter.clone(), mint_msg).unwrap();
+            }
+        };
+        mint_parents(&mut deps);
+        let breed = |token_id: &str| HandleMsg::Breed {
+            parent_a: "pikachu".into(),
+            parent_b: "bulba".into(),
+            token_id: token_id.into(),
+        };
+
+        // only whoever may send both parents can breed them
+        let mars = mock_info("mars", &[]);
+        let err = handle(&mut deps, mock_env(), mars.clone(), breed("kid")).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let venus = mock_info("venus", &[]);
+        let self_msg = HandleMsg::Breed {
+            parent_a: "pikachu".into(),
+            parent_b: "pikachu".into(),
+            token_id: "kid".into(),
+        };
+        let err = handle(&mut deps, mock_env(), venus.clone(), self_msg).unwrap_err();
+        match err {
+            ContractError::SelfBreeding {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // nor can parents of different owners breed, even if the sender may send both
+        let mint_msg = HandleMsg::Mint(MintMsg {
+            token_id: "squirtle".into(),
+            owner: "mars".into(),
+            name: "Squirtle".into(),
+            level: 4,
+            element: Some(Element::Water),
+            stats: None,
+            description: None,
+            image: None,
+        });
+        handle(&mut deps, mock_env(), minter.clone(), mint_msg).unwrap();
+        let approve_all_msg = HandleMsg::ApproveAll {
+            operator: "venus".into(),
+            expires: None,
+        };
+        handle(&mut deps, mock_env(), mars.clone(), approve_all_msg).unwrap();
+        let mixed_msg = HandleMsg::Breed {
+            parent_a: "squirtle".into(),
+            parent_b: "pikachu".into(),
+            token_id: "kid".into(),
+        };
+        let err = handle(&mut deps, mock_env(), venus.clone(), mixed_msg).unwrap_err();
+        match err {
+            ContractError::DifferentOwners {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // the child takes after both parents
+        handle(&mut deps, mock_env(), venus.clone(), breed("kid")).unwrap();
+        let child = query_nft_info(&deps, "kid".into()).unwrap();
+        assert!(child.name == "Pikasaur" || child.name == "Bulbachu");
+        assert!(child.element == Element::Electric || child.element == Element::Grass);
+        let stats = Stats::default();
+        assert!((stats.hp - 2..=stats.hp + 2).contains(&child.stats.hp));
+        assert!((stats.speed - 2..=stats.speed + 2).contains(&child.stats.speed));
+        let owner = query_owner_of(&deps, mock_env(), "kid".into(), false).unwrap();
+        assert_eq!(HumanAddr::from("venus"), owner.owner);
+        assert_eq!(4, query_num_tokens(&deps).unwrap().count);
+
+        // picking another token id doesn't change the child
+        let mut other = mock_dependencies(&[]);
+        setup_contract(&mut other);
+        mint_parents(&mut other);
+        handle(&mut other, mock_env(), venus.clone(), breed("other kid")).unwrap();
+        let other_child = query_nft_info(&other, "other kid".into()).unwrap();
+        assert_eq!(child, other_child);
+
+        let lineage = query_lineage(&deps, mock_env(), "kid".into()).unwrap();
+        assert_eq!(vec!["pikachu", "bulba"], lineage.parents);
+        let lineage = query_lineage(&deps, mock_env(), "pikachu".into()).unwrap();
+        assert_eq!(0, lineage.parents.len());
+        assert_eq!(1, lineage.breed_count);
+        assert_eq!(BREED_COOLDOWN_BLOCKS, lineage.breed_cooldown);
+
+        // parents rest between breedings
+        let err = handle(&mut deps, mock_env(), venus.clone(), breed("kid2")).unwrap_err();
+        match err {
+            ContractError::BreedingCooldown { token_id } => assert_eq!("pikachu", token_id),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // operators breed for the owner, who gets the child
+        let approve_all_msg = HandleMsg::ApproveAll {
+            operator: "mars".into(),
+            expires: None,
+        };
+        handle(&mut deps, mock_env(), venus.clone(), approve_all_msg).unwrap();
+        let mut env = mock_env();
+        env.block.height += BREED_COOLDOWN_BLOCKS;
+        let err = handle(&mut deps, env.clone(), mars.clone(), breed("kid")).unwrap_err();
+        match err {
+            ContractError::Claimed {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        handle(&mut deps, env.clone(), mars.clone(), breed("kid2")).unwrap();
+        let owner = query_owner_of(&deps, mock_env(), "kid2".into(), false).unwrap();
+        assert_eq!(HumanAddr::from("venus"), owner.owner);
+
+        // and only a few times in their life
+        env.block.height += BREED_COOLDOWN_BLOCKS;
+        handle(&mut deps, env.clone(), venus.clone(), breed("kid3")).unwrap();
+        env.block.height += BREED_COOLDOWN_BLOCKS;
+        let err = handle(&mut deps, env, venus, breed("kid4")).unwrap_err();
+        match err {
+            ContractError::BreedLimit { token_id } => assert_eq!("pikachu", token_id),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn evolving_monsters() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let minter = mock_info(MINTER, &[]);
+        let mint_msg = HandleMsg::Mint(MintMsg {
+            token_id: "charm".into(),
+            owner: "venus".into(),
+            name: "Charmander".into(),
+            level: 20,
+            element: Some(Element::Fire),
+            stats: None,
+            description: None,
+            image: Some("charmander.png".into()),
+        });
+        handle(&mut deps, mock_env(), minter.clone(), mint_msg).unwrap();
+
+        let venus = mock_info("venus", &[]);
+        let evolve_msg = HandleMsg::Evolve {
+            token_id: "charm".into(),
+        };
+        let err = handle(&mut deps, mock_env(), venus.clone(), evolve_msg.clone()).unwrap_err();
+        match err {
+            ContractError::NoEvolution { species } => assert_eq!("Charmander", species),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // only the minter registers evolutions
+        let boosts = Stats {
+            hp: 10,
+            attack: 4,
+            defense: 3,
+            speed: 2,
+        };
+        let add_msg = |species: &str, level: u64, evolved_name: &str, image: Option<&str>| {
+            HandleMsg::AddEvolution {
+                species: species.into(),
+                level,
+                evolved_name: evolved_name.into(),
+                evolved_image: image.map(String::from),
+                boosts: boosts.clone(),
+            }
+        };
+        let first = add_msg("Charmander", 16, "Charmeleon", Some("charmeleon.png"));
+        let err = handle(&mut deps, mock_env(), venus.clone(), first.clone()).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        handle(&mut deps, mock_env(), minter.clone(), first).unwrap();
+        let second = add_msg("Charmeleon", 36, "Charizard", None);
+        handle(&mut deps, mock_env(), minter.clone(), second).unwrap();
+        let circular = add_msg("Charizard", 50, "Charmander", None);
+        let err = handle(&mut deps, mock_env(), minter, circular).unwrap_err();
+        match err {
+            ContractError::CircularEvolution { .. } => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let chain = query_evolution_chain(&deps, "Charmander".into()).unwrap();
+        let names: Vec<_> = chain
+            .evolutions
+            .iter()
+            .map(|e| e.evolved_name.as_str())
+            .collect();
+        assert_eq!(vec!["Charmeleon", "Charizard"], names);
+        let chain = query_evolution_chain(&deps, "Charmeleon".into()).unwrap();
+        assert_eq!(1, chain.evolutions.len());
+        assert_eq!(36, chain.evolutions[0].level);
+
+        // only the owner evolves a monster
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("mars", &[]),
+            evolve_msg.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let res = handle(&mut deps, mock_env(), venus.clone(), evolve_msg.clone()).unwrap();
+        assert_eq!(attr("evolved_name", "Charmeleon"), res.attributes[3]);
+        let info = query_nft_info(&deps, "charm".into()).unwrap();
+        assert_eq!("Charmeleon", info.name);
+        assert_eq!(Some("charmeleon.png".into()), info.image);
+        assert_eq!(20, info.level);
+        let stats = Stats {
+            hp: 30,
+            attack: 10,
+            defense: 7,
+            speed: 7,
+        };
+        assert_eq!(stats_at_level(&stats, 20), info.stats);
+
+        // the next evolution needs a higher level
+        let err = handle(&mut deps, mock_env(), venus.clone(), evolve_msg).unwrap_err();
+        match err {
+            ContractError::LevelTooLow { level } => assert_eq!(36, level),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // evolutions follow the species, not a name a monster happens to have
+        let mut token = tokens().load(&deps.storage, "charm").unwrap();
+        token.name = "Charmander".into();
+        token.species = Some("Magikarp".into());
+        tokens().save(&mut deps.storage, "charm", &token).unwrap();
+        let evolve_msg = HandleMsg::Evolve {
+            token_id: "charm".into(),
+        };
+        let err = handle(&mut deps, mock_env(), venus.clone(), evolve_msg.clone()).unwrap_err();
+        match err {
+            ContractError::NoEvolution { species } => assert_eq!("Magikarp", species),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // boosts saturate instead of overflowing
+        let add_msg = HandleMsg::AddEvolution {
+            species: "Magikarp".into(),
+            level: 20,
+            evolved_name: "Gyarados".into(),
+            evolved_image: None,
+            boosts: Stats {
+                hp: u64::MAX,
+                attack: u64::MAX,
+                defense: u64::MAX,
+                speed: u64::MAX,
+            },
+        };
+        handle(&mut deps, mock_env(), mock_info(MINTER, &[]), add_msg).unwrap();
+        let res = handle(&mut deps, mock_env(), venus, evolve_msg).unwrap();
+        assert_eq!(attr("species", "Magikarp"), res.attributes[2]);
+        let token = tokens().load(&deps.storage, "charm").unwrap();
+        assert_eq!(Some("Gyarados".into()), token.species);
+        assert_eq!(u64::MAX, token.stats.hp);
+        assert_eq!(u64::MAX, token.stats.speed);
+    }
+
     #[test]
     fn approving_revoking() {
         let mut deps = mock_dependencies(&[]);
@@ -907,6 +3837,8 @@ mod tests {
             owner: "demeter".into(),
             name: name.clone(),
             level: 1,
+            element: None,
+            stats: None,
             description: Some(description.clone()),
             image: None,
         });
@@ -1004,6 +3936,8 @@ mod tests {
             owner: "demeter".into(),
             name: name1.clone(),
             level: 1,
+            element: None,
+            stats: None,
             description: Some(description1.clone()),
             image: None,
         });
@@ -1016,6 +3950,8 @@ mod tests {
             owner: "demeter".into(),
             name: name2.clone(),
             level: 1,
+            element: None,
+            stats: None,
             description: Some(description2.clone()),
             image: None,
         });
@@ -1177,6 +4113,8 @@ mod tests {
             owner: demeter.clone(),
             name: "Growing power".to_string(),
             level: 1,
+            element: None,
+            stats: None,
             description: Some("Allows the owner the power to grow anything".to_string()),
             image: None,
         });
@@ -1187,6 +4125,8 @@ mod tests {
             owner: ceres.clone(),
             name: "More growing power".to_string(),
             level: 1,
+            element: None,
+            stats: None,
             description: Some(
                 "Allows the owner the power to grow anything even faster".to_string(),
             ),
@@ -1199,6 +4139,8 @@ mod tests {
             owner: demeter.clone(),
             name: "Sing a lullaby".to_string(),
             level: 1,
+            element: None,
+            stats: None,
             description: Some("Calm even the most excited children".to_string()),
             image: None,
         });
@@ -14,4 +14,61 @@ pub enum ContractError {
 
     #[error("Cannot set approval that is already expired")]
     Expired {},
+
+    #[error("A monster cannot battle itself")]
+    SelfBattle {},
+
+    #[error("Monster {token_id} can only battle through a challenge")]
+    ChallengeRequired { token_id: String },
+
+    #[error("Challenge expired")]
+    ChallengeExpired {},
+
+    #[error("Challenge already accepted")]
+    ChallengeAccepted {},
+
+    #[error("Challenge not accepted yet")]
+    ChallengeNotAccepted {},
+
+    #[error("Both sides have to commit to different secrets")]
+    InvalidCommitment {},
+
+    #[error("Secret does not match any open commitment")]
+    InvalidSecret {},
+
+    #[error("Secrets can still be revealed")]
+    RevealPending {},
+
+    #[error("Monster {token_id} needs to rest before battling again")]
+    Exhausted { token_id: String },
+
+    #[error("A monster cannot breed with itself")]
+    SelfBreeding {},
+
+    #[error("Monster {token_id} needs to rest before breeding again")]
+    BreedingCooldown { token_id: String },
+
+    #[error("Monster {token_id} cannot breed anymore")]
+    BreedLimit { token_id: String },
+
+    #[error("Only monsters of the same owner can breed")]
+    DifferentOwners {},
+
+    #[error("Evolving {species} into {evolved_name} would make the evolutions circular")]
+    CircularEvolution {
+        species: String,
+        evolved_name: String,
+    },
+
+    #[error("{species} does not evolve")]
+    NoEvolution { species: String },
+
+    #[error("Evolving needs level {level}")]
+    LevelTooLow { level: u64 },
+
+    #[error("The season runs until height {end_height}")]
+    SeasonNotOver { end_height: u64 },
+
+    #[error("A new season has to end after it starts")]
+    InvalidSeasonEnd {},
 }
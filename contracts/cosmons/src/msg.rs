@@ -1,3 +1,4 @@
+use crate::package::{Element, Stats};
 use cosmwasm_std::{Binary, HumanAddr};
 use cw721::Expiration;
 use schemars::JsonSchema;
@@ -34,10 +35,60 @@ pub enum HandleMsg {
         token_id: String,
         msg: Option<Binary>,
     },
+    /// Battles two monsters the sender may both send.
+    /// Rented monsters and monsters of other owners have to be challenged instead
     BattleMonster {
         attacker_id: String,
         defender_id: String,
     },
+    /// Challenges the defender to a battle, committing to the sha256 hash of a secret.
+    /// If expiration is set, the challenge can't be accepted after it
+    Challenge {
+        attacker_id: String,
+        defender_id: String,
+        expires: Option<Expiration>,
+        commitment: Binary,
+    },
+    /// Accepts a pending challenge, committing to the sha256 hash of a secret of the defender's side.
+    /// Can only be called by whoever may send the defender or has it rented.
+    /// Both monsters spend their stamina on the battle right away
+    AcceptChallenge {
+        challenge_id: u64,
+        commitment: Binary,
+    },
+    /// Removes a challenge that was not accepted yet without a battle. Either side can call it,
+    /// and anyone once the challenge expired
+    DeclineChallenge { challenge_id: u64 },
+    /// Reveals the secret of one side of an accepted challenge. The battle is fought
+    /// once both secrets are revealed
+    RevealSecret { challenge_id: u64, secret: String },
+    /// After the reveal period, the side that revealed wins the battle by forfeit.
+    /// If neither side revealed, the challenge is dropped
+    ClaimForfeit { challenge_id: u64 },
+    /// Breeds two monsters of the same owner the sender may both send into a new
+    /// level 1 monster, which is owned by the owner of the parents. Its name, stats
+    /// and element are inherited from both parents
+    Breed {
+        parent_a: String,
+        parent_b: String,
+        token_id: String,
+    },
+    /// Registers how monsters named species evolve once they reach the level,
+    /// replacing an earlier evolution of the species. Only the minter can call it
+    AddEvolution {
+        species: String,
+        level: u64,
+        evolved_name: String,
+        evolved_image: Option<String>,
+        boosts: Stats,
+    },
+    /// Evolves a monster of a species with an evolution, once it reached the level.
+    /// Only the owner can call it
+    Evolve { token_id: String },
+    /// Ends the current ranked season and starts the next one, in which ratings
+    /// are reset halfway back to the initial rating. Only the minter can call it,
+    /// and only after the current season's end_height if one was set
+    RolloverSeason { end_height: Option<u64> },
     /// Allows operator to transfer / send the token from the owner's account.
     /// If expiration is set, then this allowance has a time/height limit
     Approve {
@@ -73,6 +124,10 @@ pub struct MintMsg {
     pub name: String,
     // Monster level
     pub level: u64,
+    /// Elemental type, normal if unset
+    pub element: Option<Element>,
+    /// Stats at level 1, the default stats if unset
+    pub stats: Option<Stats>,
     /// Describes the asset to which this NFT represents (may be empty)
     pub description: Option<String>,
     /// A URI pointing to an image representing the asset
@@ -134,9 +189,91 @@ pub enum QueryMsg {
         start_after: Option<String>,
         limit: Option<u32>,
     },
+    /// Lists the tokens from the highest level down, tokens of the same level by descending token_id.
+    /// Return type: TokenLevelsResponse.
+    TopByLevel {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the tokens with a level between min and max (inclusive), lowest level first.
+    /// Return type: TokenLevelsResponse.
+    TokensByLevelRange {
+        min: u64,
+        max: u64,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 
     // Return the minter
     Minter {},
+
+    /// Returns the stamina of a monster and the blocks until it may battle again.
+    /// Return type: StaminaResponse.
+    Stamina {
+        token_id: String,
+    },
+
+    /// Lists the pending challenges issued by the given address.
+    /// Return type: ChallengesResponse.
+    ChallengesByChallenger {
+        challenger: HumanAddr,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Lists the pending challenges against monsters of the given address, or accepted by it.
+    /// Return type: ChallengesResponse.
+    ChallengesByDefender {
+        defender: HumanAddr,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+
+    /// Returns the parents of a bred monster and how often the monster bred itself.
+    /// Return type: LineageResponse.
+    Lineage {
+        token_id: String,
+    },
+
+    /// Returns the evolutions of a species, followed by those of what it evolves into.
+    /// Return type: EvolutionChainResponse.
+    EvolutionChain {
+        species: String,
+    },
+
+    /// Returns the current ranked season.
+    /// Return type: SeasonResponse.
+    Season {},
+    /// Lists the rated monsters of a season from the best rating down, the current season
+    /// unless given.
+    /// Return type: RankingResponse.
+    Ranking {
+        season: Option<u64>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the ratings of a monster in every season it battled in.
+    /// Return type: RatingHistoryResponse.
+    RatingHistory {
+        token_id: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Lists the past seasons with their champions.
+    /// Return type: ChampionsResponse.
+    Champions {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+
+    /// Lists the battles the given monster or the monsters of the given owner fought, oldest first.
+    /// At most one of token_id and owner may be set, without either all battles are listed.
+    /// Return type: BattlesResponse.
+    BattleHistory {
+        token_id: Option<String>,
+        owner: Option<HumanAddr>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
 }
 
 /// Shows who can mint these tokens
@@ -144,3 +281,141 @@ pub enum QueryMsg {
 pub struct MinterResponse {
     pub minter: HumanAddr,
 }
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ChallengeResponse {
+    pub challenge_id: u64,
+    pub attacker_id: String,
+    pub defender_id: String,
+    pub challenger: HumanAddr,
+    /// Owner of the defender when the challenge was issued, and whoever accepted it once accepted
+    pub defender: HumanAddr,
+    pub expires: Expiration,
+    /// Height until which the secrets can be revealed, unset until accepted
+    pub reveal_deadline: Option<u64>,
+    pub challenger_revealed: bool,
+    pub defender_revealed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ChallengesResponse {
+    pub challenges: Vec<ChallengeResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct StaminaResponse {
+    pub stamina: u64,
+    pub max_stamina: u64,
+    /// Blocks left until the monster may battle again, zero if it may battle now
+    pub cooldown: u64,
+    pub last_battle_height: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct BattleResponse {
+    pub battle_id: u64,
+    pub attacker_id: String,
+    pub defender_id: String,
+    pub attacker_owner: HumanAddr,
+    pub defender_owner: HumanAddr,
+    pub attacker_level_before: u64,
+    pub attacker_level_after: u64,
+    pub defender_level_before: u64,
+    pub defender_level_after: u64,
+    pub winner: String,
+    pub height: u64,
+    /// Whether the battle was decided by the other side not revealing its secret in time
+    pub forfeit: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct BattlesResponse {
+    pub battles: Vec<BattleResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct TokenLevel {
+    pub token_id: String,
+    pub level: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct TokenLevelsResponse {
+    pub tokens: Vec<TokenLevel>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct SeasonResponse {
+    pub season: u64,
+    pub start_height: u64,
+    pub end_height: Option<u64>,
+    /// Best rated monster of a past season
+    pub champion: Option<String>,
+    pub champion_rating: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ChampionsResponse {
+    pub seasons: Vec<SeasonResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct RatingResponse {
+    pub token_id: String,
+    pub season: u64,
+    pub rating: u64,
+    pub wins: u64,
+    pub losses: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct RankingResponse {
+    pub season: u64,
+    pub ratings: Vec<RatingResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct RatingHistoryResponse {
+    pub ratings: Vec<RatingResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct LineageResponse {
+    /// Parents of a bred monster, empty for minted ones
+    pub parents: Vec<String>,
+    pub breed_count: u64,
+    pub max_breeds: u64,
+    /// Blocks left until the monster may breed again, zero if it may breed now
+    pub breed_cooldown: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct EvolutionResponse {
+    pub species: String,
+    pub level: u64,
+    pub evolved_name: String,
+    pub evolved_image: Option<String>,
+    pub boosts: Stats,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct EvolutionChainResponse {
+    pub evolutions: Vec<EvolutionResponse>,
+}
+
+/// RentalQueryMsg asks the contract owning a monster who it is rented out to,
+/// like the marketplace answers it for its rental listings
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RentalQueryMsg {
+    UserOf {
+        contract_addr: HumanAddr,
+        token_id: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct UserOfResponse {
+    pub user: Option<HumanAddr>,
+    pub expires: Option<Expiration>,
+}
@@ -8,6 +8,10 @@ pub struct NftInfoResponse {
     pub name: String,
     /// Describes Monster level
     pub level: u64,
+    /// Elemental type of the monster
+    pub element: Element,
+    /// Combat stats at the current level
+    pub stats: Stats,
     /// Describes the asset to which this NFT represents
     pub description: String,
     /// "A URI pointing to a resource with mime type image/* representing the asset to which this
@@ -24,3 +28,39 @@ pub struct AllNftInfoResponse {
     /// Data on the token itself,
     pub info: NftInfoResponse,
 }
+
+/// Elemental type of a monster, which decides how effective its attacks are
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Element {
+    Normal,
+    Fire,
+    Water,
+    Grass,
+    Electric,
+}
+
+impl Default for Element {
+    fn default() -> Self {
+        Element::Normal
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Stats {
+    pub hp: u64,
+    pub attack: u64,
+    pub defense: u64,
+    pub speed: u64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            hp: 20,
+            attack: 6,
+            defense: 4,
+            speed: 5,
+        }
+    }
+}
@@ -1,9 +1,13 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{CanonicalAddr, StdResult, Storage};
+use cosmwasm_std::{Binary, CanonicalAddr, Order, StdError, StdResult, Storage};
+
+use crate::package::{Element, Stats};
 use cw721::{ContractInfoResponse, Expiration};
-use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
+use cw_storage_plus::{
+    index_int, Bound, Index, IndexList, IndexedMap, Item, Map, MultiIndex, PrimaryKey,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct TokenInfo {
@@ -14,15 +18,31 @@ pub struct TokenInfo {
 
     /// Identifies the asset to which this NFT represents
     pub name: String,
+    /// Species the evolutions are registered for, unset for monsters minted before evolutions
+    #[serde(default)]
+    pub species: Option<String>,
 
     //Add monster level
     pub level: u64,
+    /// Elemental type, normal for monsters minted before types existed
+    #[serde(default)]
+    pub element: Element,
+    /// Stats at level 1, they grow with every level
+    #[serde(default)]
+    pub stats: Stats,
     /// Describes the asset to which this NFT represents
     pub description: String,
     /// A URI pointing to an image representing the asset
     pub image: Option<String>,
 }
 
+impl TokenInfo {
+    /// Older monsters are of the species they are named after
+    pub fn species(&self) -> &str {
+        self.species.as_deref().unwrap_or(&self.name)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct Approval {
     /// Account that can transfer/send the token
@@ -48,13 +68,19 @@ pub fn increment_tokens<S: Storage>(storage: &mut S) -> StdResult<u64> {
     Ok(val)
 }
 
+/// token_id decodes the token id at the end of an index key
+fn token_id(key: &[u8]) -> StdResult<String> {
+    String::from_utf8(key.to_vec()).map_err(StdError::invalid_utf8)
+}
+
 pub struct TokenIndexes<'a, S: Storage> {
     pub owner: MultiIndex<'a, S, TokenInfo>,
+    pub level: MultiIndex<'a, S, TokenInfo>,
 }
 
 impl<'a, S: Storage> IndexList<S, TokenInfo> for TokenIndexes<'a, S> {
     fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<S, TokenInfo>> + '_> {
-        let v: Vec<&dyn Index<S, TokenInfo>> = vec![&self.owner];
+        let v: Vec<&dyn Index<S, TokenInfo>> = vec![&self.owner, &self.level];
         Box::new(v.into_iter())
     }
 }
@@ -62,6 +88,306 @@ impl<'a, S: Storage> IndexList<S, TokenInfo> for TokenIndexes<'a, S> {
 pub fn tokens<'a, S: Storage>() -> IndexedMap<'a, &'a str, TokenInfo, S, TokenIndexes<'a, S>> {
     let indexes = TokenIndexes {
         owner: MultiIndex::new(|d| d.owner.to_vec(), b"tokens", b"tokens__owner"),
+        level: MultiIndex::new(|d| index_int(d.level), b"tokens", b"tokens__level"),
     };
     IndexedMap::new(b"tokens", indexes)
 }
+
+/// The level index of `tokens()` read as a plain map, as MultiIndex can only list the tokens of
+/// a single level while top lists and level ranges span many
+const TOKENS_BY_LEVEL: Map<&[u8], u32> = Map::new(b"tokens__level");
+
+/// level_key is the key of a token in the level index, joined the way MultiIndex stores it
+pub fn level_key(level: u64, token_id: &str) -> Vec<u8> {
+    (index_int(level).as_slice(), token_id.as_bytes()).joined_key()
+}
+
+/// tokens_by_level lists (level, token_id) pairs between the given level keys
+pub fn tokens_by_level<'a, S: Storage>(
+    storage: &'a S,
+    min: Option<Bound>,
+    max: Option<Bound>,
+    order: Order,
+) -> Box<dyn Iterator<Item = StdResult<(u64, String)>> + 'a> {
+    let mapped = TOKENS_BY_LEVEL.range(storage, min, max, order).map(|item| {
+        let (key, _) = item?;
+        let (idx, pk) = <(&[u8], &[u8])>::parse_key(&key);
+        let mut level = [0u8; 8];
+        level.copy_from_slice(idx);
+        Ok((u64::from_be_bytes(level), token_id(pk)?))
+    });
+    Box::new(mapped)
+}
+
+/// A pending battle. Once the defender's side accepts it, both sides reveal the secrets they
+/// committed to and the battle is fought with the combined seed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Challenge {
+    pub attacker_id: String,
+    pub defender_id: String,
+    /// Address that issued the challenge
+    pub challenger: CanonicalAddr,
+    /// Owner of the defender when the challenge was issued, and whoever accepted it once accepted
+    pub defender: CanonicalAddr,
+    pub expires: Expiration,
+    /// sha256 hashes of the secrets of both sides, the defender commits when accepting
+    pub challenger_commitment: Binary,
+    pub defender_commitment: Option<Binary>,
+    pub challenger_secret: Option<String>,
+    pub defender_secret: Option<String>,
+    /// Height until which the secrets can be revealed, set once accepted
+    pub reveal_deadline: Option<u64>,
+}
+
+impl Challenge {
+    /// Only battles between different owners are rated, nobody should farm a rating off
+    /// their own monsters
+    pub fn is_ranked(&self) -> bool {
+        self.challenger != self.defender
+    }
+}
+
+pub const CHALLENGE_COUNT: Item<u64> = Item::new(b"num_challenges");
+
+pub fn increment_challenges<S: Storage>(storage: &mut S) -> StdResult<u64> {
+    let val = CHALLENGE_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+    CHALLENGE_COUNT.save(storage, &val)?;
+    Ok(val)
+}
+
+pub struct ChallengeIndexes<'a, S: Storage> {
+    pub challenger: MultiIndex<'a, S, Challenge>,
+    pub defender: MultiIndex<'a, S, Challenge>,
+}
+
+impl<'a, S: Storage> IndexList<S, Challenge> for ChallengeIndexes<'a, S> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<S, Challenge>> + '_> {
+        let v: Vec<&dyn Index<S, Challenge>> = vec![&self.challenger, &self.defender];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Challenges are keyed by their big-endian id, so they are listed in the order they were issued
+pub fn challenges<'a, S: Storage>(
+) -> IndexedMap<'a, &'a [u8], Challenge, S, ChallengeIndexes<'a, S>> {
+    let indexes = ChallengeIndexes {
+        challenger: MultiIndex::new(
+            |c| c.challenger.to_vec(),
+            b"challenges",
+            b"challenges__challenger",
+        ),
+        defender: MultiIndex::new(
+            |c| c.defender.to_vec(),
+            b"challenges",
+            b"challenges__defender",
+        ),
+    };
+    IndexedMap::new(b"challenges", indexes)
+}
+
+/// Stamina of a monster as of its last battle, it regenerates over the blocks since
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Stamina {
+    pub last_battle_height: u64,
+    pub stamina: u64,
+}
+
+pub const STAMINA: Map<&str, Stamina> = Map::new(b"stamina");
+
+/// A fought battle, kept so the history of monsters and their owners can be looked up
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Battle {
+    pub attacker_id: String,
+    pub defender_id: String,
+    /// Owners of both monsters at the time of the battle
+    pub attacker_owner: CanonicalAddr,
+    pub defender_owner: CanonicalAddr,
+    pub attacker_level_before: u64,
+    pub attacker_level_after: u64,
+    pub defender_level_before: u64,
+    pub defender_level_after: u64,
+    pub winner: String,
+    pub height: u64,
+    /// Whether the battle was decided by the other side not revealing its secret in time
+    pub forfeit: bool,
+}
+
+pub const BATTLE_COUNT: Item<u64> = Item::new(b"num_battles");
+
+pub fn increment_battles<S: Storage>(storage: &mut S) -> StdResult<u64> {
+    let val = BATTLE_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+    BATTLE_COUNT.save(storage, &val)?;
+    Ok(val)
+}
+
+pub struct BattleIndexes<'a, S: Storage> {
+    pub attacker: MultiIndex<'a, S, Battle>,
+    pub defender: MultiIndex<'a, S, Battle>,
+    pub attacker_owner: MultiIndex<'a, S, Battle>,
+    pub defender_owner: MultiIndex<'a, S, Battle>,
+}
+
+impl<'a, S: Storage> IndexList<S, Battle> for BattleIndexes<'a, S> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<S, Battle>> + '_> {
+        let v: Vec<&dyn Index<S, Battle>> = vec![
+            &self.attacker,
+            &self.defender,
+            &self.attacker_owner,
+            &self.defender_owner,
+        ];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Battles are keyed by their big-endian id, so they are listed in the order they were fought
+pub fn battles<'a, S: Storage>() -> IndexedMap<'a, &'a [u8], Battle, S, BattleIndexes<'a, S>> {
+    let indexes = BattleIndexes {
+        attacker: MultiIndex::new(
+            |b| b.attacker_id.as_bytes().to_vec(),
+            b"battles",
+            b"battles__attacker",
+        ),
+        defender: MultiIndex::new(
+            |b| b.defender_id.as_bytes().to_vec(),
+            b"battles",
+            b"battles__defender",
+        ),
+        attacker_owner: MultiIndex::new(
+            |b| b.attacker_owner.to_vec(),
+            b"battles",
+            b"battles__attacker_owner",
+        ),
+        defender_owner: MultiIndex::new(
+            |b| b.defender_owner.to_vec(),
+            b"battles",
+            b"battles__defender_owner",
+        ),
+    };
+    IndexedMap::new(b"battles", indexes)
+}
+
+/// Rating of a monster in one season, kept after the season is over
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Rating {
+    pub token_id: String,
+    pub season: u64,
+    pub rating: u64,
+    pub wins: u64,
+    pub losses: u64,
+}
+
+pub struct RatingIndexes<'a, S: Storage> {
+    pub token: MultiIndex<'a, S, Rating>,
+    pub rating: MultiIndex<'a, S, Rating>,
+}
+
+impl<'a, S: Storage> IndexList<S, Rating> for RatingIndexes<'a, S> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<S, Rating>> + '_> {
+        let v: Vec<&dyn Index<S, Rating>> = vec![&self.token, &self.rating];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Ratings are keyed by the big-endian season followed by the token id
+pub fn ratings<'a, S: Storage>() -> IndexedMap<'a, &'a [u8], Rating, S, RatingIndexes<'a, S>> {
+    let indexes = RatingIndexes {
+        token: MultiIndex::new(
+            |r| r.token_id.as_bytes().to_vec(),
+            b"ratings",
+            b"ratings__token",
+        ),
+        rating: MultiIndex::new(
+            |r| [index_int(r.season), index_int(r.rating)].concat(),
+            b"ratings",
+            b"ratings__rating",
+        ),
+    };
+    IndexedMap::new(b"ratings", indexes)
+}
+
+pub fn rating_key(season: u64, token_id: &str) -> Vec<u8> {
+    [index_int(season), token_id.as_bytes().to_vec()].concat()
+}
+
+/// The rating index of `ratings()` read as a plain map, as MultiIndex can only list the ratings
+/// of a single value while a ranking spans all of a season
+const RATINGS_BY_RATING: Map<&[u8], u32> = Map::new(b"ratings__rating");
+
+/// ranking_key is the key of a rating in the rating index, joined the way MultiIndex stores it
+pub fn ranking_key(season: u64, rating: u64, token_id: &str) -> Vec<u8> {
+    let idx = [index_int(season), index_int(rating)].concat();
+    (idx.as_slice(), rating_key(season, token_id).as_slice()).joined_key()
+}
+
+/// season_ranking lists (rating, token_id) pairs of a season from the highest rating down,
+/// starting below max if given
+pub fn season_ranking<'a, S: Storage>(
+    storage: &'a S,
+    season: u64,
+    max: Option<Bound>,
+) -> Box<dyn Iterator<Item = StdResult<(u64, String)>> + 'a> {
+    let min = Bound::inclusive(ranking_key(season, 0, ""));
+    let max = max.unwrap_or_else(|| Bound::exclusive(ranking_key(season + 1, 0, "")));
+    let mapped = RATINGS_BY_RATING
+        .range(storage, Some(min), Some(max), Order::Descending)
+        .map(|item| {
+            let (key, _) = item?;
+            let (idx, pk) = <(&[u8], &[u8])>::parse_key(&key);
+            let mut rating = [0u8; 8];
+            rating.copy_from_slice(&idx[8..]);
+            Ok((u64::from_be_bytes(rating), token_id(&pk[8..])?))
+        });
+    Box::new(mapped)
+}
+
+/// A ranked season, ratings only change in battles within its block range
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Season {
+    pub season: u64,
+    pub start_height: u64,
+    /// Unless set, the season runs until it is rolled over
+    pub end_height: Option<u64>,
+    /// Best rated monster, set once the season is over
+    pub champion: Option<String>,
+    pub champion_rating: Option<u64>,
+}
+
+pub const SEASON: Item<Season> = Item::new(b"season");
+/// Past seasons, keyed by their big-endian number
+pub const SEASONS: Map<&[u8], Season> = Map::new(b"seasons");
+
+pub fn current_season<S: Storage>(storage: &S) -> StdResult<Season> {
+    Ok(SEASON.may_load(storage)?.unwrap_or(Season {
+        season: 1,
+        start_height: 0,
+        end_height: None,
+        champion: None,
+        champion_rating: None,
+    }))
+}
+
+/// How often a monster has bred, and when it last did
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Breeding {
+    pub breed_count: u64,
+    pub last_breed_height: Option<u64>,
+}
+
+pub const BREEDING: Map<&str, Breeding> = Map::new(b"breeding");
+/// Parents of bred monsters, minted ones have none
+pub const PARENTS: Map<&str, (String, String)> = Map::new(b"parents");
+
+/// Evolution of a species, registered by the minter
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Evolution {
+    /// Level from which monsters of the species can evolve
+    pub level: u64,
+    pub evolved_name: String,
+    /// Replaces the image of evolving monsters if set
+    pub evolved_image: Option<String>,
+    /// Added to the stats of evolving monsters
+    pub boosts: Stats,
+}
+
+/// Evolutions keyed by the name of the species evolving
+pub const EVOLUTIONS: Map<&str, Evolution> = Map::new(b"evolutions");
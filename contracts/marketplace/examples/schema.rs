@@ -4,7 +4,13 @@ use std::fs::create_dir_all;
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
 use marketplace::msg::{BuyNft, HandleMsg, InitMsg, QueryMsg, SellNft};
-use marketplace::package::{ContractInfoResponse, OfferingsResponse, QueryOfferingsResult};
+use marketplace::package::{
+    BuyResponse, ConfigResponse, ContractInfoResponse, DepositBalanceResponse, EscrowResponse,
+    HooksResponse, InstallmentResponse, InstallmentsResponse, MarketplaceHookMsg, OfferingResponse,
+    OfferingsResponse, PriceResponse, QueryOfferingsResult, RaffleResponse,
+    ReferralEarningsResponse, RentalsResponse, ReputationResponse, SellResponse,
+    SimulateBuyResponse, UserOfResponse,
+};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -20,4 +26,21 @@ fn main() {
     export_schema(&schema_for!(OfferingsResponse), &out_dir);
     export_schema(&schema_for!(ContractInfoResponse), &out_dir);
     export_schema(&schema_for!(QueryOfferingsResult), &out_dir);
+    export_schema(&schema_for!(OfferingResponse), &out_dir);
+    export_schema(&schema_for!(RentalsResponse), &out_dir);
+    export_schema(&schema_for!(UserOfResponse), &out_dir);
+    export_schema(&schema_for!(EscrowResponse), &out_dir);
+    export_schema(&schema_for!(InstallmentResponse), &out_dir);
+    export_schema(&schema_for!(InstallmentsResponse), &out_dir);
+    export_schema(&schema_for!(RaffleResponse), &out_dir);
+    export_schema(&schema_for!(ConfigResponse), &out_dir);
+    export_schema(&schema_for!(DepositBalanceResponse), &out_dir);
+    export_schema(&schema_for!(ReferralEarningsResponse), &out_dir);
+    export_schema(&schema_for!(ReputationResponse), &out_dir);
+    export_schema(&schema_for!(PriceResponse), &out_dir);
+    export_schema(&schema_for!(SimulateBuyResponse), &out_dir);
+    export_schema(&schema_for!(HooksResponse), &out_dir);
+    export_schema(&schema_for!(SellResponse), &out_dir);
+    export_schema(&schema_for!(BuyResponse), &out_dir);
+    export_schema(&schema_for!(MarketplaceHookMsg), &out_dir);
 }
@@ -1,74 +1,282 @@
-use crate::package::{ContractInfoResponse, OfferingsResponse, QueryOfferingsResult};
-use crate::state::{increment_offerings, Offering, CONTRACT_INFO, OFFERINGS};
+use crate::package::{
+    BuyResponse, ConfigResponse, ContractInfoResponse, DepositBalanceResponse, EscrowResponse,
+    HooksResponse, InstallmentResponse, InstallmentsResponse, MarketplaceHookMsg, NftMetadata,
+    OfferingResponse, OfferingsResponse, PriceResponse, QueryOfferingsResult, QueryRentalsResult,
+    RaffleResponse, ReferralEarningsResponse, RentalsResponse, ReputationResponse, SellResponse,
+    SimulateBuyResponse, SimulatedTransfer, TransferKind, UserOfResponse,
+};
+use crate::state::{
+    increment_offerings, installments, offerings, rentals, token_key, watch_key, watches, Escrow,
+    Installment, Offering, PricePoint, Rental, TokenDisplay, Trade, Watch, ADMIN, CONFIG,
+    CONTRACT_INFO, DEPOSIT_BALANCES, ESCROWS, HOOKS, OFFERINGS, PRICE_WINDOWS, RAFFLES,
+    RAFFLE_TICKETS, REFERRAL_EARNINGS, REPUTATIONS, TOKEN_DISPLAY, UNRATED_TRADES,
+};
 use cosmwasm_std::KV;
 use cosmwasm_std::{
-    attr, from_binary, to_binary, Api, Binary, CosmosMsg, Env, Extern, HandleResponse,
-    InitResponse, MessageInfo, Order, Querier, StdResult, Storage, WasmMsg,
+    attr, from_binary, to_binary, Api, Attribute, BankMsg, Binary, CanonicalAddr, CosmosMsg,
+    Decimal, Env, Extern, HandleResponse, HumanAddr, InitResponse, MessageInfo, Order, Querier,
+    StdError, StdResult, Storage, Uint128, WasmMsg, WasmQuery,
 };
-use cw20::{Cw20HandleMsg, Cw20ReceiveMsg};
-use cw721::{Cw721HandleMsg, Cw721ReceiveMsg};
+use cw0::NativeBalance;
+use cw20::{Cw20CoinHuman, Cw20Contract, Cw20HandleMsg, Cw20ReceiveMsg};
+use cw721::{Cw721Contract, Cw721HandleMsg, Cw721QueryMsg, Cw721ReceiveMsg, Expiration};
+use cw_storage_plus::Bound;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::str::from_utf8;
 
 use crate::error::ContractError;
-use crate::msg::{BuyNft, HandleMsg, InitMsg, QueryMsg, SellNft};
+use crate::msg::{BuyNft, Deposit, HandleMsg, InitMsg, OfferingKind, QueryMsg, SellNft};
+use crate::simulation::simulation_deps;
 
 // Note, you can use StdResult in some functions where you do not
 // make use of the custom errors
 pub fn init<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     _env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
     msg: InitMsg,
 ) -> StdResult<InitResponse> {
-    let info = ContractInfoResponse { name: msg.name };
-    CONTRACT_INFO.save(&mut deps.storage, &info)?;
+    let contract_info = ContractInfoResponse { name: msg.name };
+    CONTRACT_INFO.save(&mut deps.storage, &contract_info)?;
+    // the creator manages the hooks
+    let admin = deps.api.canonical_address(&info.sender)?;
+    ADMIN.save(&mut deps.storage, &admin)?;
     Ok(InitResponse::default())
 }
 
 // And declare a custom Error variant for the ones where you will want to make use of it
 pub fn handle<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: HandleMsg,
 ) -> Result<HandleResponse, ContractError> {
     match msg {
-        HandleMsg::WithdrawNft { offering_id } => try_withdraw(deps, info, offering_id),
-        HandleMsg::Receive(msg) => try_receive(deps, info, msg),
-        HandleMsg::ReceiveNft(msg) => try_receive_nft(deps, info, msg),
+        HandleMsg::WithdrawNft { offering_id } => {
+            try_withdraw(deps, env, info, offering_id)?.into_response()
+        }
+        HandleMsg::Receive(msg) => try_receive(deps, env, info, msg)?.into_response(),
+        HandleMsg::ReceiveNft(msg) => try_receive_nft(deps, env, info, msg),
+        HandleMsg::Dispute { offering_id } => try_dispute(deps, env, info, offering_id),
+        HandleMsg::ResolveDispute {
+            offering_id,
+            refund,
+        } => try_resolve_dispute(deps, env, info, offering_id, refund)?.into_response(),
+        HandleMsg::Finalize { offering_id } => {
+            try_finalize(deps, env, offering_id)?.into_response()
+        }
+        HandleMsg::AddHook { addr } => try_add_hook(deps, info, addr),
+        HandleMsg::RemoveHook { addr } => try_remove_hook(deps, info, addr),
+        HandleMsg::RevealRaffleSeed { offering_id, seed } => {
+            try_reveal_raffle_seed(deps, env, info, offering_id, seed)
+        }
+        HandleMsg::DrawRaffle { offering_id } => {
+            try_draw_raffle(deps, env, offering_id)?.into_response()
+        }
+        HandleMsg::CancelRaffle { offering_id } => {
+            try_cancel_raffle(deps, env, offering_id)?.into_response()
+        }
+        HandleMsg::SettleDefault { offering_id } => {
+            try_settle_default(deps, env, offering_id)?.into_response()
+        }
+        HandleMsg::UpdateConfig {
+            max_listings,
+            deposit,
+            protocol_fee,
+            referral_share,
+        } => try_update_config(
+            deps,
+            info,
+            max_listings,
+            deposit,
+            protocol_fee,
+            referral_share,
+        ),
+        HandleMsg::DelistSpam { offering_id } => {
+            try_delist_spam(deps, env, info, offering_id)?.into_response()
+        }
+        HandleMsg::TopUpDeposits {} => try_top_up_deposits(deps, info),
+        HandleMsg::WithdrawDeposits {} => try_withdraw_deposits(deps, env, info),
+        HandleMsg::WithdrawReferralEarnings {} => try_withdraw_referral_earnings(deps, info),
+        HandleMsg::Watch { offering_id } => try_watch(deps, info, offering_id),
+        HandleMsg::Unwatch { offering_id } => try_unwatch(deps, info, offering_id),
+        HandleMsg::RateSeller {
+            offering_id,
+            positive,
+        } => try_rate_seller(deps, info, offering_id, positive),
+        HandleMsg::RefreshMetadata { offering_id } => try_refresh_metadata(deps, offering_id),
     }
 }
 
 // ============================== Message Handlers ==============================
 
+/// Number of recent sales per collection and currency the price queries are based on
+const PRICE_WINDOW_SIZE: usize = 20;
+/// Most blocks a sale is weighted with in the average price, so a lone latest sale can not
+/// take over the average by going unanswered
+const MAX_PRICE_WEIGHT: u64 = 100;
+
+/// Blocks after the end of a raffle the seller has to reveal the seed in,
+/// after which anybody can cancel the raffle
+const RAFFLE_REVEAL_BLOCKS: u64 = 1_000;
+
+/// Most tickets a raffle may sell, which bounds the purchases a draw or cancel goes through
+const MAX_RAFFLE_TICKETS: u32 = 1_000;
+
+/// Transfer is a cw20 payment out of the marketplace
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transfer {
+    pub kind: TransferKind,
+    pub token: HumanAddr,
+    pub recipient: HumanAddr,
+    pub amount: Uint128,
+}
+
+/// Payout is the response of a handler that pays out cw20 tokens together with those payments.
+/// They are only turned into messages by handle, so SimulateBuy can read them as they are
+pub struct Payout {
+    pub response: HandleResponse,
+    pub transfers: Vec<Transfer>,
+}
+
+impl Payout {
+    /// into_response sends the transfers ahead of the messages of the response
+    pub fn into_response(self) -> Result<HandleResponse, ContractError> {
+        let mut messages = vec![];
+        for transfer in self.transfers {
+            messages.push(Cw20Contract(transfer.token).call(Cw20HandleMsg::Transfer {
+                recipient: transfer.recipient,
+                amount: transfer.amount,
+            })?);
+        }
+        messages.extend(self.response.messages);
+        Ok(HandleResponse {
+            messages,
+            ..self.response
+        })
+    }
+}
+
 pub fn try_receive<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
+    env: Env,
     info: MessageInfo,
     rcv_msg: Cw20ReceiveMsg,
-) -> Result<HandleResponse, ContractError> {
+) -> Result<Payout, ContractError> {
     let msg: BuyNft = match rcv_msg.msg {
-        Some(bin) => Ok(from_binary(&bin)?),
+        Some(ref bin) => Ok(from_binary(bin)?),
         None => Err(ContractError::NoData {}),
     }?;
 
     // check if offering exists
     let off = OFFERINGS.load(&deps.storage, &msg.offering_id)?;
 
-    // check for enough coins
-    if rcv_msg.amount < off.list_price.amount {
+    // check if the offering is paid with the token it is priced in
+    if info.sender != off.list_price.address {
+        return Err(ContractError::InvalidCurrency {});
+    }
+
+    // check for enough coins, installments are paid off bit by bit
+    let is_installment = matches!(off.kind, Some(OfferingKind::Installment { .. }));
+    if !is_installment && rcv_msg.amount < off.list_price.amount {
         return Err(ContractError::InsufficientFunds {});
     }
+    // only plain sales have a single purchase a frontend could be credited with
+    if msg.referrer.is_some() && off.kind.is_some() {
+        return Err(ContractError::ReferralNotSupported {});
+    }
 
-    // create transfer cw20 msg
-    let transfer_cw20_msg = Cw20HandleMsg::Transfer {
-        recipient: deps.api.human_address(&off.seller)?,
-        amount: rcv_msg.amount,
-    };
-    let exec_cw20_transfer = WasmMsg::Execute {
-        contract_addr: info.sender.clone(),
-        msg: to_binary(&transfer_cw20_msg)?,
-        send: vec![],
+    match off.kind {
+        Some(OfferingKind::Rental { blocks }) => {
+            try_rent(deps, env, info, rcv_msg, msg.offering_id, off, blocks)
+        }
+        Some(OfferingKind::Escrow {
+            ref arbiter,
+            blocks,
+        }) => {
+            let arbiter = deps.api.canonical_address(arbiter)?;
+            try_buy_escrowed(
+                deps,
+                env,
+                info,
+                rcv_msg,
+                msg.offering_id,
+                off,
+                arbiter,
+                blocks,
+            )
+        }
+        Some(OfferingKind::Installment {
+            down_payment,
+            blocks,
+            forfeit_percent,
+        }) => try_pay_installment(
+            deps,
+            env,
+            info,
+            rcv_msg,
+            msg.offering_id,
+            off,
+            down_payment,
+            blocks,
+            forfeit_percent,
+        ),
+        Some(OfferingKind::Raffle {
+            tickets,
+            end_height,
+            ..
+        }) => try_buy_tickets(
+            deps,
+            env,
+            info,
+            rcv_msg,
+            msg.offering_id,
+            off,
+            tickets,
+            end_height,
+        ),
+        None => try_buy(deps, env, info, rcv_msg, msg.offering_id, off, msg.referrer),
+    }
+}
+
+fn try_buy<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    rcv_msg: Cw20ReceiveMsg,
+    offering_id: String,
+    off: Offering,
+    referrer: Option<HumanAddr>,
+) -> Result<Payout, ContractError> {
+    let seller = deps.api.human_address(&off.seller)?;
+    let contract_addr = deps.api.human_address(&off.contract_addr)?;
+    // the buyer pays the list price, anything above it is sent back
+    let paid_price = Cw20CoinHuman {
+        address: info.sender.clone(),
+        amount: off.list_price.amount,
     };
+    let change = (rcv_msg.amount - paid_price.amount)?;
+    if referrer.as_ref() == Some(&rcv_msg.sender) {
+        return Err(ContractError::SelfReferral {});
+    }
+    let (proceeds, fee_transfers, fee_attributes) = take_fees(deps, &paid_price, referrer)?;
+
+    let mut transfers = vec![Transfer {
+        kind: TransferKind::Proceeds,
+        token: info.sender.clone(),
+        recipient: seller.clone(),
+        amount: proceeds,
+    }];
+    transfers.extend(fee_transfers);
+    if !change.is_zero() {
+        transfers.push(Transfer {
+            kind: TransferKind::Refund,
+            token: info.sender.clone(),
+            recipient: rcv_msg.sender.clone(),
+            amount: change,
+        });
+    }
 
     // create transfer cw721 msg
     let transfer_cw721_msg = Cw721HandleMsg::TransferNft {
@@ -76,276 +284,4516 @@ pub fn try_receive<S: Storage, A: Api, Q: Querier>(
         token_id: off.token_id.clone(),
     };
     let exec_cw721_transfer = WasmMsg::Execute {
-        contract_addr: deps.api.human_address(&off.contract_addr)?,
+        contract_addr: contract_addr.clone(),
         msg: to_binary(&transfer_cw721_msg)?,
         send: vec![],
     };
 
-    // if everything is fine transfer cw20 to seller
-    let cw20_transfer_cosmos_msg: CosmosMsg = exec_cw20_transfer.into();
     // transfer nft to buyer
     let cw721_transfer_cosmos_msg: CosmosMsg = exec_cw721_transfer.into();
 
-    let cosmos_msgs = vec![cw20_transfer_cosmos_msg, cw721_transfer_cosmos_msg];
+    let mut cosmos_msgs = vec![cw721_transfer_cosmos_msg];
+
+    // notify hooks
+    let hook_msg = MarketplaceHookMsg::Sold {
+        offering: humanize_offering(deps.api, &offering_id, off.clone())?,
+        buyer: rcv_msg.sender.clone(),
+        paid_price: paid_price.clone(),
+    };
+    cosmos_msgs.extend(prepare_hooks(deps, hook_msg)?);
 
     //delete offering
-    OFFERINGS.remove(&mut deps.storage, &msg.offering_id);
+    transfers.extend(remove_offering(deps, &offering_id, &off, &off.seller)?);
+    let buyer_raw = deps.api.canonical_address(&rcv_msg.sender)?;
+    record_trade(&mut deps.storage, &offering_id, &off.seller, &buyer_raw)?;
+    record_price(
+        deps,
+        env.block.height,
+        &off.contract_addr,
+        (&off.seller, &buyer_raw),
+        &off.list_price,
+    )?;
 
-    let price_string = format!("{} {}", rcv_msg.amount, info.sender);
+    let data = BuyResponse {
+        offering_id: offering_id.clone(),
+        token_id: off.token_id.clone(),
+        contract_addr: contract_addr.clone(),
+        seller: seller.clone(),
+        buyer: rcv_msg.sender.clone(),
+        paid_price: paid_price.clone(),
+    };
 
-    Ok(HandleResponse {
-        messages: cosmos_msgs,
-        attributes: vec![
-            attr("action", "buy_nft"),
-            attr("buyer", rcv_msg.sender),
-            attr("seller", off.seller),
-            attr("paid_price", price_string),
-            attr("token_id", off.token_id),
-            attr("contract_addr", off.contract_addr),
-        ],
-        data: None,
+    let mut attributes = vec![
+        attr("action", "buy_nft"),
+        attr("offering_id", offering_id),
+        attr("buyer", rcv_msg.sender),
+        attr("seller", seller),
+        attr("amount", paid_price.amount),
+        attr("denom", paid_price.address),
+        attr("token_id", off.token_id),
+        attr("contract_addr", contract_addr),
+    ];
+    attributes.extend(fee_attributes);
+
+    Ok(Payout {
+        response: HandleResponse {
+            messages: cosmos_msgs,
+            attributes,
+            data: Some(to_binary(&data)?),
+        },
+        transfers,
     })
 }
 
-pub fn try_receive_nft<S: Storage, A: Api, Q: Querier>(
+/// take_fees splits the protocol fee off a payment going to a seller. The admin gets it right
+/// away, except for the share a referring frontend earns, which is kept until withdrawn.
+/// Returns what is left for the seller, the fee transfers and their attributes
+fn take_fees<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    info: MessageInfo,
-    rcv_msg: Cw721ReceiveMsg,
-) -> Result<HandleResponse, ContractError> {
-    let msg: SellNft = match rcv_msg.msg {
-        Some(bin) => Ok(from_binary(&bin)?),
-        None => Err(ContractError::NoData {}),
-    }?;
-
-    // check if same token Id form same original contract is already on sale
-    // get OFFERING_COUNT
-    let id = increment_offerings(&mut deps.storage)?.to_string();
-
-    // save Offering
-    let off = Offering {
-        contract_addr: deps.api.canonical_address(&info.sender)?,
-        token_id: rcv_msg.token_id,
-        seller: deps.api.canonical_address(&rcv_msg.sender)?,
-        list_price: msg.list_price.clone(),
+    payment: &Cw20CoinHuman,
+    referrer: Option<HumanAddr>,
+) -> StdResult<(Uint128, Vec<Transfer>, Vec<Attribute>)> {
+    let config = CONFIG.may_load(&deps.storage)?.unwrap_or_default();
+    let fee = payment.amount * config.protocol_fee.unwrap_or_else(Decimal::zero);
+    let referral_fee = match referrer {
+        Some(_) => fee * config.referral_share.unwrap_or_else(Decimal::zero),
+        None => Uint128::zero(),
     };
+    let protocol_fee = (fee - referral_fee)?;
+    let proceeds = (payment.amount - fee)?;
 
-    OFFERINGS.save(&mut deps.storage, &id, &off)?;
-
-    let price_string = format!("{} {}", msg.list_price.amount, msg.list_price.address);
+    let mut transfers = vec![];
+    let mut attributes = vec![];
+    if !protocol_fee.is_zero() {
+        transfers.push(Transfer {
+            kind: TransferKind::ProtocolFee,
+            token: payment.address.clone(),
+            recipient: deps.api.human_address(&ADMIN.load(&deps.storage)?)?,
+            amount: protocol_fee,
+        });
+        attributes.push(attr("protocol_fee", protocol_fee));
+    }
 
-    Ok(HandleResponse {
-        messages: Vec::new(),
-        attributes: vec![
-            attr("action", "sell_nft"),
-            attr("original_contract", info.sender),
-            attr("seller", off.seller),
-            attr("list_price", price_string),
-            attr("token_id", off.token_id),
-        ],
-        data: None,
-    })
+    // referral fees are kept until the referrer withdraws them
+    if let Some(referrer) = referrer.filter(|_| !referral_fee.is_zero()) {
+        let key = deps.api.canonical_address(&referrer)?;
+        let mut earnings = REFERRAL_EARNINGS
+            .may_load(&deps.storage, key.as_slice())?
+            .unwrap_or_default();
+        match earnings
+            .iter_mut()
+            .find(|coin| coin.address == payment.address)
+        {
+            Some(coin) => coin.amount += referral_fee,
+            None => earnings.push(Cw20CoinHuman {
+                address: payment.address.clone(),
+                amount: referral_fee,
+            }),
+        }
+        REFERRAL_EARNINGS.save(&mut deps.storage, key.as_slice(), &earnings)?;
+        attributes.push(attr("referrer", referrer));
+        attributes.push(attr("referral_fee", referral_fee));
+    }
+    Ok((proceeds, transfers, attributes))
 }
 
-pub fn try_withdraw<S: Storage, A: Api, Q: Querier>(
+fn try_rent<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
+    env: Env,
     info: MessageInfo,
+    rcv_msg: Cw20ReceiveMsg,
     offering_id: String,
-) -> Result<HandleResponse, ContractError> {
-    // check if token_id is currently sold by the requesting address
-    let off = OFFERINGS.load(&deps.storage, &offering_id)?;
-    if off.seller == deps.api.canonical_address(&info.sender)? {
-        // transfer token back to original owner
-        let transfer_cw721_msg = Cw721HandleMsg::TransferNft {
-            recipient: deps.api.human_address(&off.seller)?,
-            token_id: off.token_id.clone(),
-        };
+    off: Offering,
+    blocks: u64,
+) -> Result<Payout, ContractError> {
+    // only one renter at a time, an expired rental may be replaced
+    if let Some(rental) = rentals::<S>().may_load(&deps.storage, &offering_id)? {
+        if !rental.expires.is_expired(&env.block) {
+            return Err(ContractError::Rented {});
+        }
+    }
 
-        let exec_cw721_transfer = WasmMsg::Execute {
-            contract_addr: deps.api.human_address(&off.contract_addr)?,
-            msg: to_binary(&transfer_cw721_msg)?,
-            send: vec![],
-        };
+    let rental = Rental {
+        token_id: off.token_id.clone(),
+        contract_addr: off.contract_addr.clone(),
+        owner: off.seller.clone(),
+        user: deps.api.canonical_address(&rcv_msg.sender)?,
+        expires: Expiration::AtHeight(env.block.height + blocks),
+    };
+    rentals().save(&mut deps.storage, &offering_id, &rental)?;
 
-        let cw721_transfer_cosmos_msg: Vec<CosmosMsg> = vec![exec_cw721_transfer.into()];
+    // the fee goes to the owner right away, the token stays with the marketplace
+    let owner = deps.api.human_address(&off.seller)?;
+    let payment = Cw20CoinHuman {
+        address: info.sender.clone(),
+        amount: rcv_msg.amount,
+    };
+    let (proceeds, fee_transfers, fee_attributes) = take_fees(deps, &payment, None)?;
+    let mut transfers = vec![Transfer {
+        kind: TransferKind::Proceeds,
+        token: info.sender.clone(),
+        recipient: owner.clone(),
+        amount: proceeds,
+    }];
+    transfers.extend(fee_transfers);
 
-        // remove offering
-        OFFERINGS.remove(&mut deps.storage, &offering_id);
+    let mut attributes = vec![
+        attr("action", "rent_nft"),
+        attr("offering_id", offering_id),
+        attr("renter", rcv_msg.sender),
+        attr("owner", owner),
+        attr("amount", rcv_msg.amount),
+        attr("denom", info.sender),
+        attr("token_id", off.token_id),
+        attr("expires", rental.expires),
+    ];
+    attributes.extend(fee_attributes);
 
-        return Ok(HandleResponse {
-            messages: cw721_transfer_cosmos_msg,
-            attributes: vec![
-                attr("action", "withdraw_nft"),
-                attr("seller", info.sender),
-                attr("offering_id", offering_id),
-            ],
+    Ok(Payout {
+        response: HandleResponse {
+            messages: vec![],
+            attributes,
             data: None,
-        });
-    }
-    Err(ContractError::Unauthorized {})
+        },
+        transfers,
+    })
 }
 
-pub fn query<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-    _env: Env,
-    msg: QueryMsg,
-) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::GetOfferings {} => to_binary(&query_offerings(deps)?),
-    }
-}
+#[allow(clippy::too_many_arguments)]
+fn try_buy_escrowed<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    rcv_msg: Cw20ReceiveMsg,
+    offering_id: String,
+    off: Offering,
+    arbiter: CanonicalAddr,
+    blocks: u64,
+) -> Result<Payout, ContractError> {
+    let paid_price = Cw20CoinHuman {
+        address: info.sender,
+        amount: rcv_msg.amount,
+    };
 
-// ============================== Query Handlers ==============================
+    // notify hooks
+    let hook_msg = MarketplaceHookMsg::Sold {
+        offering: humanize_offering(deps.api, &offering_id, off.clone())?,
+        buyer: rcv_msg.sender.clone(),
+        paid_price: paid_price.clone(),
+    };
+    let messages = prepare_hooks(deps, hook_msg)?;
 
-fn query_offerings<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-) -> StdResult<OfferingsResponse> {
-    let res: StdResult<Vec<QueryOfferingsResult>> = OFFERINGS
-        .range(&deps.storage, None, None, Order::Ascending)
-        .map(|kv_item| parse_offering(deps.api, kv_item))
+    // both the payment and the token stay with the marketplace until the trade is finalized
+    let escrow = Escrow {
+        token_id: off.token_id.clone(),
+        contract_addr: off.contract_addr.clone(),
+        seller: off.seller.clone(),
+        buyer: deps.api.canonical_address(&rcv_msg.sender)?,
+        arbiter,
+        payment: paid_price.clone(),
+        list_price: off.list_price.amount,
+        expires: Expiration::AtHeight(env.block.height + blocks),
+        disputed: false,
+    };
+    ESCROWS.save(&mut deps.storage, &offering_id, &escrow)?;
+
+    // the offering is sold and cannot be bought or withdrawn anymore
+    let transfers = remove_offering(deps, &offering_id, &off, &off.seller)?
+        .into_iter()
         .collect();
 
-    Ok(OfferingsResponse {
-        offerings: res?, // Placeholder
-    })
-}
+    let seller = deps.api.human_address(&off.seller)?;
+    let contract_addr = deps.api.human_address(&off.contract_addr)?;
+    let data = BuyResponse {
+        offering_id: offering_id.clone(),
+        token_id: off.token_id.clone(),
+        contract_addr: contract_addr.clone(),
+        seller: seller.clone(),
+        buyer: rcv_msg.sender.clone(),
+        paid_price: paid_price.clone(),
+    };
 
-fn parse_offering<A: Api>(
-    api: A,
-    item: StdResult<KV<Offering>>,
-) -> StdResult<QueryOfferingsResult> {
-    item.and_then(|(k, offering)| {
-        let id = from_utf8(&k)?;
-        Ok(QueryOfferingsResult {
-            id: id.to_string(),
-            token_id: offering.token_id,
-            list_price: offering.list_price,
-            contract_addr: api.human_address(&offering.contract_addr)?,
-            seller: api.human_address(&offering.seller)?,
-        })
+    let attributes = vec![
+        attr("action", "buy_nft_escrowed"),
+        attr("offering_id", offering_id),
+        attr("buyer", rcv_msg.sender),
+        attr("seller", seller),
+        attr("amount", paid_price.amount),
+        attr("denom", paid_price.address),
+        attr("token_id", off.token_id),
+        attr("contract_addr", contract_addr),
+        attr("expires", escrow.expires),
+    ];
+
+    Ok(Payout {
+        response: HandleResponse {
+            messages,
+            attributes,
+            data: Some(to_binary(&data)?),
+        },
+        transfers,
     })
 }
 
-// ============================== Test ==============================
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coins, from_binary, HumanAddr, Uint128};
-    use cw20::Cw20CoinHuman;
+#[allow(clippy::too_many_arguments)]
+fn try_pay_installment<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    rcv_msg: Cw20ReceiveMsg,
+    offering_id: String,
+    off: Offering,
+    down_payment: Uint128,
+    blocks: u64,
+    forfeit_percent: u64,
+) -> Result<Payout, ContractError> {
+    let buyer_raw = deps.api.canonical_address(&rcv_msg.sender)?;
+    let mut transfers = vec![];
 
-    //     #[test]
-    //     fn proper_initialization() {
-    //         let mut deps = mock_dependencies(&[]);
+    let reservation = match installments::<S>().may_load(&deps.storage, &offering_id)? {
+        // a defaulted reservation is settled before the token can be reserved again
+        Some(installment) if installment.deadline.is_expired(&env.block) => {
+            transfers = settle_default(
+                deps,
+                &offering_id,
+                &off,
+                installment,
+                down_payment,
+                forfeit_percent,
+            )?;
+            None
+        }
+        Some(installment) if installment.buyer != buyer_raw => {
+            return Err(ContractError::Reserved {});
+        }
+        reservation => reservation,
+    };
 
-    //         let msg = InitMsg { count: 17 };
-    //         let info = mock_info("creator", &coins(1000, "earth"));
+    let mut installment = match reservation {
+        Some(installment) => installment,
+        None => {
+            if rcv_msg.amount < down_payment {
+                return Err(ContractError::InsufficientFunds {});
+            }
+            Installment {
+                buyer: buyer_raw,
+                paid: Uint128::zero(),
+                deadline: Expiration::AtHeight(env.block.height + blocks),
+            }
+        }
+    };
+    installment.paid += rcv_msg.amount;
 
-    //         // we can just call .unwrap() to assert this was a success
-    //         let res = init(&mut deps, mock_env(), info, msg).unwrap();
-    //         assert_eq!(0, res.messages.len());
+    if installment.paid < off.list_price.amount {
+        installments().save(&mut deps.storage, &offering_id, &installment)?;
+        let outstanding = (off.list_price.amount - installment.paid)?;
+        let attributes = vec![
+            attr("action", "pay_installment"),
+            attr("offering_id", offering_id),
+            attr("buyer", rcv_msg.sender),
+            attr("amount", rcv_msg.amount),
+            attr("denom", info.sender),
+            attr("paid", installment.paid),
+            attr("outstanding", outstanding),
+        ];
 
-    //         // it worked, let's query the state
-    //         let res = query(&deps, mock_env(), QueryMsg::GetCount {}).unwrap();
-    //         let value: CountResponse = from_binary(&res).unwrap();
-    //         assert_eq!(17, value.count);
-    //     }
+        return Ok(Payout {
+            response: HandleResponse {
+                messages: vec![],
+                attributes,
+                data: None,
+            },
+            transfers,
+        });
+    }
 
-    #[test]
-    fn sell_offering_happy_path() {
-        let mut deps = mock_dependencies(&coins(2, "token"));
+    // paid off, the seller gets all payments less the fees and the buyer the token
+    let seller = deps.api.human_address(&off.seller)?;
+    let contract_addr = deps.api.human_address(&off.contract_addr)?;
+    let paid_price = Cw20CoinHuman {
+        address: info.sender,
+        amount: installment.paid,
+    };
+    let (proceeds, fee_transfers, fee_attributes) = take_fees(deps, &paid_price, None)?;
+    transfers.push(Transfer {
+        kind: TransferKind::Proceeds,
+        token: paid_price.address.clone(),
+        recipient: seller.clone(),
+        amount: proceeds,
+    });
+    transfers.extend(fee_transfers);
+    let mut messages =
+        vec![
+            Cw721Contract(contract_addr.clone()).call(Cw721HandleMsg::TransferNft {
+                recipient: rcv_msg.sender.clone(),
+                token_id: off.token_id.clone(),
+            })?,
+        ];
 
-        let msg = InitMsg {
-            name: String::from("test market"),
-        };
-        let info = mock_info("creator", &coins(2, "token"));
-        let _res = init(&mut deps, mock_env(), info, msg).unwrap();
+    // notify hooks
+    let hook_msg = MarketplaceHookMsg::Sold {
+        offering: humanize_offering(deps.api, &offering_id, off.clone())?,
+        buyer: rcv_msg.sender.clone(),
+        paid_price: paid_price.clone(),
+    };
+    messages.extend(prepare_hooks(deps, hook_msg)?);
 
-        // beneficiary can release it
-        let info = mock_info("anyone", &coins(2, "token"));
+    installments::<S>().remove(&mut deps.storage, &offering_id)?;
+    transfers.extend(remove_offering(deps, &offering_id, &off, &off.seller)?);
+    record_trade(
+        &mut deps.storage,
+        &offering_id,
+        &off.seller,
+        &installment.buyer,
+    )?;
+    record_price(
+        deps,
+        env.block.height,
+        &off.contract_addr,
+        (&off.seller, &installment.buyer),
+        &off.list_price,
+    )?;
 
-        let sell_msg = SellNft {
-            list_price: Cw20CoinHuman {
-                address: HumanAddr::from("cw20ContractAddr"),
-                amount: Uint128(5),
-            },
-        };
+    let data = BuyResponse {
+        offering_id: offering_id.clone(),
+        token_id: off.token_id.clone(),
+        contract_addr: contract_addr.clone(),
+        seller: seller.clone(),
+        buyer: rcv_msg.sender.clone(),
+        paid_price: paid_price.clone(),
+    };
 
-        let msg = HandleMsg::ReceiveNft(Cw721ReceiveMsg {
-            sender: HumanAddr::from("seller"),
-            token_id: String::from("SellableNFT"),
-            msg: to_binary(&sell_msg).ok(),
-        });
-        let _res = handle(&mut deps, mock_env(), info, msg).unwrap();
+    let mut attributes = vec![
+        attr("action", "buy_nft"),
+        attr("offering_id", offering_id),
+        attr("buyer", rcv_msg.sender),
+        attr("seller", seller),
+        attr("amount", paid_price.amount),
+        attr("denom", paid_price.address),
+        attr("token_id", off.token_id),
+        attr("contract_addr", contract_addr),
+    ];
+    attributes.extend(fee_attributes);
 
-        // Offering should be listed
-        let res = query(&deps, mock_env(), QueryMsg::GetOfferings {}).unwrap();
-        let value: OfferingsResponse = from_binary(&res).unwrap();
-        assert_eq!(1, value.offerings.len());
+    Ok(Payout {
+        response: HandleResponse {
+            messages,
+            attributes,
+            data: Some(to_binary(&data)?),
+        },
+        transfers,
+    })
+}
 
-        let buy_msg = BuyNft {
-            offering_id: value.offerings[0].id.clone(),
-        };
+/// settle_default removes a defaulted reservation. The seller keeps the forfeited share of the
+/// down payment and the buyer gets the rest of the payments back.
+fn settle_default<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    offering_id: &str,
+    off: &Offering,
+    installment: Installment,
+    down_payment: Uint128,
+    forfeit_percent: u64,
+) -> StdResult<Vec<Transfer>> {
+    let forfeited = down_payment
+        .multiply_ratio(forfeit_percent, 100u64)
+        .min(installment.paid);
+    let refund = (installment.paid - forfeited)?;
 
-        let msg2 = HandleMsg::Receive(Cw20ReceiveMsg {
-            sender: HumanAddr::from("buyer"),
-            amount: Uint128(5),
-            msg: to_binary(&buy_msg).ok(),
+    let mut transfers = vec![];
+    if !forfeited.is_zero() {
+        transfers.push(Transfer {
+            kind: TransferKind::Proceeds,
+            token: off.list_price.address.clone(),
+            recipient: deps.api.human_address(&off.seller)?,
+            amount: forfeited,
         });
+    }
+    if !refund.is_zero() {
+        transfers.push(Transfer {
+            kind: TransferKind::Refund,
+            token: off.list_price.address.clone(),
+            recipient: deps.api.human_address(&installment.buyer)?,
+            amount: refund,
+        });
+    }
 
-        let info_buy = mock_info("cw20ContractAddr", &coins(2, "token"));
-
-        let _res = handle(&mut deps, mock_env(), info_buy, msg2).unwrap();
+    installments::<S>().remove(&mut deps.storage, offering_id)?;
+    Ok(transfers)
+}
 
-        // check offerings again. Should be 0
-        let res2 = query(&deps, mock_env(), QueryMsg::GetOfferings {}).unwrap();
-        let value2: OfferingsResponse = from_binary(&res2).unwrap();
-        assert_eq!(0, value2.offerings.len());
+pub fn try_settle_default<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    offering_id: String,
+) -> Result<Payout, ContractError> {
+    let off = OFFERINGS.load(&deps.storage, &offering_id)?;
+    let installment = installments::<S>().load(&deps.storage, &offering_id)?;
+    if !installment.deadline.is_expired(&env.block) {
+        return Err(ContractError::NotDefaulted {});
     }
 
-    #[test]
-    fn withdraw_offering_happy_path() {
-        let mut deps = mock_dependencies(&coins(2, "token"));
+    let (down_payment, forfeit_percent) = match off.kind {
+        Some(OfferingKind::Installment {
+            down_payment,
+            forfeit_percent,
+            ..
+        }) => (down_payment, forfeit_percent),
+        _ => return Err(ContractError::InvalidTerms {}),
+    };
+    let buyer = deps.api.human_address(&installment.buyer)?;
+    let transfers = settle_default(
+        deps,
+        &offering_id,
+        &off,
+        installment,
+        down_payment,
+        forfeit_percent,
+    )?;
 
-        let msg = InitMsg {
-            name: String::from("test market"),
-        };
-        let info = mock_info("creator", &coins(2, "token"));
-        let _res = init(&mut deps, mock_env(), info, msg).unwrap();
+    Ok(Payout {
+        response: HandleResponse {
+            messages: vec![],
+            attributes: vec![
+                attr("action", "settle_default"),
+                attr("offering_id", offering_id),
+                attr("buyer", buyer),
+            ],
+            data: None,
+        },
+        transfers,
+    })
+}
 
-        // beneficiary can release it
-        let info = mock_info("anyone", &coins(2, "token"));
+#[allow(clippy::too_many_arguments)]
+fn try_buy_tickets<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    rcv_msg: Cw20ReceiveMsg,
+    offering_id: String,
+    off: Offering,
+    tickets: u32,
+    end_height: u64,
+) -> Result<Payout, ContractError> {
+    if env.block.height >= end_height {
+        return Err(ContractError::RaffleEnded {});
+    }
 
-        let sell_msg = SellNft {
-            list_price: Cw20CoinHuman {
-                address: HumanAddr::from("cw20ContractAddr"),
-                amount: Uint128(5),
-            },
-        };
+    // as many tickets as the payment covers, the change is sent back
+    let count = rcv_msg.amount.u128() / off.list_price.amount.u128();
+    let mut raffle = RAFFLES
+        .may_load(&deps.storage, &offering_id)?
+        .unwrap_or_default();
+    if raffle.sold as u128 + count > tickets as u128 {
+        return Err(ContractError::SoldOut {});
+    }
 
-        let msg = HandleMsg::ReceiveNft(Cw721ReceiveMsg {
-            sender: HumanAddr::from("seller"),
-            token_id: String::from("SellableNFT"),
-            msg: to_binary(&sell_msg).ok(),
+    if count > 0 {
+        let buyer_raw = deps.api.canonical_address(&rcv_msg.sender)?;
+        let first = raffle.sold.to_be_bytes();
+        RAFFLE_TICKETS.save(&mut deps.storage, (&offering_id, &first), &buyer_raw)?;
+        raffle.sold += count as u32;
+    }
+    RAFFLES.save(&mut deps.storage, &offering_id, &raffle)?;
+
+    let cost = off.list_price.amount.multiply_ratio(count, 1u128);
+    let change = (rcv_msg.amount - cost)?;
+    let mut transfers = vec![];
+    if !change.is_zero() {
+        transfers.push(Transfer {
+            kind: TransferKind::Refund,
+            token: info.sender.clone(),
+            recipient: rcv_msg.sender.clone(),
+            amount: change,
         });
-        let _res = handle(&mut deps, mock_env(), info, msg).unwrap();
+    }
 
-        // Offering should be listed
-        let res = query(&deps, mock_env(), QueryMsg::GetOfferings {}).unwrap();
+    Ok(Payout {
+        response: HandleResponse {
+            messages: vec![],
+            attributes: vec![
+                attr("action", "buy_tickets"),
+                attr("offering_id", offering_id),
+                attr("buyer", rcv_msg.sender),
+                attr("tickets", count),
+                attr("amount", cost),
+                attr("denom", info.sender),
+            ],
+            data: None,
+        },
+        transfers,
+    })
+}
+
+pub fn try_reveal_raffle_seed<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    offering_id: String,
+    seed: String,
+) -> Result<HandleResponse, ContractError> {
+    let off = OFFERINGS.load(&deps.storage, &offering_id)?;
+    let (end_height, seed_commitment) = match off.kind {
+        Some(OfferingKind::Raffle {
+            end_height,
+            seed_commitment,
+            ..
+        }) => (end_height, seed_commitment),
+        _ => return Err(ContractError::NotRaffle {}),
+    };
+
+    if off.seller != deps.api.canonical_address(&info.sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    // revealing early would let ticket buyers work out the draw in advance
+    if env.block.height < end_height {
+        return Err(ContractError::RaffleNotEnded {});
+    }
+    if Sha256::digest(seed.as_bytes())[..] != seed_commitment.as_slice()[..] {
+        return Err(ContractError::InvalidSeed {});
+    }
+
+    let mut raffle = RAFFLES
+        .may_load(&deps.storage, &offering_id)?
+        .unwrap_or_default();
+    raffle.seed = Some(seed);
+    RAFFLES.save(&mut deps.storage, &offering_id, &raffle)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "reveal_raffle_seed"),
+            attr("offering_id", offering_id),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_draw_raffle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    offering_id: String,
+) -> Result<Payout, ContractError> {
+    let off = OFFERINGS.load(&deps.storage, &offering_id)?;
+    let (min_tickets, end_height) = match off.kind {
+        Some(OfferingKind::Raffle {
+            min_tickets,
+            end_height,
+            ..
+        }) => (min_tickets, end_height),
+        _ => return Err(ContractError::NotRaffle {}),
+    };
+
+    if env.block.height < end_height {
+        return Err(ContractError::RaffleNotEnded {});
+    }
+    let raffle = RAFFLES
+        .may_load(&deps.storage, &offering_id)?
+        .unwrap_or_default();
+    if raffle.sold == 0 || raffle.sold < min_tickets {
+        return Err(ContractError::NotEnoughTickets {});
+    }
+    let seed = raffle.seed.ok_or(ContractError::SeedNotRevealed {})?;
+
+    // the buyers didn't know the seed when buying tickets. Nothing of the draw block goes in,
+    // as whoever draws knows the seed by then and could wait for a block they win in
+    let mut hasher = Sha256::new();
+    hasher.update(seed.as_bytes());
+    hasher.update(end_height.to_be_bytes());
+    hasher.update(offering_id.as_bytes());
+    let randomness = hasher.finalize();
+    let mut head = [0u8; 8];
+    head.copy_from_slice(&randomness[..8]);
+    let index = (u64::from_be_bytes(head) % raffle.sold as u64) as u32;
+    // the winning ticket belongs to the last purchase starting at or before it
+    let (_, winner_raw) = RAFFLE_TICKETS
+        .prefix(&offering_id)
+        .range(
+            &deps.storage,
+            None,
+            Some(Bound::inclusive(index.to_be_bytes().to_vec())),
+            Order::Descending,
+        )
+        .next()
+        .ok_or_else(|| StdError::not_found("raffle ticket"))??;
+    let winner = deps.api.human_address(&winner_raw)?;
+
+    let seller = deps.api.human_address(&off.seller)?;
+    let contract_addr = deps.api.human_address(&off.contract_addr)?;
+    let paid_price = Cw20CoinHuman {
+        address: off.list_price.address.clone(),
+        amount: off.list_price.amount.multiply_ratio(raffle.sold, 1u128),
+    };
+
+    // the ticket revenue less the fees goes to the seller and the token to the winner
+    let (proceeds, fee_transfers, fee_attributes) = take_fees(deps, &paid_price, None)?;
+    let mut transfers = vec![Transfer {
+        kind: TransferKind::Proceeds,
+        token: paid_price.address.clone(),
+        recipient: seller.clone(),
+        amount: proceeds,
+    }];
+    transfers.extend(fee_transfers);
+    let mut messages =
+        vec![
+            Cw721Contract(contract_addr.clone()).call(Cw721HandleMsg::TransferNft {
+                recipient: winner.clone(),
+                token_id: off.token_id.clone(),
+            })?,
+        ];
+
+    // notify hooks
+    let hook_msg = MarketplaceHookMsg::Sold {
+        offering: humanize_offering(deps.api, &offering_id, off.clone())?,
+        buyer: winner.clone(),
+        paid_price: paid_price.clone(),
+    };
+    messages.extend(prepare_hooks(deps, hook_msg)?);
+
+    transfers.extend(remove_offering(deps, &offering_id, &off, &off.seller)?);
+    record_trade(&mut deps.storage, &offering_id, &off.seller, &winner_raw)?;
+    remove_raffle(&mut deps.storage, &offering_id)?;
+
+    let data = BuyResponse {
+        offering_id: offering_id.clone(),
+        token_id: off.token_id.clone(),
+        contract_addr: contract_addr.clone(),
+        seller: seller.clone(),
+        buyer: winner.clone(),
+        paid_price: paid_price.clone(),
+    };
+
+    let mut attributes = vec![
+        attr("action", "draw_raffle"),
+        attr("offering_id", offering_id),
+        attr("winner", winner),
+        attr("seller", seller),
+        attr("amount", paid_price.amount),
+        attr("denom", paid_price.address),
+        attr("token_id", off.token_id),
+        attr("contract_addr", contract_addr),
+    ];
+    attributes.extend(fee_attributes);
+
+    Ok(Payout {
+        response: HandleResponse {
+            messages,
+            attributes,
+            data: Some(to_binary(&data)?),
+        },
+        transfers,
+    })
+}
+
+pub fn try_cancel_raffle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    offering_id: String,
+) -> Result<Payout, ContractError> {
+    let off = OFFERINGS.load(&deps.storage, &offering_id)?;
+    let (min_tickets, end_height) = match off.kind {
+        Some(OfferingKind::Raffle {
+            min_tickets,
+            end_height,
+            ..
+        }) => (min_tickets, end_height),
+        _ => return Err(ContractError::NotRaffle {}),
+    };
+
+    if env.block.height < end_height {
+        return Err(ContractError::RaffleNotEnded {});
+    }
+    let raffle = RAFFLES
+        .may_load(&deps.storage, &offering_id)?
+        .unwrap_or_default();
+    let too_few_sold = raffle.sold == 0 || raffle.sold < min_tickets;
+    let never_revealed =
+        raffle.seed.is_none() && env.block.height >= end_height + RAFFLE_REVEAL_BLOCKS;
+    if !too_few_sold && !never_revealed {
+        return Err(ContractError::NotCancellable {});
+    }
+
+    // refund every ticket holder in one transfer, each purchase lasts until the next one
+    let purchases = RAFFLE_TICKETS
+        .prefix(&offering_id)
+        .range(&deps.storage, None, None, Order::Descending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut refunds: Vec<(CanonicalAddr, u32)> = vec![];
+    let mut end = raffle.sold;
+    for (first, holder) in purchases {
+        let first = parse_ticket(&first)?;
+        match refunds.iter_mut().find(|(addr, _)| *addr == holder) {
+            Some((_, count)) => *count += end - first,
+            None => refunds.push((holder, end - first)),
+        }
+        end = first;
+    }
+    let mut transfers = vec![];
+    for (holder, count) in refunds {
+        transfers.push(Transfer {
+            kind: TransferKind::Refund,
+            token: off.list_price.address.clone(),
+            recipient: deps.api.human_address(&holder)?,
+            amount: off.list_price.amount.multiply_ratio(count, 1u128),
+        });
+    }
+
+    // transfer token back to the seller
+    let contract_addr = deps.api.human_address(&off.contract_addr)?;
+    let mut messages = vec![
+        Cw721Contract(contract_addr).call(Cw721HandleMsg::TransferNft {
+            recipient: deps.api.human_address(&off.seller)?,
+            token_id: off.token_id.clone(),
+        })?,
+    ];
+
+    // notify hooks
+    let hook_msg = MarketplaceHookMsg::Withdrawn {
+        offering: humanize_offering(deps.api, &offering_id, off.clone())?,
+    };
+    messages.extend(prepare_hooks(deps, hook_msg)?);
+
+    remove_raffle(&mut deps.storage, &offering_id)?;
+    transfers.extend(remove_offering(deps, &offering_id, &off, &off.seller)?);
+
+    Ok(Payout {
+        response: HandleResponse {
+            messages,
+            attributes: vec![
+                attr("action", "cancel_raffle"),
+                attr("offering_id", offering_id),
+            ],
+            data: None,
+        },
+        transfers,
+    })
+}
+
+/// remove_raffle deletes the ticket count and all ticket purchases of a raffle
+fn remove_raffle<S: Storage>(storage: &mut S, offering_id: &str) -> StdResult<()> {
+    let firsts = RAFFLE_TICKETS
+        .prefix(offering_id)
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(first, _)| first))
+        .collect::<StdResult<Vec<_>>>()?;
+    for first in firsts {
+        RAFFLE_TICKETS.remove(storage, (offering_id, &first));
+    }
+    RAFFLES.remove(storage, offering_id);
+    Ok(())
+}
+
+/// parse_ticket reads the ticket number of a RAFFLE_TICKETS key
+fn parse_ticket(key: &[u8]) -> StdResult<u32> {
+    let mut number = [0u8; 4];
+    if key.len() != number.len() {
+        return Err(StdError::parse_err("u32", "invalid raffle ticket key"));
+    }
+    number.copy_from_slice(key);
+    Ok(u32::from_be_bytes(number))
+}
+
+pub fn try_dispute<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    offering_id: String,
+) -> Result<HandleResponse, ContractError> {
+    let mut escrow = ESCROWS.load(&deps.storage, &offering_id)?;
+
+    // only the parties of the trade can dispute it
+    let sender_raw = deps.api.canonical_address(&info.sender)?;
+    if sender_raw != escrow.buyer && sender_raw != escrow.seller {
+        return Err(ContractError::Unauthorized {});
+    }
+    if escrow.disputed {
+        return Err(ContractError::Disputed {});
+    }
+    if escrow.expires.is_expired(&env.block) {
+        return Err(ContractError::DisputeWindowClosed {});
+    }
+
+    escrow.disputed = true;
+    ESCROWS.save(&mut deps.storage, &offering_id, &escrow)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "dispute"),
+            attr("sender", info.sender),
+            attr("offering_id", offering_id),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_resolve_dispute<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    offering_id: String,
+    refund: bool,
+) -> Result<Payout, ContractError> {
+    let escrow = ESCROWS.load(&deps.storage, &offering_id)?;
+
+    if deps.api.canonical_address(&info.sender)? != escrow.arbiter {
+        return Err(ContractError::Unauthorized {});
+    }
+    if !escrow.disputed {
+        return Err(ContractError::NotDisputed {});
+    }
+
+    let mut attributes = vec![
+        attr("action", "resolve_dispute"),
+        attr("arbiter", info.sender),
+        attr("offering_id", &offering_id),
+        attr("refund", refund),
+    ];
+    let (message, transfers) = if refund {
+        // unwind the trade, the token goes back to the seller and the payment to the buyer
+        let (message, transfer) = release_escrow(
+            deps.api,
+            &escrow,
+            (&escrow.seller, &escrow.buyer),
+            TransferKind::Refund,
+            escrow.payment.amount,
+        )?;
+        (message, vec![transfer])
+    } else {
+        record_trade(
+            &mut deps.storage,
+            &offering_id,
+            &escrow.seller,
+            &escrow.buyer,
+        )?;
+        record_price(
+            deps,
+            env.block.height,
+            &escrow.contract_addr,
+            (&escrow.seller, &escrow.buyer),
+            &Cw20CoinHuman {
+                address: escrow.payment.address.clone(),
+                amount: escrow.list_price,
+            },
+        )?;
+        let (proceeds, fee_transfers, fee_attributes) = take_fees(deps, &escrow.payment, None)?;
+        attributes.extend(fee_attributes);
+        let (message, transfer) = release_escrow(
+            deps.api,
+            &escrow,
+            (&escrow.buyer, &escrow.seller),
+            TransferKind::Proceeds,
+            proceeds,
+        )?;
+        let mut transfers = vec![transfer];
+        transfers.extend(fee_transfers);
+        (message, transfers)
+    };
+    ESCROWS.remove(&mut deps.storage, &offering_id);
+
+    Ok(Payout {
+        response: HandleResponse {
+            messages: vec![message],
+            attributes,
+            data: None,
+        },
+        transfers,
+    })
+}
+
+pub fn try_finalize<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    offering_id: String,
+) -> Result<Payout, ContractError> {
+    let escrow = ESCROWS.load(&deps.storage, &offering_id)?;
+
+    if escrow.disputed {
+        return Err(ContractError::Disputed {});
+    }
+    if !escrow.expires.is_expired(&env.block) {
+        return Err(ContractError::DisputeWindowOpen {});
+    }
+
+    let (proceeds, fee_transfers, fee_attributes) = take_fees(deps, &escrow.payment, None)?;
+    let (message, transfer) = release_escrow(
+        deps.api,
+        &escrow,
+        (&escrow.buyer, &escrow.seller),
+        TransferKind::Proceeds,
+        proceeds,
+    )?;
+    let mut transfers = vec![transfer];
+    transfers.extend(fee_transfers);
+    record_trade(
+        &mut deps.storage,
+        &offering_id,
+        &escrow.seller,
+        &escrow.buyer,
+    )?;
+    record_price(
+        deps,
+        env.block.height,
+        &escrow.contract_addr,
+        (&escrow.seller, &escrow.buyer),
+        &Cw20CoinHuman {
+            address: escrow.payment.address.clone(),
+            amount: escrow.list_price,
+        },
+    )?;
+    ESCROWS.remove(&mut deps.storage, &offering_id);
+
+    let mut attributes = vec![attr("action", "finalize"), attr("offering_id", offering_id)];
+    attributes.extend(fee_attributes);
+
+    Ok(Payout {
+        response: HandleResponse {
+            messages: vec![message],
+            attributes,
+            data: None,
+        },
+        transfers,
+    })
+}
+
+/// release_escrow sends the escrowed token to the first and amount of the payment to the second
+/// of the recipients
+fn release_escrow<A: Api>(
+    api: A,
+    escrow: &Escrow,
+    (token_recipient, payment_recipient): (&CanonicalAddr, &CanonicalAddr),
+    kind: TransferKind,
+    amount: Uint128,
+) -> StdResult<(CosmosMsg, Transfer)> {
+    let cw721 = Cw721Contract(api.human_address(&escrow.contract_addr)?);
+    let transfer_nft = cw721.call(Cw721HandleMsg::TransferNft {
+        recipient: api.human_address(token_recipient)?,
+        token_id: escrow.token_id.clone(),
+    })?;
+
+    let transfer = Transfer {
+        kind,
+        token: escrow.payment.address.clone(),
+        recipient: api.human_address(payment_recipient)?,
+        amount,
+    };
+    Ok((transfer_nft, transfer))
+}
+
+pub fn try_receive_nft<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    rcv_msg: Cw721ReceiveMsg,
+) -> Result<HandleResponse, ContractError> {
+    let msg: SellNft = match rcv_msg.msg {
+        Some(bin) => Ok(from_binary(&bin)?),
+        None => Err(ContractError::NoData {}),
+    }?;
+
+    // anyone can call ReceiveNft, so make sure the sending contract really handed over the token
+    let owner = Cw721Contract(info.sender.clone()).owner_of(
+        &deps.querier,
+        rcv_msg.token_id.clone(),
+        false,
+    )?;
+    if owner.owner != env.contract.address {
+        return Err(ContractError::SpoofedListing {});
+    }
+
+    match msg.kind {
+        Some(OfferingKind::Rental { blocks }) | Some(OfferingKind::Escrow { blocks, .. })
+            if blocks == 0 =>
+        {
+            return Err(ContractError::InvalidTerms {});
+        }
+        // reject an arbiter that could never settle a dispute
+        Some(OfferingKind::Escrow { ref arbiter, .. }) => {
+            deps.api.canonical_address(arbiter)?;
+        }
+        Some(OfferingKind::Installment {
+            down_payment,
+            blocks,
+            forfeit_percent,
+        }) if down_payment > msg.list_price.amount || blocks == 0 || forfeit_percent > 100 => {
+            return Err(ContractError::InvalidTerms {});
+        }
+        Some(OfferingKind::Raffle {
+            tickets,
+            min_tickets,
+            end_height,
+            ref seed_commitment,
+        }) if msg.list_price.amount.is_zero()
+            || min_tickets > tickets
+            || tickets > MAX_RAFFLE_TICKETS
+            || end_height <= env.block.height
+            || seed_commitment.len() != 32 =>
+        {
+            return Err(ContractError::InvalidTerms {});
+        }
+        _ => {}
+    }
+
+    let config = CONFIG.may_load(&deps.storage)?.unwrap_or_default();
+    let seller = deps.api.canonical_address(&rcv_msg.sender)?;
+
+    // keep a single seller from flooding the marketplace
+    if let Some(max_listings) = config.max_listings {
+        let listings = offerings::<S>()
+            .idx
+            .seller
+            .pks(&deps.storage, &seller, None, None, Order::Ascending)
+            .count();
+        if listings >= max_listings as usize {
+            return Err(ContractError::TooManyListings {});
+        }
+    }
+
+    // lock the listing deposit, it is returned once the offering is sold or withdrawn
+    let mut messages = vec![];
+    match config.deposit {
+        Some(Deposit::Cw20(ref coin)) => {
+            messages.push(Cw20Contract(coin.address.clone()).call(
+                Cw20HandleMsg::TransferFrom {
+                    owner: rcv_msg.sender.clone(),
+                    recipient: env.contract.address.clone(),
+                    amount: coin.amount,
+                },
+            )?);
+        }
+        Some(Deposit::Native(ref coin)) => {
+            let balance = DEPOSIT_BALANCES
+                .may_load(&deps.storage, seller.as_slice())?
+                .unwrap_or_default();
+            if !balance.has(coin) {
+                return Err(ContractError::InsufficientFunds {});
+            }
+            let balance = (balance - coin.clone())?;
+            DEPOSIT_BALANCES.save(&mut deps.storage, seller.as_slice(), &balance)?;
+        }
+        None => {}
+    }
+
+    // cache how to display prices in this token, queries can't write it themselves
+    let price_token = deps.api.canonical_address(&msg.list_price.address)?;
+    if TOKEN_DISPLAY
+        .may_load(&deps.storage, price_token.as_slice())?
+        .is_none()
+    {
+        // not every price token has to answer TokenInfo, its prices are just shown raw then
+        let price_contract = Cw20Contract(msg.list_price.address.clone());
+        if let Ok(info) = price_contract.meta(&deps.querier) {
+            let display = TokenDisplay {
+                symbol: info.symbol,
+                decimals: info.decimals,
+            };
+            TOKEN_DISPLAY.save(&mut deps.storage, price_token.as_slice(), &display)?;
+        }
+    }
+
+    // snapshot what the token looks like, listing works without it as well
+    let metadata = fetch_metadata(&deps.querier, info.sender.clone(), &rcv_msg.token_id).ok();
+
+    // check if same token Id form same original contract is already on sale
+    // get OFFERING_COUNT
+    let id = increment_offerings(&mut deps.storage)?.to_string();
+
+    // save Offering
+    let off = Offering {
+        contract_addr: deps.api.canonical_address(&info.sender)?,
+        token_id: rcv_msg.token_id,
+        seller,
+        list_price: msg.list_price.clone(),
+        kind: msg.kind,
+        deposit: config.deposit,
+        listed_height: env.block.height,
+        metadata,
+    };
+
+    offerings().save(&mut deps.storage, &id, &off)?;
+
+    // notify hooks
+    let hook_msg = MarketplaceHookMsg::Listed {
+        offering: humanize_offering(deps.api, &id, off.clone())?,
+    };
+    messages.extend(prepare_hooks(deps, hook_msg)?);
+
+    Ok(HandleResponse {
+        messages,
+        attributes: vec![
+            attr("action", "sell_nft"),
+            attr("offering_id", id.clone()),
+            attr("contract_addr", info.sender),
+            attr("seller", rcv_msg.sender),
+            attr("amount", msg.list_price.amount),
+            attr("denom", msg.list_price.address),
+            attr("token_id", off.token_id),
+        ],
+        data: Some(to_binary(&SellResponse { offering_id: id })?),
+    })
+}
+
+pub fn try_withdraw<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    offering_id: String,
+) -> Result<Payout, ContractError> {
+    // check if token_id is currently sold by the requesting address
+    let off = OFFERINGS.load(&deps.storage, &offering_id)?;
+    if off.seller == deps.api.canonical_address(&info.sender)? {
+        let mut transfers = release_reservations(deps, &env, &offering_id, &off)?;
+
+        // transfer token back to original owner
+        let transfer_cw721_msg = Cw721HandleMsg::TransferNft {
+            recipient: deps.api.human_address(&off.seller)?,
+            token_id: off.token_id.clone(),
+        };
+
+        let exec_cw721_transfer = WasmMsg::Execute {
+            contract_addr: deps.api.human_address(&off.contract_addr)?,
+            msg: to_binary(&transfer_cw721_msg)?,
+            send: vec![],
+        };
+
+        let mut messages: Vec<CosmosMsg> = vec![exec_cw721_transfer.into()];
+
+        // notify hooks
+        let hook_msg = MarketplaceHookMsg::Withdrawn {
+            offering: humanize_offering(deps.api, &offering_id, off.clone())?,
+        };
+        messages.extend(prepare_hooks(deps, hook_msg)?);
+
+        // remove offering
+        transfers.extend(remove_offering(deps, &offering_id, &off, &off.seller)?);
+
+        return Ok(Payout {
+            response: HandleResponse {
+                messages,
+                attributes: vec![
+                    attr("action", "withdraw_nft"),
+                    attr("seller", info.sender),
+                    attr("offering_id", offering_id),
+                ],
+                data: None,
+            },
+            transfers,
+        });
+    }
+    Err(ContractError::Unauthorized {})
+}
+
+/// release_reservations makes sure nobody but the seller has a claim on the listed token
+/// anymore, settling expired rentals and defaulted installments on the way
+fn release_reservations<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    offering_id: &str,
+    off: &Offering,
+) -> Result<Vec<Transfer>, ContractError> {
+    // a rented out token can only be reclaimed once the rental expired
+    if let Some(rental) = rentals::<S>().may_load(&deps.storage, offering_id)? {
+        if !rental.expires.is_expired(&env.block) {
+            return Err(ContractError::Rented {});
+        }
+        rentals::<S>().remove(&mut deps.storage, offering_id)?;
+    }
+
+    // a raffle with tickets sold has to be drawn or cancelled
+    if RAFFLES.may_load(&deps.storage, offering_id)?.is_some() {
+        return Err(ContractError::TicketsSold {});
+    }
+
+    // a reserved token can only be reclaimed once the buyer defaulted
+    if let Some(installment) = installments::<S>().may_load(&deps.storage, offering_id)? {
+        if !installment.deadline.is_expired(&env.block) {
+            return Err(ContractError::Reserved {});
+        }
+        if let Some(OfferingKind::Installment {
+            down_payment,
+            forfeit_percent,
+            ..
+        }) = off.kind
+        {
+            let settled = settle_default(
+                deps,
+                offering_id,
+                off,
+                installment,
+                down_payment,
+                forfeit_percent,
+            )?;
+            return Ok(settled);
+        }
+    }
+    Ok(vec![])
+}
+
+/// remove_offering deletes the offering and hands its listing deposit to deposit_recipient,
+/// which is the seller unless the offering was delisted as spam
+fn remove_offering<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    offering_id: &str,
+    off: &Offering,
+    deposit_recipient: &CanonicalAddr,
+) -> StdResult<Option<Transfer>> {
+    offerings::<S>().remove(&mut deps.storage, offering_id)?;
+
+    // nobody can watch a removed offering anymore
+    let watch_keys: Vec<Vec<u8>> = watches::<S>()
+        .idx
+        .offering
+        .pks(
+            &deps.storage,
+            offering_id.as_bytes(),
+            None,
+            None,
+            Order::Ascending,
+        )
+        .collect();
+    for key in watch_keys {
+        watches::<S>().remove(&mut deps.storage, &key)?;
+    }
+
+    match off.deposit {
+        Some(Deposit::Cw20(ref coin)) => Ok(Some(Transfer {
+            kind: TransferKind::Refund,
+            token: coin.address.clone(),
+            recipient: deps.api.human_address(deposit_recipient)?,
+            amount: coin.amount,
+        })),
+        // native deposits are credited to the recipient's deposit balance
+        Some(Deposit::Native(ref coin)) => {
+            let key = deposit_recipient.as_slice();
+            let mut balance = DEPOSIT_BALANCES
+                .may_load(&deps.storage, key)?
+                .unwrap_or_default();
+            balance += coin.clone();
+            DEPOSIT_BALANCES.save(&mut deps.storage, key, &balance)?;
+            Ok(None)
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn try_delist_spam<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    offering_id: String,
+) -> Result<Payout, ContractError> {
+    assert_admin(deps, &info)?;
+
+    let off = OFFERINGS.load(&deps.storage, &offering_id)?;
+    let mut transfers = release_reservations(deps, &env, &offering_id, &off)?;
+
+    // the token goes back to the seller, the deposit is forfeited to the admin
+    let mut messages = vec![
+        Cw721Contract(deps.api.human_address(&off.contract_addr)?).call(
+            Cw721HandleMsg::TransferNft {
+                recipient: deps.api.human_address(&off.seller)?,
+                token_id: off.token_id.clone(),
+            },
+        )?,
+    ];
+
+    // notify hooks
+    let hook_msg = MarketplaceHookMsg::Withdrawn {
+        offering: humanize_offering(deps.api, &offering_id, off.clone())?,
+    };
+    messages.extend(prepare_hooks(deps, hook_msg)?);
+
+    let admin = ADMIN.load(&deps.storage)?;
+    transfers.extend(remove_offering(deps, &offering_id, &off, &admin)?);
+
+    Ok(Payout {
+        response: HandleResponse {
+            messages,
+            attributes: vec![
+                attr("action", "delist_spam"),
+                attr("offering_id", offering_id),
+            ],
+            data: None,
+        },
+        transfers,
+    })
+}
+
+/// try_update_config patches the config, unset fields are kept and null ones cleared
+pub fn try_update_config<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    info: MessageInfo,
+    max_listings: Option<Option<u32>>,
+    deposit: Option<Option<Deposit>>,
+    protocol_fee: Option<Option<Decimal>>,
+    referral_share: Option<Option<Decimal>>,
+) -> Result<HandleResponse, ContractError> {
+    assert_admin(deps, &info)?;
+
+    let mut config = CONFIG.may_load(&deps.storage)?.unwrap_or_default();
+    if let Some(max_listings) = max_listings {
+        config.max_listings = max_listings;
+    }
+    if let Some(deposit) = deposit {
+        config.deposit = deposit;
+    }
+    if let Some(protocol_fee) = protocol_fee {
+        config.protocol_fee = protocol_fee;
+    }
+    if let Some(referral_share) = referral_share {
+        config.referral_share = referral_share;
+    }
+
+    if let Some(Deposit::Cw20(ref coin)) = config.deposit {
+        deps.api.canonical_address(&coin.address)?;
+    }
+    let shares = [config.protocol_fee, config.referral_share];
+    if shares.iter().flatten().any(|share| *share > Decimal::one()) {
+        return Err(ContractError::InvalidTerms {});
+    }
+    CONFIG.save(&mut deps.storage, &config)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![attr("action", "update_config")],
+        data: None,
+    })
+}
+
+pub fn try_top_up_deposits<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    info: MessageInfo,
+) -> Result<HandleResponse, ContractError> {
+    if info.sent_funds.is_empty() {
+        return Err(ContractError::InsufficientFunds {});
+    }
+
+    let key = deps.api.canonical_address(&info.sender)?;
+    let mut balance = DEPOSIT_BALANCES
+        .may_load(&deps.storage, key.as_slice())?
+        .unwrap_or_default();
+    balance += NativeBalance(info.sent_funds);
+    balance.normalize();
+    DEPOSIT_BALANCES.save(&mut deps.storage, key.as_slice(), &balance)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "top_up_deposits"),
+            attr("sender", info.sender),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_withdraw_deposits<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+) -> Result<HandleResponse, ContractError> {
+    let key = deps.api.canonical_address(&info.sender)?;
+    let balance = DEPOSIT_BALANCES
+        .may_load(&deps.storage, key.as_slice())?
+        .unwrap_or_default();
+    if balance.is_empty() {
+        return Err(ContractError::InsufficientFunds {});
+    }
+    DEPOSIT_BALANCES.remove(&mut deps.storage, key.as_slice());
+
+    let send = BankMsg::Send {
+        from_address: env.contract.address,
+        to_address: info.sender.clone(),
+        amount: balance.into_vec(),
+    };
+
+    Ok(HandleResponse {
+        messages: vec![send.into()],
+        attributes: vec![
+            attr("action", "withdraw_deposits"),
+            attr("sender", info.sender),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_watch<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    info: MessageInfo,
+    offering_id: String,
+) -> Result<HandleResponse, ContractError> {
+    // only active offerings can be watched
+    OFFERINGS.load(&deps.storage, &offering_id)?;
+
+    let watch = Watch {
+        offering_id: offering_id.clone(),
+        watcher: deps.api.canonical_address(&info.sender)?,
+    };
+    let key = watch_key(&watch.watcher, &offering_id);
+    watches().save(&mut deps.storage, &key, &watch)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "watch"),
+            attr("offering_id", offering_id),
+            attr("watcher", info.sender),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_unwatch<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    info: MessageInfo,
+    offering_id: String,
+) -> Result<HandleResponse, ContractError> {
+    let watcher = deps.api.canonical_address(&info.sender)?;
+    watches::<S>().remove(&mut deps.storage, &watch_key(&watcher, &offering_id))?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "unwatch"),
+            attr("offering_id", offering_id),
+            attr("watcher", info.sender),
+        ],
+        data: None,
+    })
+}
+
+/// record_trade counts a completed purchase and lets the buyer rate the seller for it
+fn record_trade<S: Storage>(
+    storage: &mut S,
+    offering_id: &str,
+    seller: &CanonicalAddr,
+    buyer: &CanonicalAddr,
+) -> StdResult<()> {
+    // buying from yourself is no trade to build a reputation on
+    if seller == buyer {
+        return Ok(());
+    }
+
+    let mut reputation = REPUTATIONS
+        .may_load(storage, seller.as_slice())?
+        .unwrap_or_default();
+    reputation.sales += 1;
+    REPUTATIONS.save(storage, seller.as_slice(), &reputation)?;
+
+    let mut reputation = REPUTATIONS
+        .may_load(storage, buyer.as_slice())?
+        .unwrap_or_default();
+    reputation.purchases += 1;
+    REPUTATIONS.save(storage, buyer.as_slice(), &reputation)?;
+
+    let trade = Trade {
+        seller: seller.clone(),
+        buyer: buyer.clone(),
+    };
+    UNRATED_TRADES.save(storage, offering_id, &trade)
+}
+
+/// record_price adds a sale at its list price to the rolling price window of its collection
+/// and currency, so overpaying does not move the price. Raffles are left out, their ticket
+/// revenue says little about the value of the token, and so are trades with oneself
+fn record_price<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    height: u64,
+    contract_addr: &CanonicalAddr,
+    (seller, buyer): (&CanonicalAddr, &CanonicalAddr),
+    price: &Cw20CoinHuman,
+) -> StdResult<()> {
+    if seller == buyer {
+        return Ok(());
+    }
+    let currency = deps.api.canonical_address(&price.address)?;
+    let key = (contract_addr.as_slice(), currency.as_slice());
+    let mut window = PRICE_WINDOWS
+        .may_load(&deps.storage, key)?
+        .unwrap_or_default();
+    window.push(PricePoint {
+        price: price.amount,
+        height,
+    });
+    if window.len() > PRICE_WINDOW_SIZE {
+        window.remove(0);
+    }
+    PRICE_WINDOWS.save(&mut deps.storage, key, &window)
+}
+
+pub fn try_rate_seller<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    info: MessageInfo,
+    offering_id: String,
+    positive: bool,
+) -> Result<HandleResponse, ContractError> {
+    let trade = UNRATED_TRADES
+        .may_load(&deps.storage, &offering_id)?
+        .ok_or(ContractError::NothingToRate {})?;
+
+    // only the buyer rates, and not their own listing
+    let sender = deps.api.canonical_address(&info.sender)?;
+    if trade.buyer != sender || trade.seller == sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut reputation = REPUTATIONS
+        .may_load(&deps.storage, trade.seller.as_slice())?
+        .unwrap_or_default();
+    if positive {
+        reputation.positive += 1;
+    } else {
+        reputation.negative += 1;
+    }
+    REPUTATIONS.save(&mut deps.storage, trade.seller.as_slice(), &reputation)?;
+    UNRATED_TRADES.remove(&mut deps.storage, &offering_id);
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "rate_seller"),
+            attr("offering_id", offering_id),
+            attr("seller", deps.api.human_address(&trade.seller)?),
+            attr("positive", positive),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_refresh_metadata<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    offering_id: String,
+) -> Result<HandleResponse, ContractError> {
+    let mut off = OFFERINGS.load(&deps.storage, &offering_id)?;
+    let contract_addr = deps.api.human_address(&off.contract_addr)?;
+    off.metadata = Some(fetch_metadata(&deps.querier, contract_addr, &off.token_id)?);
+    offerings().save(&mut deps.storage, &offering_id, &off)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "refresh_metadata"),
+            attr("offering_id", offering_id),
+        ],
+        data: None,
+    })
+}
+
+/// fetch_metadata queries NftInfo of a token. Contracts other than Cosmons don't report a level
+fn fetch_metadata<Q: Querier>(
+    querier: &Q,
+    contract_addr: HumanAddr,
+    token_id: &str,
+) -> StdResult<NftMetadata> {
+    let msg = Cw721QueryMsg::NftInfo {
+        token_id: token_id.to_string(),
+    };
+    querier.query(
+        &WasmQuery::Smart {
+            contract_addr,
+            msg: to_binary(&msg)?,
+        }
+        .into(),
+    )
+}
+
+pub fn try_withdraw_referral_earnings<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    info: MessageInfo,
+) -> Result<HandleResponse, ContractError> {
+    let key = deps.api.canonical_address(&info.sender)?;
+    let earnings = REFERRAL_EARNINGS
+        .may_load(&deps.storage, key.as_slice())?
+        .unwrap_or_default();
+    if earnings.is_empty() {
+        return Err(ContractError::InsufficientFunds {});
+    }
+    REFERRAL_EARNINGS.remove(&mut deps.storage, key.as_slice());
+
+    let messages = earnings
+        .into_iter()
+        .map(|coin| {
+            Cw20Contract(coin.address).call(Cw20HandleMsg::Transfer {
+                recipient: info.sender.clone(),
+                amount: coin.amount,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(HandleResponse {
+        messages,
+        attributes: vec![
+            attr("action", "withdraw_referral_earnings"),
+            attr("referrer", info.sender),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_add_hook<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    info: MessageInfo,
+    addr: HumanAddr,
+) -> Result<HandleResponse, ContractError> {
+    assert_admin(deps, &info)?;
+
+    let addr_raw = deps.api.canonical_address(&addr)?;
+    let mut hooks = HOOKS.may_load(&deps.storage)?.unwrap_or_default();
+    if hooks.contains(&addr_raw) {
+        return Err(ContractError::HookAlreadyRegistered {});
+    }
+    hooks.push(addr_raw);
+    HOOKS.save(&mut deps.storage, &hooks)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "add_hook"),
+            attr("sender", info.sender),
+            attr("hook", addr),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_remove_hook<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    info: MessageInfo,
+    addr: HumanAddr,
+) -> Result<HandleResponse, ContractError> {
+    assert_admin(deps, &info)?;
+
+    let addr_raw = deps.api.canonical_address(&addr)?;
+    let mut hooks = HOOKS.may_load(&deps.storage)?.unwrap_or_default();
+    if !hooks.contains(&addr_raw) {
+        return Err(ContractError::HookNotRegistered {});
+    }
+    hooks.retain(|hook| hook != &addr_raw);
+    HOOKS.save(&mut deps.storage, &hooks)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "remove_hook"),
+            attr("sender", info.sender),
+            attr("hook", addr),
+        ],
+        data: None,
+    })
+}
+
+/// returns an error unless the sender is the admin of the marketplace
+fn assert_admin<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    info: &MessageInfo,
+) -> Result<(), ContractError> {
+    let admin = ADMIN.load(&deps.storage)?;
+    if deps.api.canonical_address(&info.sender)? != admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+/// prepare_hooks creates a message sending msg to each registered hook
+fn prepare_hooks<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: MarketplaceHookMsg,
+) -> StdResult<Vec<CosmosMsg>> {
+    HOOKS
+        .may_load(&deps.storage)?
+        .unwrap_or_default()
+        .iter()
+        .map(|hook| {
+            let addr = deps.api.human_address(hook)?;
+            msg.clone().into_cosmos_msg(addr)
+        })
+        .collect()
+}
+
+pub fn query<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    msg: QueryMsg,
+) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::GetOfferings {
+            resolve_prices,
+            min_reputation,
+            start_after,
+            limit,
+        } => to_binary(&query_offerings(
+            deps,
+            resolve_prices.unwrap_or(false),
+            min_reputation,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::Offering { offering_id } => to_binary(&query_offering(deps, offering_id)?),
+        QueryMsg::SimulateBuy {
+            offering_id,
+            buyer,
+            amount,
+            currency,
+            referrer,
+        } => to_binary(&query_simulate_buy(
+            deps,
+            env,
+            offering_id,
+            buyer,
+            amount,
+            currency,
+            referrer,
+        )?),
+        QueryMsg::Watchlist {
+            watcher,
+            start_after,
+            limit,
+        } => to_binary(&query_watchlist(deps, watcher, start_after, limit)?),
+        QueryMsg::Reputation { address } => to_binary(&query_reputation(deps, address)?),
+        QueryMsg::FloorPrice {
+            contract_addr,
+            currency,
+        } => to_binary(&query_price(deps, contract_addr, currency, floor_price)?),
+        QueryMsg::MedianPrice {
+            contract_addr,
+            currency,
+        } => to_binary(&query_price(deps, contract_addr, currency, median_price)?),
+        QueryMsg::AveragePrice {
+            contract_addr,
+            currency,
+        } => {
+            let height = env.block.height;
+            let average = |window: &[PricePoint]| average_price(window, height);
+            to_binary(&query_price(deps, contract_addr, currency, average)?)
+        }
+        QueryMsg::RentalsByRenter {
+            renter,
+            start_after,
+            limit,
+        } => to_binary(&query_rentals_by_renter(
+            deps,
+            env,
+            renter,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::RentalsByOwner {
+            owner,
+            start_after,
+            limit,
+        } => to_binary(&query_rentals_by_owner(
+            deps,
+            env,
+            owner,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::UserOf {
+            contract_addr,
+            token_id,
+        } => to_binary(&query_user_of(deps, env, contract_addr, token_id)?),
+        QueryMsg::Escrow { offering_id } => to_binary(&query_escrow(deps, offering_id)?),
+        QueryMsg::Hooks {} => to_binary(&query_hooks(deps)?),
+        QueryMsg::Installment { offering_id } => to_binary(&query_installment(deps, offering_id)?),
+        QueryMsg::Raffle { offering_id } => to_binary(&query_raffle(deps, offering_id)?),
+        QueryMsg::Config {} => to_binary(&query_config(deps)?),
+        QueryMsg::ReferralEarnings { referrer } => {
+            to_binary(&query_referral_earnings(deps, referrer)?)
+        }
+        QueryMsg::DepositBalance { address } => to_binary(&query_deposit_balance(deps, address)?),
+        QueryMsg::InstallmentsByBuyer {
+            buyer,
+            start_after,
+            limit,
+        } => to_binary(&query_installments_by_buyer(
+            deps,
+            buyer,
+            start_after,
+            limit,
+        )?),
+    }
+}
+
+// ============================== Query Handlers ==============================
+
+fn query_offerings<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    resolve_prices: bool,
+    min_reputation: Option<i64>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<OfferingsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let mut res: Vec<QueryOfferingsResult> = vec![];
+    // sellers usually have several offerings, so each score is only loaded once
+    let mut scores: BTreeMap<Vec<u8>, i64> = BTreeMap::new();
+    for item in OFFERINGS.range(&deps.storage, start, None, Order::Ascending) {
+        if res.len() == limit {
+            break;
+        }
+        let (k, offering) = item?;
+        if let Some(min_reputation) = min_reputation {
+            let score = match scores.get(offering.seller.as_slice()) {
+                Some(score) => *score,
+                None => {
+                    let score = REPUTATIONS
+                        .may_load(&deps.storage, offering.seller.as_slice())?
+                        .unwrap_or_default()
+                        .score();
+                    scores.insert(offering.seller.to_vec(), score);
+                    score
+                }
+            };
+            if score < min_reputation {
+                continue;
+            }
+        }
+        res.push(parse_offering(deps.api, Ok((k, offering)))?);
+    }
+
+    if resolve_prices {
+        for offering in res.iter_mut() {
+            offering.display_price = token_display(deps, &offering.list_price.address)?
+                .map(|display| format_price(offering.list_price.amount, &display));
+        }
+    }
+
+    Ok(OfferingsResponse { offerings: res })
+}
+
+/// query_price applies the given valuation to the recent sales of a collection in a currency
+fn query_price<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    contract_addr: HumanAddr,
+    currency: HumanAddr,
+    valuation: impl Fn(&[PricePoint]) -> StdResult<Uint128>,
+) -> StdResult<PriceResponse> {
+    let collection = deps.api.canonical_address(&contract_addr)?;
+    let currency_raw = deps.api.canonical_address(&currency)?;
+    let window = PRICE_WINDOWS
+        .may_load(
+            &deps.storage,
+            (collection.as_slice(), currency_raw.as_slice()),
+        )?
+        .unwrap_or_default();
+    let price = if window.is_empty() {
+        None
+    } else {
+        Some(valuation(&window)?)
+    };
+    Ok(PriceResponse {
+        contract_addr,
+        currency,
+        price,
+        sales: window.len() as u32,
+    })
+}
+
+fn floor_price(window: &[PricePoint]) -> StdResult<Uint128> {
+    Ok(window
+        .iter()
+        .map(|point| point.price)
+        .min()
+        .unwrap_or_default())
+}
+
+/// median_price is the middle of the sorted prices, or the mean of the two middle ones
+fn median_price(window: &[PricePoint]) -> StdResult<Uint128> {
+    let mut prices: Vec<u128> = window.iter().map(|point| point.price.u128()).collect();
+    prices.sort_unstable();
+    let mid = prices.len() / 2;
+    if prices.len() % 2 == 1 {
+        Ok(Uint128(prices[mid]))
+    } else {
+        Ok(Uint128(
+            prices[mid - 1] + (prices[mid] - prices[mid - 1]) / 2,
+        ))
+    }
+}
+
+/// average_price weighs each sale by the blocks it was the latest price for, up to the
+/// current height. Every sale counts for at least one and at most MAX_PRICE_WEIGHT blocks
+fn average_price(window: &[PricePoint], height: u64) -> StdResult<Uint128> {
+    let overflow = || StdError::generic_err("Price overflow");
+    let mut weighted = 0u128;
+    let mut blocks = 0u128;
+    for (i, point) in window.iter().enumerate() {
+        let until = window.get(i + 1).map_or(height, |next| next.height);
+        let weight = until
+            .saturating_sub(point.height)
+            .clamp(1, MAX_PRICE_WEIGHT) as u128;
+        weighted = point
+            .price
+            .u128()
+            .checked_mul(weight)
+            .and_then(|value| weighted.checked_add(value))
+            .ok_or_else(overflow)?;
+        blocks += weight;
+    }
+    Ok(Uint128(weighted / blocks))
+}
+
+fn query_reputation<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+) -> StdResult<ReputationResponse> {
+    let key = deps.api.canonical_address(&address)?;
+    let reputation = REPUTATIONS
+        .may_load(&deps.storage, key.as_slice())?
+        .unwrap_or_default();
+    Ok(ReputationResponse {
+        address,
+        positive: reputation.positive,
+        negative: reputation.negative,
+        score: reputation.score(),
+        sales: reputation.sales,
+        purchases: reputation.purchases,
+    })
+}
+
+fn query_offering<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    offering_id: String,
+) -> StdResult<OfferingResponse> {
+    let off = OFFERINGS.load(&deps.storage, &offering_id)?;
+    let seller_listings = offerings::<S>()
+        .idx
+        .seller
+        .pks(&deps.storage, &off.seller, None, None, Order::Ascending)
+        .filter(|pk| pk.as_slice() != offering_id.as_bytes())
+        .count();
+    let watchers = watches::<S>()
+        .idx
+        .offering
+        .pks(
+            &deps.storage,
+            offering_id.as_bytes(),
+            None,
+            None,
+            Order::Ascending,
+        )
+        .count();
+
+    Ok(OfferingResponse {
+        listed_height: off.listed_height,
+        offering: humanize_offering(deps.api, &offering_id, off)?,
+        seller_listings: seller_listings as u32,
+        watchers: watchers as u32,
+    })
+}
+
+fn query_watchlist<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    watcher: HumanAddr,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<OfferingsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let watcher_raw = deps.api.canonical_address(&watcher)?;
+    let start = start_after.map(|id| Bound::exclusive(watch_key(&watcher_raw, &id)));
+
+    let mut res = vec![];
+    for item in
+        watches::<S>()
+            .idx
+            .watcher
+            .items(&deps.storage, &watcher_raw, start, None, Order::Ascending)
+    {
+        let (_, watch) = item?;
+        let off = OFFERINGS.load(&deps.storage, &watch.offering_id)?;
+        res.push(humanize_offering(deps.api, &watch.offering_id, off)?);
+        if res.len() == limit {
+            break;
+        }
+    }
+    Ok(OfferingsResponse { offerings: res })
+}
+
+/// query_simulate_buy runs the purchase handler on a copy-on-write view of the storage and
+/// reports the transfers it pays out, nothing it writes is persisted
+fn query_simulate_buy<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    offering_id: String,
+    buyer: HumanAddr,
+    amount: Uint128,
+    currency: HumanAddr,
+    referrer: Option<HumanAddr>,
+) -> StdResult<SimulateBuyResponse> {
+    let mut res = SimulateBuyResponse {
+        offering_id: offering_id.clone(),
+        transfers: vec![],
+        referral_fee: Uint128::zero(),
+        nft_recipient: None,
+        error: None,
+    };
+
+    let info = MessageInfo {
+        sender: currency.clone(),
+        sent_funds: vec![],
+    };
+    let rcv_msg = Cw20ReceiveMsg {
+        sender: buyer.clone(),
+        amount,
+        msg: Some(to_binary(&BuyNft {
+            offering_id,
+            referrer: referrer.clone(),
+        })?),
+    };
+    let mut simulation = simulation_deps(deps);
+    let payout = match try_receive(&mut simulation, env, info, rcv_msg) {
+        Ok(payout) => payout,
+        Err(err) => {
+            res.error = Some(err.to_string());
+            return Ok(res);
+        }
+    };
+
+    for msg in payout.response.messages {
+        if let CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) = msg {
+            if let Ok(Cw721HandleMsg::TransferNft { recipient, .. }) = from_binary(&msg) {
+                res.nft_recipient = Some(recipient);
+            }
+        }
+    }
+    res.transfers = payout
+        .transfers
+        .into_iter()
+        .filter(|transfer| transfer.token == currency)
+        .map(|transfer| SimulatedTransfer {
+            kind: transfer.kind,
+            recipient: transfer.recipient,
+            amount: transfer.amount,
+        })
+        .collect();
+
+    // the referral fee isn't transferred but added to the referrer's earnings
+    if let Some(referrer) = referrer {
+        let key = deps.api.canonical_address(&referrer)?;
+        let before = referral_earnings(&deps.storage, &key, &currency)?;
+        let after = referral_earnings(&simulation.storage, &key, &currency)?;
+        res.referral_fee = (after - before)?;
+    }
+    Ok(res)
+}
+
+/// referral_earnings is what a referrer earned in the given token and didn't withdraw yet
+fn referral_earnings<S: Storage>(
+    storage: &S,
+    referrer: &CanonicalAddr,
+    token: &HumanAddr,
+) -> StdResult<Uint128> {
+    let earnings = REFERRAL_EARNINGS
+        .may_load(storage, referrer.as_slice())?
+        .unwrap_or_default();
+    Ok(earnings
+        .into_iter()
+        .find(|coin| coin.address == *token)
+        .map(|coin| coin.amount)
+        .unwrap_or_default())
+}
+
+/// token_display looks up the symbol and decimals of a cw20 token, falling back to querying
+/// tokens which are not cached yet. None if the contract does not answer TokenInfo
+fn token_display<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    token: &HumanAddr,
+) -> StdResult<Option<TokenDisplay>> {
+    let key = deps.api.canonical_address(token)?;
+    if let Some(display) = TOKEN_DISPLAY.may_load(&deps.storage, key.as_slice())? {
+        return Ok(Some(display));
+    }
+    let display = Cw20Contract(token.clone())
+        .meta(&deps.querier)
+        .ok()
+        .map(|info| TokenDisplay {
+            symbol: info.symbol,
+            decimals: info.decimals,
+        });
+    Ok(display)
+}
+
+/// format_price shifts the raw amount by the token decimals and appends the symbol
+fn format_price(amount: Uint128, display: &TokenDisplay) -> String {
+    let decimals = display.decimals as usize;
+    let digits = format!("{:0>width$}", amount.u128(), width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{} {}", whole, display.symbol)
+    } else {
+        format!("{}.{} {}", whole, fraction, display.symbol)
+    }
+}
+
+fn parse_offering<A: Api>(
+    api: A,
+    item: StdResult<KV<Offering>>,
+) -> StdResult<QueryOfferingsResult> {
+    item.and_then(|(k, offering)| {
+        let id = from_utf8(&k)?;
+        humanize_offering(api, id, offering)
+    })
+}
+
+fn humanize_offering<A: Api>(
+    api: A,
+    id: &str,
+    offering: Offering,
+) -> StdResult<QueryOfferingsResult> {
+    Ok(QueryOfferingsResult {
+        id: id.to_string(),
+        token_id: offering.token_id,
+        list_price: offering.list_price,
+        contract_addr: api.human_address(&offering.contract_addr)?,
+        seller: api.human_address(&offering.seller)?,
+        kind: offering.kind,
+        display_price: None,
+        metadata: offering.metadata,
+    })
+}
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+fn query_rentals_by_renter<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    renter: HumanAddr,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<RentalsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let renter_raw = deps.api.canonical_address(&renter)?;
+    let res: StdResult<Vec<_>> = rentals::<S>()
+        .idx
+        .user
+        .items(&deps.storage, &renter_raw, start, None, Order::Ascending)
+        .filter(|r| r.is_err() || !r.as_ref().unwrap().1.expires.is_expired(&env.block))
+        .take(limit)
+        .map(|item| parse_rental(deps.api, item))
+        .collect();
+    Ok(RentalsResponse { rentals: res? })
+}
+
+fn query_rentals_by_owner<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    owner: HumanAddr,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<RentalsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let owner_raw = deps.api.canonical_address(&owner)?;
+    let res: StdResult<Vec<_>> = rentals::<S>()
+        .idx
+        .owner
+        .items(&deps.storage, &owner_raw, start, None, Order::Ascending)
+        .filter(|r| r.is_err() || !r.as_ref().unwrap().1.expires.is_expired(&env.block))
+        .take(limit)
+        .map(|item| parse_rental(deps.api, item))
+        .collect();
+    Ok(RentalsResponse { rentals: res? })
+}
+
+fn query_user_of<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    contract_addr: HumanAddr,
+    token_id: String,
+) -> StdResult<UserOfResponse> {
+    let contract_raw = deps.api.canonical_address(&contract_addr)?;
+    let rental = rentals::<S>()
+        .idx
+        .token
+        .item(&deps.storage, &token_key(&contract_raw, &token_id))?
+        .map(|(_, rental)| rental)
+        .filter(|rental| !rental.expires.is_expired(&env.block));
+
+    match rental {
+        Some(rental) => Ok(UserOfResponse {
+            user: Some(deps.api.human_address(&rental.user)?),
+            expires: Some(rental.expires),
+        }),
+        None => Ok(UserOfResponse {
+            user: None,
+            expires: None,
+        }),
+    }
+}
+
+fn query_escrow<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    offering_id: String,
+) -> StdResult<EscrowResponse> {
+    let escrow = ESCROWS.load(&deps.storage, &offering_id)?;
+    Ok(EscrowResponse {
+        offering_id,
+        token_id: escrow.token_id,
+        contract_addr: deps.api.human_address(&escrow.contract_addr)?,
+        seller: deps.api.human_address(&escrow.seller)?,
+        buyer: deps.api.human_address(&escrow.buyer)?,
+        arbiter: deps.api.human_address(&escrow.arbiter)?,
+        payment: escrow.payment,
+        expires: escrow.expires,
+        disputed: escrow.disputed,
+    })
+}
+
+fn query_hooks<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<HooksResponse> {
+    let hooks: StdResult<Vec<_>> = HOOKS
+        .may_load(&deps.storage)?
+        .unwrap_or_default()
+        .iter()
+        .map(|hook| deps.api.human_address(hook))
+        .collect();
+    Ok(HooksResponse { hooks: hooks? })
+}
+
+fn query_installment<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    offering_id: String,
+) -> StdResult<InstallmentResponse> {
+    let installment = installments::<S>().load(&deps.storage, &offering_id)?;
+    humanize_installment(deps, offering_id, installment)
+}
+
+fn query_installments_by_buyer<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    buyer: HumanAddr,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<InstallmentsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let buyer_raw = deps.api.canonical_address(&buyer)?;
+    let res: StdResult<Vec<_>> = installments::<S>()
+        .idx
+        .buyer
+        .items(&deps.storage, &buyer_raw, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.and_then(|(k, installment)| {
+                let offering_id = from_utf8(&k)?.to_string();
+                humanize_installment(deps, offering_id, installment)
+            })
+        })
+        .collect();
+    Ok(InstallmentsResponse { installments: res? })
+}
+
+fn humanize_installment<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    offering_id: String,
+    installment: Installment,
+) -> StdResult<InstallmentResponse> {
+    let off = OFFERINGS.load(&deps.storage, &offering_id)?;
+    let outstanding = (off.list_price.amount - installment.paid)?;
+    Ok(InstallmentResponse {
+        offering_id,
+        buyer: deps.api.human_address(&installment.buyer)?,
+        paid: installment.paid,
+        outstanding,
+        deadline: installment.deadline,
+    })
+}
+
+fn query_raffle<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    offering_id: String,
+) -> StdResult<RaffleResponse> {
+    let off = OFFERINGS.load(&deps.storage, &offering_id)?;
+    let (tickets, min_tickets, end_height) = match off.kind {
+        Some(OfferingKind::Raffle {
+            tickets,
+            min_tickets,
+            end_height,
+            ..
+        }) => (tickets, min_tickets, end_height),
+        _ => return Err(StdError::generic_err("Offering is not a raffle")),
+    };
+    let raffle = RAFFLES
+        .may_load(&deps.storage, &offering_id)?
+        .unwrap_or_default();
+    Ok(RaffleResponse {
+        offering_id,
+        tickets_sold: raffle.sold,
+        tickets,
+        min_tickets,
+        end_height,
+        seed_revealed: raffle.seed.is_some(),
+    })
+}
+
+fn query_config<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<ConfigResponse> {
+    let config = CONFIG.may_load(&deps.storage)?.unwrap_or_default();
+    Ok(ConfigResponse {
+        max_listings: config.max_listings,
+        deposit: config.deposit,
+        protocol_fee: config.protocol_fee,
+        referral_share: config.referral_share,
+    })
+}
+
+fn query_referral_earnings<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    referrer: HumanAddr,
+) -> StdResult<ReferralEarningsResponse> {
+    let key = deps.api.canonical_address(&referrer)?;
+    let earnings = REFERRAL_EARNINGS
+        .may_load(&deps.storage, key.as_slice())?
+        .unwrap_or_default();
+    Ok(ReferralEarningsResponse { earnings })
+}
+
+fn query_deposit_balance<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+) -> StdResult<DepositBalanceResponse> {
+    let key = deps.api.canonical_address(&address)?;
+    let balance = DEPOSIT_BALANCES
+        .may_load(&deps.storage, key.as_slice())?
+        .unwrap_or_default();
+    Ok(DepositBalanceResponse {
+        balance: balance.into_vec(),
+    })
+}
+
+fn parse_rental<A: Api>(api: A, item: StdResult<KV<Rental>>) -> StdResult<QueryRentalsResult> {
+    item.and_then(|(k, rental)| {
+        let offering_id = from_utf8(&k)?;
+        Ok(QueryRentalsResult {
+            offering_id: offering_id.to_string(),
+            token_id: rental.token_id,
+            contract_addr: api.human_address(&rental.contract_addr)?,
+            owner: api.human_address(&rental.owner)?,
+            user: api.human_address(&rental.user)?,
+            expires: rental.expires,
+        })
+    })
+}
+
+// ============================== Test ==============================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::OverlayStorage;
+    use crate::state::num_offerings;
+    use cosmwasm_std::testing::{
+        mock_env, mock_info, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR,
+    };
+    use cosmwasm_std::{
+        coin, coins, from_binary, from_slice, Coin, Empty, HumanAddr, QuerierResult, QueryRequest,
+        ReadonlyStorage, SystemError, SystemResult, Uint128, WasmQuery,
+    };
+    use cw20::{Cw20CoinHuman, Cw20QueryMsg, TokenInfoResponse};
+    use cw721::{NftInfoResponse, OwnerOfResponse};
+
+    /// MarketQuerier answers the cw721 and cw20 queries of the marketplace and passes
+    /// everything else on to the MockQuerier
+    struct MarketQuerier {
+        base: MockQuerier,
+        /// reported as owner of every token
+        nft_owner: HumanAddr,
+        /// reported by every cw20 contract, TokenInfo fails if unset
+        token_info: Option<TokenInfoResponse>,
+        /// reported for every token, NftInfo fails if unset
+        nft_info: Option<NftInfoResponse>,
+    }
+
+    impl Querier for MarketQuerier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = match from_slice(bin_request) {
+                Ok(v) => v,
+                Err(e) => {
+                    return SystemResult::Err(SystemError::InvalidRequest {
+                        error: format!("Parsing query request: {}", e),
+                        request: bin_request.into(),
+                    })
+                }
+            };
+            match &request {
+                QueryRequest::Wasm(WasmQuery::Smart { msg, .. }) => match from_binary(msg) {
+                    Ok(Cw721QueryMsg::OwnerOf { .. }) => {
+                        let res = OwnerOfResponse {
+                            owner: self.nft_owner.clone(),
+                            approvals: vec![],
+                        };
+                        SystemResult::Ok(to_binary(&res).into())
+                    }
+                    Ok(Cw721QueryMsg::NftInfo { .. }) if self.nft_info.is_some() => {
+                        SystemResult::Ok(to_binary(&self.nft_info).into())
+                    }
+                    _ => match (from_binary(msg), &self.token_info) {
+                        (Ok(Cw20QueryMsg::TokenInfo {}), Some(info)) => {
+                            SystemResult::Ok(to_binary(info).into())
+                        }
+                        _ => SystemResult::Err(SystemError::UnsupportedRequest {
+                            kind: String::from("wasm"),
+                        }),
+                    },
+                },
+                _ => self.base.handle_query(&request),
+            }
+        }
+    }
+
+    /// like mock_dependencies, but the marketplace owns every token it is asked about
+    fn mock_dependencies(contract_balance: &[Coin]) -> Extern<MockStorage, MockApi, MarketQuerier> {
+        let contract_addr = HumanAddr::from(MOCK_CONTRACT_ADDR);
+        Extern {
+            storage: MockStorage::default(),
+            api: MockApi::default(),
+            querier: MarketQuerier {
+                base: MockQuerier::new(&[(&contract_addr, contract_balance)]),
+                nft_owner: contract_addr.clone(),
+                token_info: None,
+                nft_info: None,
+            },
+        }
+    }
+
+    //     #[test]
+    //     fn proper_initialization() {
+    //         let mut deps = mock_dependencies(&[]);
+
+    //         let msg = InitMsg { count: 17 };
+    //         let info = mock_info("creator", &coins(1000, "earth"));
+
+    //         // we can just call .unwrap() to assert this was a success
+    //         let res = init(&mut deps, mock_env(), info, msg).unwrap();
+    //         assert_eq!(0, res.messages.len());
+
+    //         // it worked, let's query the state
+    //         let res = query(&deps, mock_env(), QueryMsg::GetCount {}).unwrap();
+    //         let value: CountResponse = from_binary(&res).unwrap();
+    //         assert_eq!(17, value.count);
+    //     }
+
+    #[test]
+    fn sell_offering_happy_path() {
+        let mut deps = mock_dependencies(&coins(2, "token"));
+
+        let msg = InitMsg {
+            name: String::from("test market"),
+        };
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = init(&mut deps, mock_env(), info, msg).unwrap();
+
+        // beneficiary can release it
+        let info = mock_info("anyone", &coins(2, "token"));
+
+        let sell_msg = SellNft {
+            list_price: Cw20CoinHuman {
+                address: HumanAddr::from("cw20ContractAddr"),
+                amount: Uint128(5),
+            },
+            kind: None,
+        };
+
+        let msg = HandleMsg::ReceiveNft(Cw721ReceiveMsg {
+            sender: HumanAddr::from("seller"),
+            token_id: String::from("SellableNFT"),
+            msg: to_binary(&sell_msg).ok(),
+        });
+        let _res = handle(&mut deps, mock_env(), info, msg).unwrap();
+
+        // Offering should be listed
+        let res = query(
+            &deps,
+            mock_env(),
+            QueryMsg::GetOfferings {
+                resolve_prices: None,
+                min_reputation: None,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: OfferingsResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.offerings.len());
+
+        let buy_msg = BuyNft {
+            offering_id: value.offerings[0].id.clone(),
+            referrer: None,
+        };
+
+        let msg2 = HandleMsg::Receive(Cw20ReceiveMsg {
+            sender: HumanAddr::from("buyer"),
+            amount: Uint128(5),
+            msg: to_binary(&buy_msg).ok(),
+        });
+
+        let info_buy = mock_info("cw20ContractAddr", &coins(2, "token"));
+
+        let _res = handle(&mut deps, mock_env(), info_buy, msg2).unwrap();
+
+        // check offerings again. Should be 0
+        let res2 = query(
+            &deps,
+            mock_env(),
+            QueryMsg::GetOfferings {
+                resolve_prices: None,
+                min_reputation: None,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value2: OfferingsResponse = from_binary(&res2).unwrap();
+        assert_eq!(0, value2.offerings.len());
+    }
+
+    #[test]
+    fn withdraw_offering_happy_path() {
+        let mut deps = mock_dependencies(&coins(2, "token"));
+
+        let msg = InitMsg {
+            name: String::from("test market"),
+        };
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = init(&mut deps, mock_env(), info, msg).unwrap();
+
+        // beneficiary can release it
+        let info = mock_info("anyone", &coins(2, "token"));
+
+        let sell_msg = SellNft {
+            list_price: Cw20CoinHuman {
+                address: HumanAddr::from("cw20ContractAddr"),
+                amount: Uint128(5),
+            },
+            kind: None,
+        };
+
+        let msg = HandleMsg::ReceiveNft(Cw721ReceiveMsg {
+            sender: HumanAddr::from("seller"),
+            token_id: String::from("SellableNFT"),
+            msg: to_binary(&sell_msg).ok(),
+        });
+        let _res = handle(&mut deps, mock_env(), info, msg).unwrap();
+
+        // Offering should be listed
+        let res = query(
+            &deps,
+            mock_env(),
+            QueryMsg::GetOfferings {
+                resolve_prices: None,
+                min_reputation: None,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: OfferingsResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.offerings.len());
+
+        // withdraw offering
+        let withdraw_info = mock_info("seller", &coins(2, "token"));
+        let withdraw_msg = HandleMsg::WithdrawNft {
+            offering_id: value.offerings[0].id.clone(),
+        };
+        let _res = handle(&mut deps, mock_env(), withdraw_info, withdraw_msg).unwrap();
+
+        // Offering should be removed
+        let res2 = query(
+            &deps,
+            mock_env(),
+            QueryMsg::GetOfferings {
+                resolve_prices: None,
+                min_reputation: None,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value2: OfferingsResponse = from_binary(&res2).unwrap();
+        assert_eq!(0, value2.offerings.len());
+    }
+
+    #[test]
+    fn rent_offering_happy_path() {
+        let mut deps = mock_dependencies(&coins(2, "token"));
+
+        let msg = InitMsg {
+            name: String::from("test market"),
+        };
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = init(&mut deps, mock_env(), info, msg).unwrap();
+
+        // list the token for rent
+        let info = mock_info("nftContractAddr", &[]);
+        let sell_msg = SellNft {
+            list_price: Cw20CoinHuman {
+                address: HumanAddr::from("cw20ContractAddr"),
+                amount: Uint128(5),
+            },
+            kind: Some(OfferingKind::Rental { blocks: 100 }),
+        };
+        let msg = HandleMsg::ReceiveNft(Cw721ReceiveMsg {
+            sender: HumanAddr::from("owner"),
+            token_id: String::from("RentableNFT"),
+            msg: to_binary(&sell_msg).ok(),
+        });
+        let _res = handle(&mut deps, mock_env(), info, msg).unwrap();
+        let offering_id = String::from("1");
+
+        // paying with another token fails
+        let rent_msg = HandleMsg::Receive(Cw20ReceiveMsg {
+            sender: HumanAddr::from("renter"),
+            amount: Uint128(5),
+            msg: to_binary(&BuyNft {
+                offering_id: offering_id.clone(),
+                referrer: None,
+            })
+            .ok(),
+        });
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("otherCw20Addr", &[]),
+            rent_msg.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::InvalidCurrency {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // rent it, the fee goes to the owner and the token stays
+        let cw20 = mock_info("cw20ContractAddr", &[]);
+        let res = handle(&mut deps, mock_env(), cw20.clone(), rent_msg.clone()).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        let query_msg = QueryMsg::UserOf {
+            contract_addr: HumanAddr::from("nftContractAddr"),
+            token_id: String::from("RentableNFT"),
+        };
+        let res: UserOfResponse =
+            from_binary(&query(&deps, mock_env(), query_msg.clone()).unwrap()).unwrap();
+        assert_eq!(
+            res,
+            UserOfResponse {
+                user: Some(HumanAddr::from("renter")),
+                expires: Some(Expiration::AtHeight(mock_env().block.height + 100)),
+            }
+        );
+        let res: RentalsResponse = from_binary(
+            &query(
+                &deps,
+                mock_env(),
+                QueryMsg::RentalsByOwner {
+                    owner: HumanAddr::from("owner"),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(1, res.rentals.len());
+        assert_eq!(HumanAddr::from("renter"), res.rentals[0].user);
+
+        // nobody else can rent it and the owner cannot reclaim it yet
+        let err = handle(&mut deps, mock_env(), cw20.clone(), rent_msg).unwrap_err();
+        match err {
+            ContractError::Rented {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let withdraw_msg = HandleMsg::WithdrawNft {
+            offering_id: offering_id.clone(),
+        };
+        let owner = mock_info("owner", &[]);
+        let err = handle(&mut deps, mock_env(), owner.clone(), withdraw_msg.clone()).unwrap_err();
+        match err {
+            ContractError::Rented {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // once expired the rental no longer shows up and the owner gets the token back
+        let mut late_env = mock_env();
+        late_env.block.height += 100;
+        let res: UserOfResponse =
+            from_binary(&query(&deps, late_env.clone(), query_msg).unwrap()).unwrap();
+        assert_eq!(None, res.user);
+        let res: RentalsResponse = from_binary(
+            &query(
+                &deps,
+                late_env.clone(),
+                QueryMsg::RentalsByRenter {
+                    renter: HumanAddr::from("renter"),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(0, res.rentals.len());
+
+        let _res = handle(&mut deps, late_env, owner, withdraw_msg).unwrap();
+        let res = query(
+            &deps,
+            mock_env(),
+            QueryMsg::GetOfferings {
+                resolve_prices: None,
+                min_reputation: None,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: OfferingsResponse = from_binary(&res).unwrap();
+        assert_eq!(0, value.offerings.len());
+    }
+
+    fn setup_contract<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>) {
+        let msg = InitMsg {
+            name: String::from("test market"),
+        };
+        let info = mock_info("creator", &[]);
+        let res = init(deps, mock_env(), info, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+    }
+
+    /// lists token_id of "nftContractAddr" for 5 "cw20ContractAddr" and returns the offering_id
+    fn list_nft<S: Storage, A: Api, Q: Querier>(
+        deps: &mut Extern<S, A, Q>,
+        seller: &str,
+        token_id: &str,
+        kind: Option<OfferingKind>,
+    ) -> String {
+        list_nft_at(deps, seller, token_id, 5, kind)
+    }
+
+    fn list_nft_at<S: Storage, A: Api, Q: Querier>(
+        deps: &mut Extern<S, A, Q>,
+        seller: &str,
+        token_id: &str,
+        price: u128,
+        kind: Option<OfferingKind>,
+    ) -> String {
+        let sell_msg = SellNft {
+            list_price: Cw20CoinHuman {
+                address: HumanAddr::from("cw20ContractAddr"),
+                amount: Uint128(price),
+            },
+            kind,
+        };
+        let msg = HandleMsg::ReceiveNft(Cw721ReceiveMsg {
+            sender: HumanAddr::from(seller),
+            token_id: String::from(token_id),
+            msg: to_binary(&sell_msg).ok(),
+        });
+        let info = mock_info("nftContractAddr", &[]);
+        handle(deps, mock_env(), info, msg).unwrap();
+        num_offerings(&deps.storage).unwrap().to_string()
+    }
+
+    fn buy_msg(buyer: &str, amount: u128, offering_id: &str) -> HandleMsg {
+        HandleMsg::Receive(Cw20ReceiveMsg {
+            sender: HumanAddr::from(buyer),
+            amount: Uint128(amount),
+            msg: to_binary(&BuyNft {
+                offering_id: String::from(offering_id),
+                referrer: None,
+            })
+            .ok(),
+        })
+    }
+
+    #[test]
+    fn escrowed_trades() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        // escrows and rentals need a period of at least one block
+        let zero_blocks = vec![
+            OfferingKind::Escrow {
+                arbiter: HumanAddr::from("arbiter"),
+                blocks: 0,
+            },
+            OfferingKind::Rental { blocks: 0 },
+        ];
+        for kind in zero_blocks {
+            let sell_msg = SellNft {
+                list_price: Cw20CoinHuman {
+                    address: HumanAddr::from("cw20ContractAddr"),
+                    amount: Uint128(5),
+                },
+                kind: Some(kind),
+            };
+            let msg = HandleMsg::ReceiveNft(Cw721ReceiveMsg {
+                sender: HumanAddr::from("seller"),
+                token_id: String::from("ZeroNFT"),
+                msg: to_binary(&sell_msg).ok(),
+            });
+            let info = mock_info("nftContractAddr", &[]);
+            match handle(&mut deps, mock_env(), info, msg).unwrap_err() {
+                ContractError::InvalidTerms {} => {}
+                e => panic!("unexpected error: {}", e),
+            }
+        }
+
+        let kind = Some(OfferingKind::Escrow {
+            arbiter: HumanAddr::from("arbiter"),
+            blocks: 50,
+        });
+        let disputed_id = list_nft(&mut deps, "seller", "DisputedNFT", kind.clone());
+        let finalized_id = list_nft(&mut deps, "seller", "FinalizedNFT", kind);
+
+        // buying only escrows the payment
+        let cw20 = mock_info("cw20ContractAddr", &[]);
+        for id in &[&disputed_id, &finalized_id] {
+            let res = handle(&mut deps, mock_env(), cw20.clone(), buy_msg("buyer", 5, id)).unwrap();
+            assert_eq!(0, res.messages.len());
+        }
+        let res = query(
+            &deps,
+            mock_env(),
+            QueryMsg::GetOfferings {
+                resolve_prices: None,
+                min_reputation: None,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
         let value: OfferingsResponse = from_binary(&res).unwrap();
-        assert_eq!(1, value.offerings.len());
+        assert_eq!(0, value.offerings.len());
 
-        // withdraw offering
-        let withdraw_info = mock_info("seller", &coins(2, "token"));
-        let withdraw_msg = HandleMsg::WithdrawNft {
-            offering_id: value.offerings[0].id.clone(),
+        // nobody can finalize while the dispute window is open
+        let finalize_msg = HandleMsg::Finalize {
+            offering_id: finalized_id.clone(),
         };
-        let _res = handle(&mut deps, mock_env(), withdraw_info, withdraw_msg).unwrap();
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("anyone", &[]),
+            finalize_msg.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::DisputeWindowOpen {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
 
-        // Offering should be removed
-        let res2 = query(&deps, mock_env(), QueryMsg::GetOfferings {}).unwrap();
-        let value2: OfferingsResponse = from_binary(&res2).unwrap();
-        assert_eq!(0, value2.offerings.len());
+        // only the parties can dispute
+        let dispute_msg = HandleMsg::Dispute {
+            offering_id: disputed_id.clone(),
+        };
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("anyone", &[]),
+            dispute_msg.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        handle(&mut deps, mock_env(), mock_info("buyer", &[]), dispute_msg).unwrap();
+
+        // a disputed trade is not finalized, even after the window closed
+        let mut late_env = mock_env();
+        late_env.block.height += 50;
+        let err = handle(
+            &mut deps,
+            late_env.clone(),
+            mock_info("anyone", &[]),
+            HandleMsg::Finalize {
+                offering_id: disputed_id.clone(),
+            },
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Disputed {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // only the arbiter settles it
+        let resolve_msg = HandleMsg::ResolveDispute {
+            offering_id: disputed_id.clone(),
+            refund: true,
+        };
+        let err = handle(
+            &mut deps,
+            late_env.clone(),
+            mock_info("seller", &[]),
+            resolve_msg.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let res = handle(
+            &mut deps,
+            late_env.clone(),
+            mock_info("arbiter", &[]),
+            resolve_msg,
+        )
+        .unwrap();
+        let refund = Cw20Contract(HumanAddr::from("cw20ContractAddr"))
+            .call(Cw20HandleMsg::Transfer {
+                recipient: HumanAddr::from("buyer"),
+                amount: Uint128(5),
+            })
+            .unwrap();
+        let give_back = Cw721Contract(HumanAddr::from("nftContractAddr"))
+            .call(Cw721HandleMsg::TransferNft {
+                recipient: HumanAddr::from("seller"),
+                token_id: String::from("DisputedNFT"),
+            })
+            .unwrap();
+        assert_eq!(vec![refund, give_back], res.messages);
+
+        // the undisputed trade completes for anyone once the window closed
+        let res: EscrowResponse = from_binary(
+            &query(
+                &deps,
+                late_env.clone(),
+                QueryMsg::Escrow {
+                    offering_id: finalized_id.clone(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(HumanAddr::from("buyer"), res.buyer);
+        assert!(!res.disputed);
+        let res = handle(&mut deps, late_env, mock_info("anyone", &[]), finalize_msg).unwrap();
+        let payment = Cw20Contract(HumanAddr::from("cw20ContractAddr"))
+            .call(Cw20HandleMsg::Transfer {
+                recipient: HumanAddr::from("seller"),
+                amount: Uint128(5),
+            })
+            .unwrap();
+        let delivery = Cw721Contract(HumanAddr::from("nftContractAddr"))
+            .call(Cw721HandleMsg::TransferNft {
+                recipient: HumanAddr::from("buyer"),
+                token_id: String::from("FinalizedNFT"),
+            })
+            .unwrap();
+        assert_eq!(vec![payment, delivery], res.messages);
+        query(
+            &deps,
+            mock_env(),
+            QueryMsg::Escrow {
+                offering_id: finalized_id,
+            },
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn hooks_notified_on_offering_changes() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        // only the admin manages hooks
+        let add_msg = HandleMsg::AddHook {
+            addr: HumanAddr::from("rewards"),
+        };
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("anyone", &[]),
+            add_msg.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let admin = mock_info("creator", &[]);
+        handle(&mut deps, mock_env(), admin.clone(), add_msg.clone()).unwrap();
+        let err = handle(&mut deps, mock_env(), admin.clone(), add_msg).unwrap_err();
+        match err {
+            ContractError::HookAlreadyRegistered {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let res: HooksResponse =
+            from_binary(&query(&deps, mock_env(), QueryMsg::Hooks {}).unwrap()).unwrap();
+        assert_eq!(vec![HumanAddr::from("rewards")], res.hooks);
+
+        // listing notifies the hook
+        let sell_msg = SellNft {
+            list_price: Cw20CoinHuman {
+                address: HumanAddr::from("cw20ContractAddr"),
+                amount: Uint128(5),
+            },
+            kind: None,
+        };
+        let msg = HandleMsg::ReceiveNft(Cw721ReceiveMsg {
+            sender: HumanAddr::from("seller"),
+            token_id: String::from("SellableNFT"),
+            msg: to_binary(&sell_msg).ok(),
+        });
+        let res = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("nftContractAddr", &[]),
+            msg,
+        )
+        .unwrap();
+        let offering = QueryOfferingsResult {
+            id: String::from("1"),
+            token_id: String::from("SellableNFT"),
+            list_price: sell_msg.list_price.clone(),
+            contract_addr: HumanAddr::from("nftContractAddr"),
+            seller: HumanAddr::from("seller"),
+            kind: None,
+            display_price: None,
+            metadata: None,
+        };
+        let listed = MarketplaceHookMsg::Listed {
+            offering: offering.clone(),
+        }
+        .into_cosmos_msg(HumanAddr::from("rewards"))
+        .unwrap();
+        assert_eq!(vec![listed], res.messages);
+
+        // and so does buying
+        let cw20 = mock_info("cw20ContractAddr", &[]);
+        let res = handle(&mut deps, mock_env(), cw20, buy_msg("buyer", 7, "1")).unwrap();
+        let sold = MarketplaceHookMsg::Sold {
+            offering,
+            buyer: HumanAddr::from("buyer"),
+            paid_price: Cw20CoinHuman {
+                address: HumanAddr::from("cw20ContractAddr"),
+                amount: Uint128(5),
+            },
+        }
+        .into_cosmos_msg(HumanAddr::from("rewards"))
+        .unwrap();
+        assert_eq!(4, res.messages.len());
+        assert_eq!(sold, res.messages[3]);
+
+        // and withdrawing
+        let offering_id = list_nft(&mut deps, "seller", "OtherNFT", None);
+        let withdraw_msg = HandleMsg::WithdrawNft { offering_id };
+        let res = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("seller", &[]),
+            withdraw_msg,
+        )
+        .unwrap();
+        assert_eq!(2, res.messages.len());
+
+        // removed hooks are not notified anymore
+        let remove_msg = HandleMsg::RemoveHook {
+            addr: HumanAddr::from("rewards"),
+        };
+        handle(&mut deps, mock_env(), admin.clone(), remove_msg.clone()).unwrap();
+        let err = handle(&mut deps, mock_env(), admin, remove_msg).unwrap_err();
+        match err {
+            ContractError::HookNotRegistered {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let offering_id = list_nft(&mut deps, "seller", "ThirdNFT", None);
+        let withdraw_msg = HandleMsg::WithdrawNft { offering_id };
+        let res = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("seller", &[]),
+            withdraw_msg,
+        )
+        .unwrap();
+        assert_eq!(1, res.messages.len());
+    }
+
+    #[test]
+    fn sell_and_buy_return_data() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let sell_msg = SellNft {
+            list_price: Cw20CoinHuman {
+                address: HumanAddr::from("cw20ContractAddr"),
+                amount: Uint128(5),
+            },
+            kind: None,
+        };
+        let msg = HandleMsg::ReceiveNft(Cw721ReceiveMsg {
+            sender: HumanAddr::from("seller"),
+            token_id: String::from("SellableNFT"),
+            msg: to_binary(&sell_msg).ok(),
+        });
+        let res = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("nftContractAddr", &[]),
+            msg,
+        )
+        .unwrap();
+        let data: SellResponse = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(String::from("1"), data.offering_id);
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "sell_nft"),
+                attr("offering_id", "1"),
+                attr("contract_addr", "nftContractAddr"),
+                attr("seller", "seller"),
+                attr("amount", "5"),
+                attr("denom", "cw20ContractAddr"),
+                attr("token_id", "SellableNFT"),
+            ]
+        );
+
+        let cw20 = mock_info("cw20ContractAddr", &[]);
+        let res = handle(&mut deps, mock_env(), cw20, buy_msg("buyer", 6, "1")).unwrap();
+        let data: BuyResponse = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(
+            data,
+            BuyResponse {
+                offering_id: String::from("1"),
+                token_id: String::from("SellableNFT"),
+                contract_addr: HumanAddr::from("nftContractAddr"),
+                seller: HumanAddr::from("seller"),
+                buyer: HumanAddr::from("buyer"),
+                paid_price: Cw20CoinHuman {
+                    address: HumanAddr::from("cw20ContractAddr"),
+                    amount: Uint128(5),
+                },
+            }
+        );
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "buy_nft"),
+                attr("offering_id", "1"),
+                attr("buyer", "buyer"),
+                attr("seller", "seller"),
+                attr("amount", "5"),
+                attr("denom", "cw20ContractAddr"),
+                attr("token_id", "SellableNFT"),
+                attr("contract_addr", "nftContractAddr"),
+            ]
+        );
+        // the buyer gets back what they paid too much
+        let refund = Cw20Contract(HumanAddr::from("cw20ContractAddr"))
+            .call(Cw20HandleMsg::Transfer {
+                recipient: HumanAddr::from("buyer"),
+                amount: Uint128(1),
+            })
+            .unwrap();
+        assert_eq!(refund, res.messages[1]);
+    }
+
+    #[test]
+    fn spoofed_listing() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        // the sending contract never handed the token over
+        deps.querier.nft_owner = HumanAddr::from("seller");
+        let sell_msg = SellNft {
+            list_price: Cw20CoinHuman {
+                address: HumanAddr::from("cw20ContractAddr"),
+                amount: Uint128(5),
+            },
+            kind: None,
+        };
+        let msg = HandleMsg::ReceiveNft(Cw721ReceiveMsg {
+            sender: HumanAddr::from("seller"),
+            token_id: String::from("SpoofedNFT"),
+            msg: to_binary(&sell_msg).ok(),
+        });
+        let info = mock_info("fakeNftContract", &[]);
+        let err = handle(&mut deps, mock_env(), info, msg).unwrap_err();
+        match err {
+            ContractError::SpoofedListing {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let res = query(
+            &deps,
+            mock_env(),
+            QueryMsg::GetOfferings {
+                resolve_prices: None,
+                min_reputation: None,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: OfferingsResponse = from_binary(&res).unwrap();
+        assert_eq!(0, value.offerings.len());
+    }
+
+    #[test]
+    fn installment_purchase() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let kind = Some(OfferingKind::Installment {
+            down_payment: Uint128(2),
+            blocks: 100,
+            forfeit_percent: 50,
+        });
+        let offering_id = list_nft(&mut deps, "seller", "LayawayNFT", kind);
+        let cw20 = || mock_info("cw20ContractAddr", &[]);
+
+        // the first payment must cover the down payment
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            cw20(),
+            buy_msg("buyer", 1, &offering_id),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::InsufficientFunds {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let res = handle(
+            &mut deps,
+            mock_env(),
+            cw20(),
+            buy_msg("buyer", 2, &offering_id),
+        )
+        .unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // nobody else can pay on a reserved token
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            cw20(),
+            buy_msg("other", 5, &offering_id),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Reserved {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let info = mock_info("seller", &[]);
+        let msg = HandleMsg::WithdrawNft {
+            offering_id: offering_id.clone(),
+        };
+        let err = handle(&mut deps, mock_env(), info, msg).unwrap_err();
+        match err {
+            ContractError::Reserved {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let msg = QueryMsg::InstallmentsByBuyer {
+            buyer: HumanAddr::from("buyer"),
+            start_after: None,
+            limit: None,
+        };
+        let res: InstallmentsResponse =
+            from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(1, res.installments.len());
+        assert_eq!(Uint128(2), res.installments[0].paid);
+        assert_eq!(Uint128(3), res.installments[0].outstanding);
+        assert_eq!(Expiration::AtHeight(12_445), res.installments[0].deadline);
+
+        // paying off the rest completes the purchase
+        let res = handle(
+            &mut deps,
+            mock_env(),
+            cw20(),
+            buy_msg("buyer", 3, &offering_id),
+        )
+        .unwrap();
+        assert_eq!(2, res.messages.len());
+        assert_eq!(
+            res.messages[0],
+            Cw20Contract(HumanAddr::from("cw20ContractAddr"))
+                .call(Cw20HandleMsg::Transfer {
+                    recipient: HumanAddr::from("seller"),
+                    amount: Uint128(5),
+                })
+                .unwrap()
+        );
+        let msg = QueryMsg::Installment { offering_id };
+        assert!(query(&deps, mock_env(), msg).is_err());
+
+        // a defaulted reservation forfeits part of the down payment and frees the token
+        let kind = Some(OfferingKind::Installment {
+            down_payment: Uint128(2),
+            blocks: 100,
+            forfeit_percent: 50,
+        });
+        let offering_id = list_nft(&mut deps, "seller", "DefaultedNFT", kind);
+        handle(
+            &mut deps,
+            mock_env(),
+            cw20(),
+            buy_msg("buyer", 3, &offering_id),
+        )
+        .unwrap();
+
+        let mut env = mock_env();
+        env.block.height += 101;
+        let res = handle(
+            &mut deps,
+            env.clone(),
+            cw20(),
+            buy_msg("other", 2, &offering_id),
+        )
+        .unwrap();
+        let cw20_contract = Cw20Contract(HumanAddr::from("cw20ContractAddr"));
+        assert_eq!(
+            res.messages,
+            vec![
+                cw20_contract
+                    .call(Cw20HandleMsg::Transfer {
+                        recipient: HumanAddr::from("seller"),
+                        amount: Uint128(1),
+                    })
+                    .unwrap(),
+                cw20_contract
+                    .call(Cw20HandleMsg::Transfer {
+                        recipient: HumanAddr::from("buyer"),
+                        amount: Uint128(2),
+                    })
+                    .unwrap(),
+            ]
+        );
+        let msg = QueryMsg::Installment {
+            offering_id: offering_id.clone(),
+        };
+        let res: InstallmentResponse =
+            from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(HumanAddr::from("other"), res.buyer);
+
+        // the defaulted buyer can claim their refund without waiting for anybody else
+        let settle = HandleMsg::SettleDefault {
+            offering_id: offering_id.clone(),
+        };
+        let info = mock_info("other", &[]);
+        let err = handle(&mut deps, env.clone(), info.clone(), settle.clone()).unwrap_err();
+        match err {
+            ContractError::NotDefaulted {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        env.block.height += 100;
+        let res = handle(&mut deps, env.clone(), info.clone(), settle.clone()).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![
+                cw20_contract
+                    .call(Cw20HandleMsg::Transfer {
+                        recipient: HumanAddr::from("seller"),
+                        amount: Uint128(1),
+                    })
+                    .unwrap(),
+                cw20_contract
+                    .call(Cw20HandleMsg::Transfer {
+                        recipient: HumanAddr::from("other"),
+                        amount: Uint128(1),
+                    })
+                    .unwrap(),
+            ]
+        );
+        assert!(handle(&mut deps, env, info, settle).is_err());
+        let msg = QueryMsg::Installment { offering_id };
+        assert!(query(&deps, mock_env(), msg).is_err());
+    }
+
+    #[test]
+    fn raffle_draw_and_cancel() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let raffle = |seed: &[u8]| {
+            Some(OfferingKind::Raffle {
+                tickets: 3,
+                min_tickets: 2,
+                end_height: 12_445,
+                seed_commitment: Binary::from(Sha256::digest(seed).to_vec()),
+            })
+        };
+        let cw20 = || mock_info("cw20ContractAddr", &[]);
+        let mut ended = mock_env();
+        ended.block.height = 12_445;
+
+        // tickets are bought at 5 each, the change is refunded
+        let offering_id = list_nft(&mut deps, "seller", "PrizeNFT", raffle(b"secret"));
+        let res = handle(
+            &mut deps,
+            mock_env(),
+            cw20(),
+            buy_msg("alice", 12, &offering_id),
+        )
+        .unwrap();
+        assert_eq!(1, res.messages.len());
+        assert_eq!(attr("tickets", "2"), res.attributes[3]);
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            cw20(),
+            buy_msg("bob", 10, &offering_id),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::SoldOut {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        handle(
+            &mut deps,
+            mock_env(),
+            cw20(),
+            buy_msg("bob", 5, &offering_id),
+        )
+        .unwrap();
+        let err = handle(
+            &mut deps,
+            ended.clone(),
+            cw20(),
+            buy_msg("bob", 5, &offering_id),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::RaffleEnded {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // the draw needs the revealed seed
+        let draw = HandleMsg::DrawRaffle {
+            offering_id: offering_id.clone(),
+        };
+        let err = handle(
+            &mut deps,
+            ended.clone(),
+            mock_info("anyone", &[]),
+            draw.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::SeedNotRevealed {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let reveal = |seed: &str| HandleMsg::RevealRaffleSeed {
+            offering_id: offering_id.clone(),
+            seed: String::from(seed),
+        };
+        let err = handle(
+            &mut deps,
+            ended.clone(),
+            mock_info("seller", &[]),
+            reveal("guess"),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::InvalidSeed {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        handle(
+            &mut deps,
+            ended.clone(),
+            mock_info("seller", &[]),
+            reveal("secret"),
+        )
+        .unwrap();
+        let msg = QueryMsg::Raffle {
+            offering_id: offering_id.clone(),
+        };
+        let res: RaffleResponse = from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(3, res.tickets_sold);
+        assert!(res.seed_revealed);
+
+        // the winner is the same whichever block the draw lands in
+        let winners: Vec<_> = [12_445, 12_446, 12_500, 13_000]
+            .iter()
+            .map(|height| {
+                let mut copy = mock_dependencies(&[]);
+                for (k, v) in deps.storage.range(None, None, Order::Ascending) {
+                    copy.storage.set(&k, &v);
+                }
+                let mut env = mock_env();
+                env.block.height = *height;
+                env.block.time += height - 12_445;
+                let res = handle(&mut copy, env, mock_info("anyone", &[]), draw.clone()).unwrap();
+                let data: BuyResponse = from_binary(&res.data.unwrap()).unwrap();
+                data.buyer
+            })
+            .collect();
+        assert!(winners.iter().all(|w| *w == winners[0]));
+
+        let res = handle(&mut deps, ended.clone(), mock_info("anyone", &[]), draw).unwrap();
+        let data: BuyResponse = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(winners[0], data.buyer);
+        assert!(data.buyer.as_str() == "alice" || data.buyer.as_str() == "bob");
+        assert_eq!(Uint128(15), data.paid_price.amount);
+        assert_eq!(
+            res.messages[0],
+            Cw20Contract(HumanAddr::from("cw20ContractAddr"))
+                .call(Cw20HandleMsg::Transfer {
+                    recipient: HumanAddr::from("seller"),
+                    amount: Uint128(15),
+                })
+                .unwrap()
+        );
+
+        // too few tickets sold, anybody can cancel and the tickets are refunded
+        let offering_id = list_nft(&mut deps, "seller", "UnpopularNFT", raffle(b"secret"));
+        handle(
+            &mut deps,
+            mock_env(),
+            cw20(),
+            buy_msg("alice", 5, &offering_id),
+        )
+        .unwrap();
+        let msg = HandleMsg::WithdrawNft {
+            offering_id: offering_id.clone(),
+        };
+        let err = handle(&mut deps, mock_env(), mock_info("seller", &[]), msg).unwrap_err();
+        match err {
+            ContractError::TicketsSold {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let cancel = HandleMsg::CancelRaffle {
+            offering_id: offering_id.clone(),
+        };
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("anyone", &[]),
+            cancel.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::RaffleNotEnded {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let res = handle(&mut deps, ended, mock_info("anyone", &[]), cancel).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![
+                Cw20Contract(HumanAddr::from("cw20ContractAddr"))
+                    .call(Cw20HandleMsg::Transfer {
+                        recipient: HumanAddr::from("alice"),
+                        amount: Uint128(5),
+                    })
+                    .unwrap(),
+                Cw721Contract(HumanAddr::from("nftContractAddr"))
+                    .call(Cw721HandleMsg::TransferNft {
+                        recipient: HumanAddr::from("seller"),
+                        token_id: String::from("UnpopularNFT"),
+                    })
+                    .unwrap(),
+            ]
+        );
+        let res = query(
+            &deps,
+            mock_env(),
+            QueryMsg::GetOfferings {
+                resolve_prices: None,
+                min_reputation: None,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: OfferingsResponse = from_binary(&res).unwrap();
+        assert_eq!(0, value.offerings.len());
+
+        // an unrevealed seed cancels the raffle, refunding all purchases of each holder at once
+        let offering_id = list_nft(&mut deps, "seller", "ForgottenNFT", raffle(b"secret"));
+        for buyer in &["alice", "bob", "alice"] {
+            handle(
+                &mut deps,
+                mock_env(),
+                cw20(),
+                buy_msg(buyer, 5, &offering_id),
+            )
+            .unwrap();
+        }
+        let mut forgotten = mock_env();
+        forgotten.block.height = 12_445 + RAFFLE_REVEAL_BLOCKS;
+        let cancel = HandleMsg::CancelRaffle {
+            offering_id: offering_id.clone(),
+        };
+        let res = handle(&mut deps, forgotten, mock_info("anyone", &[]), cancel).unwrap();
+        let refund = |recipient: &str, amount: u128| {
+            Cw20Contract(HumanAddr::from("cw20ContractAddr"))
+                .call(Cw20HandleMsg::Transfer {
+                    recipient: HumanAddr::from(recipient),
+                    amount: Uint128(amount),
+                })
+                .unwrap()
+        };
+        assert_eq!(res.messages[..2], [refund("alice", 10), refund("bob", 5)]);
+        let purchases = RAFFLE_TICKETS
+            .prefix(&offering_id)
+            .range(&deps.storage, None, None, Order::Ascending)
+            .count();
+        assert_eq!(0, purchases);
+
+        // the number of tickets is capped
+        let sell_msg = SellNft {
+            list_price: Cw20CoinHuman {
+                address: HumanAddr::from("cw20ContractAddr"),
+                amount: Uint128(5),
+            },
+            kind: Some(OfferingKind::Raffle {
+                tickets: MAX_RAFFLE_TICKETS + 1,
+                min_tickets: 1,
+                end_height: 12_445,
+                seed_commitment: Binary::from(Sha256::digest(b"secret").to_vec()),
+            }),
+        };
+        let msg = HandleMsg::ReceiveNft(Cw721ReceiveMsg {
+            sender: HumanAddr::from("seller"),
+            token_id: String::from("HugeNFT"),
+            msg: to_binary(&sell_msg).ok(),
+        });
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("nftContractAddr", &[]),
+            msg,
+        )
+        .unwrap_err();
+        match err {
+            ContractError::InvalidTerms {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn listing_caps_and_deposits() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let config = HandleMsg::UpdateConfig {
+            max_listings: Some(Some(1)),
+            deposit: Some(Some(Deposit::Native(coin(10, "ucosm")))),
+            protocol_fee: None,
+            referral_share: None,
+        };
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("seller", &[]),
+            config.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        handle(&mut deps, mock_env(), mock_info("creator", &[]), config).unwrap();
+
+        let sell = |token_id: &str| {
+            let sell_msg = SellNft {
+                list_price: Cw20CoinHuman {
+                    address: HumanAddr::from("cw20ContractAddr"),
+                    amount: Uint128(5),
+                },
+                kind: None,
+            };
+            HandleMsg::ReceiveNft(Cw721ReceiveMsg {
+                sender: HumanAddr::from("seller"),
+                token_id: String::from(token_id),
+                msg: to_binary(&sell_msg).ok(),
+            })
+        };
+        let nft = || mock_info("nftContractAddr", &[]);
+        let balance = |deps: &Extern<_, _, _>, address: &str| {
+            let msg = QueryMsg::DepositBalance {
+                address: HumanAddr::from(address),
+            };
+            let res: DepositBalanceResponse =
+                from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap();
+            res.balance
+        };
+
+        // the deposit has to be paid in before listing
+        let err = handle(&mut deps, mock_env(), nft(), sell("FirstNFT")).unwrap_err();
+        match err {
+            ContractError::InsufficientFunds {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let info = mock_info("seller", &coins(25, "ucosm"));
+        handle(&mut deps, mock_env(), info, HandleMsg::TopUpDeposits {}).unwrap();
+        handle(&mut deps, mock_env(), nft(), sell("FirstNFT")).unwrap();
+        assert_eq!(coins(15, "ucosm"), balance(&deps, "seller"));
+
+        // only one active listing per seller
+        let err = handle(&mut deps, mock_env(), nft(), sell("SecondNFT")).unwrap_err();
+        match err {
+            ContractError::TooManyListings {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // withdrawing returns the deposit
+        let msg = HandleMsg::WithdrawNft {
+            offering_id: String::from("1"),
+        };
+        handle(&mut deps, mock_env(), mock_info("seller", &[]), msg).unwrap();
+        assert_eq!(coins(25, "ucosm"), balance(&deps, "seller"));
+
+        // a spam listing loses its deposit to the admin
+        handle(&mut deps, mock_env(), nft(), sell("SpamNFT")).unwrap();
+        let msg = HandleMsg::DelistSpam {
+            offering_id: String::from("2"),
+        };
+        let res = handle(&mut deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![Cw721Contract(HumanAddr::from("nftContractAddr"))
+                .call(Cw721HandleMsg::TransferNft {
+                    recipient: HumanAddr::from("seller"),
+                    token_id: String::from("SpamNFT"),
+                })
+                .unwrap()]
+        );
+        assert_eq!(coins(15, "ucosm"), balance(&deps, "seller"));
+        assert_eq!(coins(10, "ucosm"), balance(&deps, "creator"));
+
+        let info = mock_info("seller", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::WithdrawDeposits {}).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![BankMsg::Send {
+                from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+                to_address: HumanAddr::from("seller"),
+                amount: coins(15, "ucosm"),
+            }
+            .into()]
+        );
+
+        // a cw20 deposit is taken from the seller's allowance and returned on sale
+        let deposit = Cw20CoinHuman {
+            address: HumanAddr::from("depositToken"),
+            amount: Uint128(3),
+        };
+        let config = HandleMsg::UpdateConfig {
+            max_listings: Some(None),
+            deposit: Some(Some(Deposit::Cw20(deposit.clone()))),
+            protocol_fee: None,
+            referral_share: None,
+        };
+        handle(&mut deps, mock_env(), mock_info("creator", &[]), config).unwrap();
+        let res = handle(&mut deps, mock_env(), nft(), sell("ThirdNFT")).unwrap();
+        let cw20 = Cw20Contract(deposit.address.clone());
+        assert_eq!(
+            res.messages,
+            vec![cw20
+                .call(Cw20HandleMsg::TransferFrom {
+                    owner: HumanAddr::from("seller"),
+                    recipient: HumanAddr::from(MOCK_CONTRACT_ADDR),
+                    amount: Uint128(3),
+                })
+                .unwrap()]
+        );
+        let info = mock_info("cw20ContractAddr", &[]);
+        let res = handle(&mut deps, mock_env(), info, buy_msg("buyer", 5, "3")).unwrap();
+        assert_eq!(
+            res.messages[1],
+            cw20.call(Cw20HandleMsg::Transfer {
+                recipient: HumanAddr::from("seller"),
+                amount: Uint128(3),
+            })
+            .unwrap()
+        );
+
+        // updating one value keeps the others
+        let config = HandleMsg::UpdateConfig {
+            max_listings: None,
+            deposit: None,
+            protocol_fee: Some(Some(Decimal::percent(1))),
+            referral_share: None,
+        };
+        handle(&mut deps, mock_env(), mock_info("creator", &[]), config).unwrap();
+        let res: ConfigResponse =
+            from_binary(&query(&deps, mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(None, res.max_listings);
+        assert_eq!(Some(Deposit::Cw20(deposit)), res.deposit);
+        assert_eq!(Some(Decimal::percent(1)), res.protocol_fee);
+
+        // while a left out value is kept, null unsets it
+        let config: HandleMsg =
+            from_slice(br#"{"update_config": {"protocol_fee": null}}"#).unwrap();
+        handle(&mut deps, mock_env(), mock_info("creator", &[]), config).unwrap();
+        let res: ConfigResponse =
+            from_binary(&query(&deps, mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(None, res.protocol_fee);
+        assert!(res.deposit.is_some());
+    }
+
+    #[test]
+    fn offerings_with_display_prices() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        // the token info is cached when listing
+        deps.querier.token_info = Some(TokenInfoResponse {
+            name: String::from("Token"),
+            symbol: String::from("TKN"),
+            decimals: 6,
+            total_supply: Uint128(1_000_000_000),
+        });
+        list_nft(&mut deps, "seller", "PricedNFT", None);
+        deps.querier.token_info = None;
+
+        let msg = QueryMsg::GetOfferings {
+            resolve_prices: None,
+            min_reputation: None,
+            start_after: None,
+            limit: None,
+        };
+        let res: OfferingsResponse = from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(None, res.offerings[0].display_price);
+
+        let msg = QueryMsg::GetOfferings {
+            resolve_prices: Some(true),
+            min_reputation: None,
+            start_after: None,
+            limit: None,
+        };
+        let res: OfferingsResponse = from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(
+            Some(String::from("0.000005 TKN")),
+            res.offerings[0].display_price
+        );
+
+        let display = TokenDisplay {
+            symbol: String::from("TKN"),
+            decimals: 6,
+        };
+        assert_eq!("1.5 TKN", format_price(Uint128(1_500_000), &display));
+        assert_eq!("2 TKN", format_price(Uint128(2_000_000), &display));
+        assert_eq!("0 TKN", format_price(Uint128(0), &display));
+
+        // the offerings are paged even when they aren't filtered
+        for i in 0..11 {
+            list_nft(&mut deps, "seller", &format!("NFT{}", i), None);
+        }
+        let offerings = |limit: Option<u32>| {
+            let msg = QueryMsg::GetOfferings {
+                resolve_prices: Some(true),
+                min_reputation: None,
+                start_after: None,
+                limit,
+            };
+            let res: OfferingsResponse =
+                from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+            res.offerings.len()
+        };
+        assert_eq!(DEFAULT_LIMIT as usize, offerings(None));
+        assert_eq!(12, offerings(Some(u32::MAX)));
+    }
+
+    #[test]
+    fn referral_fees() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let config = HandleMsg::UpdateConfig {
+            max_listings: None,
+            deposit: None,
+            protocol_fee: Some(Some(Decimal::percent(10))),
+            referral_share: Some(Some(Decimal::percent(40))),
+        };
+        handle(&mut deps, mock_env(), mock_info("creator", &[]), config).unwrap();
+
+        let referred_buy = |buyer: &str, offering_id: &str| {
+            HandleMsg::Receive(Cw20ReceiveMsg {
+                sender: HumanAddr::from(buyer),
+                amount: Uint128(50),
+                msg: to_binary(&BuyNft {
+                    offering_id: String::from(offering_id),
+                    referrer: Some(HumanAddr::from("frontend")),
+                })
+                .ok(),
+            })
+        };
+        let cw20 = Cw20Contract(HumanAddr::from("cw20ContractAddr"));
+        let transfer = |recipient: &str, amount: u128| {
+            cw20.call(Cw20HandleMsg::Transfer {
+                recipient: HumanAddr::from(recipient),
+                amount: Uint128(amount),
+            })
+            .unwrap()
+        };
+
+        // 10% protocol fee, of which the referrer earns 40%
+        let offering_id = list_nft_at(&mut deps, "seller", "FirstNFT", 50, None);
+        let info = mock_info("cw20ContractAddr", &[]);
+        let res = handle(
+            &mut deps,
+            mock_env(),
+            info,
+            referred_buy("buyer", &offering_id),
+        )
+        .unwrap();
+        assert_eq!(transfer("seller", 45), res.messages[0]);
+        assert_eq!(transfer("creator", 3), res.messages[1]);
+        let referral_fee = res.attributes.iter().find(|a| a.key == "referral_fee");
+        assert_eq!(Some(&attr("referral_fee", "2")), referral_fee);
+
+        // a buyer can't pocket the referral fee
+        let offering_id = list_nft_at(&mut deps, "seller", "SecondNFT", 50, None);
+        let info = mock_info("cw20ContractAddr", &[]);
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            info,
+            referred_buy("frontend", &offering_id),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::SelfReferral {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let info = mock_info("cw20ContractAddr", &[]);
+        handle(
+            &mut deps,
+            mock_env(),
+            info,
+            referred_buy("buyer", &offering_id),
+        )
+        .unwrap();
+
+        // earnings add up until withdrawn
+        let msg = QueryMsg::ReferralEarnings {
+            referrer: HumanAddr::from("frontend"),
+        };
+        let res: ReferralEarningsResponse =
+            from_binary(&query(&deps, mock_env(), msg.clone()).unwrap()).unwrap();
+        assert_eq!(Uint128(4), res.earnings[0].amount);
+
+        let info = mock_info("frontend", &[]);
+        let res = handle(
+            &mut deps,
+            mock_env(),
+            info,
+            HandleMsg::WithdrawReferralEarnings {},
+        )
+        .unwrap();
+        assert_eq!(vec![transfer("frontend", 4)], res.messages);
+        let res: ReferralEarningsResponse =
+            from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert!(res.earnings.is_empty());
+
+        // other kinds of offerings can't be referred, but pay the protocol fee all the same
+        let kind = Some(OfferingKind::Escrow {
+            arbiter: HumanAddr::from("arbiter"),
+            blocks: 50,
+        });
+        let offering_id = list_nft(&mut deps, "seller", "EscrowedNFT", kind);
+        let info = mock_info("cw20ContractAddr", &[]);
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            info.clone(),
+            referred_buy("buyer", &offering_id),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::ReferralNotSupported {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        handle(
+            &mut deps,
+            mock_env(),
+            info,
+            buy_msg("buyer", 50, &offering_id),
+        )
+        .unwrap();
+        let mut env = mock_env();
+        env.block.height += 50;
+        let msg = HandleMsg::Finalize { offering_id };
+        let res = handle(&mut deps, env, mock_info("anyone", &[]), msg).unwrap();
+        assert_eq!(transfer("seller", 45), res.messages[0]);
+        assert_eq!(transfer("creator", 5), res.messages[1]);
+    }
+
+    #[test]
+    fn seller_reputation() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let offering_id = list_nft(&mut deps, "seller", "RatedNFT", None);
+        list_nft(&mut deps, "newcomer", "UnratedNFT", None);
+        let info = mock_info("cw20ContractAddr", &[]);
+        handle(
+            &mut deps,
+            mock_env(),
+            info,
+            buy_msg("buyer", 5, &offering_id),
+        )
+        .unwrap();
+
+        // only the buyer rates, and only once
+        let rate = HandleMsg::RateSeller {
+            offering_id: offering_id.clone(),
+            positive: true,
+        };
+        let err = handle(
+            &mut deps,
+            mock_env(),
+            mock_info("seller", &[]),
+            rate.clone(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        handle(&mut deps, mock_env(), mock_info("buyer", &[]), rate.clone()).unwrap();
+        let err = handle(&mut deps, mock_env(), mock_info("buyer", &[]), rate).unwrap_err();
+        match err {
+            ContractError::NothingToRate {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let msg = QueryMsg::Reputation {
+            address: HumanAddr::from("seller"),
+        };
+        let res: ReputationResponse = from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(1, res.positive);
+        assert_eq!(1, res.score);
+        assert_eq!(1, res.sales);
+        let msg = QueryMsg::Reputation {
+            address: HumanAddr::from("buyer"),
+        };
+        let res: ReputationResponse = from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(1, res.purchases);
+
+        // a second listing of the rated seller passes the filter, the newcomer's does not
+        list_nft(&mut deps, "seller", "AnotherNFT", None);
+        let msg = QueryMsg::GetOfferings {
+            resolve_prices: None,
+            min_reputation: Some(1),
+            start_after: None,
+            limit: None,
+        };
+        let res: OfferingsResponse = from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(1, res.offerings.len());
+        assert_eq!("AnotherNFT", res.offerings[0].token_id);
+
+        // filtered offerings come in pages
+        let another = res.offerings[0].id.clone();
+        list_nft(&mut deps, "seller", "ThirdNFT", None);
+        let page = |start_after: Option<String>| {
+            let msg = QueryMsg::GetOfferings {
+                resolve_prices: None,
+                min_reputation: Some(1),
+                start_after,
+                limit: Some(1),
+            };
+            let res: OfferingsResponse =
+                from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+            res.offerings
+                .into_iter()
+                .map(|offering| offering.token_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["AnotherNFT"], page(None));
+        assert_eq!(vec!["ThirdNFT"], page(Some(another)));
+
+        // buying from yourself neither counts as a sale nor can be rated
+        let offering_id = list_nft(&mut deps, "seller", "WashedNFT", None);
+        let info = mock_info("cw20ContractAddr", &[]);
+        handle(
+            &mut deps,
+            mock_env(),
+            info,
+            buy_msg("seller", 5, &offering_id),
+        )
+        .unwrap();
+        let rate = HandleMsg::RateSeller {
+            offering_id,
+            positive: true,
+        };
+        let err = handle(&mut deps, mock_env(), mock_info("seller", &[]), rate).unwrap_err();
+        match err {
+            ContractError::NothingToRate {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let msg = QueryMsg::Reputation {
+            address: HumanAddr::from("seller"),
+        };
+        let res: ReputationResponse = from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(1, res.sales);
+        assert_eq!(0, res.purchases);
+        assert_eq!(1, res.score);
+    }
+
+    #[test]
+    fn price_oracle() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let price_query = |deps: &Extern<_, _, _>, msg: QueryMsg, height: u64| {
+            let mut env = mock_env();
+            env.block.height = height;
+            let res: PriceResponse = from_binary(&query(deps, env, msg).unwrap()).unwrap();
+            res
+        };
+        let collection = HumanAddr::from("nftContractAddr");
+        let currency = HumanAddr::from("cw20ContractAddr");
+        let floor = QueryMsg::FloorPrice {
+            contract_addr: collection.clone(),
+            currency: currency.clone(),
+        };
+        let median = QueryMsg::MedianPrice {
+            contract_addr: collection.clone(),
+            currency: currency.clone(),
+        };
+        let average = QueryMsg::AveragePrice {
+            contract_addr: collection,
+            currency,
+        };
+        assert_eq!(None, price_query(&deps, floor.clone(), 12_345).price);
+
+        // sales listed for 5 at 12_345, 9 at 12_355 and 20 at 12_365, the first one overpaid
+        for (i, (amount, paid)) in [(5u128, 50u128), (9, 9), (20, 20)].iter().enumerate() {
+            let sell_msg = SellNft {
+                list_price: Cw20CoinHuman {
+                    address: HumanAddr::from("cw20ContractAddr"),
+                    amount: Uint128(*amount),
+                },
+                kind: None,
+            };
+            let msg = HandleMsg::ReceiveNft(Cw721ReceiveMsg {
+                sender: HumanAddr::from("seller"),
+                token_id: format!("NFT{}", i),
+                msg: to_binary(&sell_msg).ok(),
+            });
+            let info = mock_info("nftContractAddr", &[]);
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+            let offering_id = num_offerings(&deps.storage).unwrap().to_string();
+
+            let mut env = mock_env();
+            env.block.height += 10 * i as u64;
+            let info = mock_info("cw20ContractAddr", &[]);
+            handle(&mut deps, env, info, buy_msg("buyer", *paid, &offering_id)).unwrap();
+        }
+        // a seller buying their own token does not make a sale
+        let offering_id = list_nft(&mut deps, "seller", "WashNFT", None);
+        let mut env = mock_env();
+        env.block.height += 20;
+        let info = mock_info("cw20ContractAddr", &[]);
+        handle(&mut deps, env, info, buy_msg("seller", 5, &offering_id)).unwrap();
+
+        let res = price_query(&deps, floor, 12_375);
+        assert_eq!(Some(Uint128(5)), res.price);
+        assert_eq!(3, res.sales);
+        assert_eq!(Some(Uint128(9)), price_query(&deps, median, 12_375).price);
+        // (5 * 10 + 9 * 10 + 20 * 10) / 30
+        assert_eq!(
+            Some(Uint128(11)),
+            price_query(&deps, average.clone(), 12_375).price
+        );
+        // the latest sale counts for at least one block: (5 * 10 + 9 * 10 + 20) / 21
+        assert_eq!(
+            Some(Uint128(7)),
+            price_query(&deps, average.clone(), 12_365).price
+        );
+        // and for at most 100, long after it: (5 * 10 + 9 * 10 + 20 * 100) / 120
+        assert_eq!(Some(Uint128(17)), price_query(&deps, average, 22_365).price);
+    }
+
+    #[test]
+    fn offering_details_and_watchlist() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let first = list_nft(&mut deps, "seller", "FirstNFT", None);
+        let second = list_nft(&mut deps, "seller", "SecondNFT", None);
+        let third = list_nft(&mut deps, "seller", "ThirdNFT", None);
+
+        for (watcher, offering_id) in &[("alice", &first), ("alice", &second), ("bob", &first)] {
+            let msg = HandleMsg::Watch {
+                offering_id: offering_id.to_string(),
+            };
+            handle(&mut deps, mock_env(), mock_info(*watcher, &[]), msg).unwrap();
+        }
+
+        let msg = QueryMsg::Offering {
+            offering_id: first.clone(),
+        };
+        let res: OfferingResponse = from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert_eq!("FirstNFT", res.offering.token_id);
+        assert_eq!(12_345, res.listed_height);
+        assert_eq!(2, res.seller_listings);
+        assert_eq!(2, res.watchers);
+
+        // sold and unwatched offerings drop out of the watchlist
+        let info = mock_info("cw20ContractAddr", &[]);
+        handle(&mut deps, mock_env(), info, buy_msg("buyer", 5, &second)).unwrap();
+        let alice = deps
+            .api
+            .canonical_address(&HumanAddr::from("alice"))
+            .unwrap();
+        let watched: Vec<_> = watches::<MockStorage>()
+            .idx
+            .watcher
+            .pks(&deps.storage, &alice, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(vec![watch_key(&alice, &first)], watched);
+        let msg = HandleMsg::Unwatch {
+            offering_id: first.clone(),
+        };
+        handle(&mut deps, mock_env(), mock_info("bob", &[]), msg).unwrap();
+        let msg = HandleMsg::Watch {
+            offering_id: third.clone(),
+        };
+        handle(&mut deps, mock_env(), mock_info("alice", &[]), msg).unwrap();
+
+        let watchlist = |watcher: &str, start_after: Option<String>| {
+            let msg = QueryMsg::Watchlist {
+                watcher: HumanAddr::from(watcher),
+                start_after,
+                limit: None,
+            };
+            let res: OfferingsResponse =
+                from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+            res.offerings
+                .into_iter()
+                .map(|offering| offering.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![first.clone(), third.clone()], watchlist("alice", None));
+        assert_eq!(vec![third.clone()], watchlist("alice", Some(first)));
+        assert!(watchlist("bob", None).is_empty());
+
+        // offerings stored before the listing height was recorded still load
+        #[derive(serde::Serialize)]
+        struct LegacyOffering {
+            token_id: String,
+            contract_addr: CanonicalAddr,
+            seller: CanonicalAddr,
+            list_price: Cw20CoinHuman,
+            kind: Option<OfferingKind>,
+        }
+        let off = OFFERINGS.load(&deps.storage, &third).unwrap();
+        let legacy = to_binary(&LegacyOffering {
+            token_id: off.token_id.clone(),
+            contract_addr: off.contract_addr.clone(),
+            seller: off.seller.clone(),
+            list_price: off.list_price.clone(),
+            kind: None,
+        })
+        .unwrap();
+        let legacy: Offering = from_binary(&legacy).unwrap();
+        assert_eq!(0, legacy.listed_height);
+        assert_eq!(off.token_id, legacy.token_id);
+    }
+
+    #[test]
+    fn overlay_storage() {
+        let mut base = MockStorage::new();
+        for key in &[b"a", b"b", b"c", b"d"] {
+            base.set(*key, b"base");
+        }
+        let mut overlay = OverlayStorage::new(&base);
+        overlay.set(b"b", b"changed");
+        overlay.remove(b"c");
+        overlay.set(b"e", b"added");
+        overlay.remove(b"f");
+
+        let keys = |order: Order, start: Option<&[u8]>, end: Option<&[u8]>| {
+            overlay
+                .range(start, end, order)
+                .map(|(key, value)| (String::from_utf8(key).unwrap(), value))
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![
+            ("a".to_string(), b"base".to_vec()),
+            ("b".to_string(), b"changed".to_vec()),
+            ("d".to_string(), b"base".to_vec()),
+            ("e".to_string(), b"added".to_vec()),
+        ];
+        assert_eq!(expected, keys(Order::Ascending, None, None));
+        let reversed: Vec<_> = expected.into_iter().rev().collect();
+        assert_eq!(reversed, keys(Order::Descending, None, None));
+        assert_eq!(
+            vec![("b".to_string(), b"changed".to_vec())],
+            keys(Order::Descending, Some(b"b"), Some(b"d"))
+        );
+        assert!(keys(Order::Ascending, Some(b"d"), Some(b"b")).is_empty());
+        // the base storage is left alone
+        assert_eq!(Some(b"base".to_vec()), base.get(b"c"));
+    }
+
+    #[test]
+    fn simulate_buy() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        let config = HandleMsg::UpdateConfig {
+            max_listings: None,
+            deposit: None,
+            protocol_fee: Some(Some(Decimal::percent(10))),
+            referral_share: Some(Some(Decimal::percent(40))),
+        };
+        handle(&mut deps, mock_env(), mock_info("creator", &[]), config).unwrap();
+        let offering_id = list_nft_at(&mut deps, "seller", "FirstNFT", 50, None);
+
+        let simulate = |amount: u128, currency: &str| QueryMsg::SimulateBuy {
+            offering_id: offering_id.clone(),
+            buyer: HumanAddr::from("buyer"),
+            amount: Uint128(amount),
+            currency: HumanAddr::from(currency),
+            referrer: Some(HumanAddr::from("frontend")),
+        };
+        let transfer = |kind: TransferKind, recipient: &str, amount: u128| SimulatedTransfer {
+            kind,
+            recipient: HumanAddr::from(recipient),
+            amount: Uint128(amount),
+        };
+
+        // the dry run reports what the purchase pays out
+        let res: SimulateBuyResponse =
+            from_binary(&query(&deps, mock_env(), simulate(50, "cw20ContractAddr")).unwrap())
+                .unwrap();
+        assert_eq!(
+            vec![
+                transfer(TransferKind::Proceeds, "seller", 45),
+                transfer(TransferKind::ProtocolFee, "creator", 3),
+            ],
+            res.transfers
+        );
+        assert_eq!(Uint128(2), res.referral_fee);
+        assert_eq!(Some(HumanAddr::from("buyer")), res.nft_recipient);
+        assert_eq!(None, res.error);
+
+        // anything paid above the list price goes back to the buyer
+        let res: SimulateBuyResponse =
+            from_binary(&query(&deps, mock_env(), simulate(60, "cw20ContractAddr")).unwrap())
+                .unwrap();
+        assert_eq!(
+            vec![
+                transfer(TransferKind::Proceeds, "seller", 45),
+                transfer(TransferKind::ProtocolFee, "creator", 3),
+                transfer(TransferKind::Refund, "buyer", 10),
+            ],
+            res.transfers
+        );
+        assert_eq!(Uint128(2), res.referral_fee);
+
+        // but leaves the offering and the referral earnings alone
+        let msg = QueryMsg::Offering {
+            offering_id: offering_id.clone(),
+        };
+        query(&deps, mock_env(), msg).unwrap();
+        let msg = QueryMsg::ReferralEarnings {
+            referrer: HumanAddr::from("frontend"),
+        };
+        let res: ReferralEarningsResponse =
+            from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert!(res.earnings.is_empty());
+
+        // failing purchases report the error of the handler
+        let res: SimulateBuyResponse =
+            from_binary(&query(&deps, mock_env(), simulate(49, "cw20ContractAddr")).unwrap())
+                .unwrap();
+        assert!(res.transfers.is_empty());
+        assert_eq!(
+            Some(ContractError::InsufficientFunds {}.to_string()),
+            res.error
+        );
+        let res: SimulateBuyResponse =
+            from_binary(&query(&deps, mock_env(), simulate(50, "otherToken")).unwrap()).unwrap();
+        assert_eq!(
+            Some(ContractError::InvalidCurrency {}.to_string()),
+            res.error
+        );
+
+        // transfers are told apart by what they are for, not by who gets them
+        let config = HandleMsg::UpdateConfig {
+            max_listings: None,
+            deposit: Some(Some(Deposit::Cw20(Cw20CoinHuman {
+                address: HumanAddr::from("cw20ContractAddr"),
+                amount: Uint128(1),
+            }))),
+            protocol_fee: None,
+            referral_share: None,
+        };
+        handle(&mut deps, mock_env(), mock_info("creator", &[]), config).unwrap();
+        let offering_id = list_nft_at(&mut deps, "creator", "SecondNFT", 50, None);
+        let msg = QueryMsg::SimulateBuy {
+            offering_id,
+            buyer: HumanAddr::from("buyer"),
+            amount: Uint128(50),
+            currency: HumanAddr::from("cw20ContractAddr"),
+            referrer: None,
+        };
+        let res: SimulateBuyResponse =
+            from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(
+            vec![
+                transfer(TransferKind::Proceeds, "creator", 45),
+                transfer(TransferKind::ProtocolFee, "creator", 5),
+                transfer(TransferKind::Refund, "creator", 1),
+            ],
+            res.transfers
+        );
+    }
+
+    #[test]
+    fn offering_metadata() {
+        let mut deps = mock_dependencies(&[]);
+        setup_contract(&mut deps);
+
+        // contracts without NftInfo can still list
+        let offering_id = list_nft(&mut deps, "seller", "FirstNFT", None);
+        let refresh = HandleMsg::RefreshMetadata {
+            offering_id: offering_id.clone(),
+        };
+        handle(
+            &mut deps,
+            mock_env(),
+            mock_info("anyone", &[]),
+            refresh.clone(),
+        )
+        .unwrap_err();
+
+        let mut monster = NftInfoResponse {
+            name: String::from("Cosmon"),
+            level: 3,
+            description: String::from("A fierce monster"),
+            image: Some(String::from("ipfs://cosmon")),
+        };
+        deps.querier.nft_info = Some(monster.clone());
+        let metadata = |level: u64| {
+            Some(NftMetadata {
+                name: String::from("Cosmon"),
+                image: Some(String::from("ipfs://cosmon")),
+                level: Some(level),
+            })
+        };
+
+        // the snapshot is taken at listing time
+        list_nft(&mut deps, "seller", "SecondNFT", None);
+        let msg = QueryMsg::GetOfferings {
+            resolve_prices: None,
+            min_reputation: None,
+            start_after: None,
+            limit: None,
+        };
+        let res: OfferingsResponse =
+            from_binary(&query(&deps, mock_env(), msg.clone()).unwrap()).unwrap();
+        assert_eq!(None, res.offerings[0].metadata);
+        assert_eq!(metadata(3), res.offerings[1].metadata);
+
+        // and updated on request by anybody
+        monster.level = 5;
+        deps.querier.nft_info = Some(monster);
+        handle(&mut deps, mock_env(), mock_info("anyone", &[]), refresh).unwrap();
+        let res: OfferingsResponse = from_binary(&query(&deps, mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(metadata(5), res.offerings[0].metadata);
     }
 
     //     #[test]
@@ -11,8 +11,84 @@ pub enum ContractError {
 
     #[error("Unauthorized")]
     Unauthorized {},
+
     // Add any other custom errors you like here.
     // Look at https://docs.rs/thiserror/1.0.21/thiserror/ for details.
+    #[error("Marketplace does not own the listed token")]
+    SpoofedListing {},
+
     #[error("Insufficient funds")]
     InsufficientFunds {},
+
+    #[error("Offering is not priced in this token")]
+    InvalidCurrency {},
+
+    #[error("Token is rented out")]
+    Rented {},
+
+    #[error("Token is reserved by another buyer")]
+    Reserved {},
+
+    #[error("Invalid listing terms")]
+    InvalidTerms {},
+
+    #[error("Installment deadline has not passed")]
+    NotDefaulted {},
+
+    #[error("Trade is disputed")]
+    Disputed {},
+
+    #[error("Trade is not disputed")]
+    NotDisputed {},
+
+    #[error("Dispute window is still open")]
+    DisputeWindowOpen {},
+
+    #[error("Dispute window has closed")]
+    DisputeWindowClosed {},
+
+    #[error("Seller has reached the maximum number of active listings")]
+    TooManyListings {},
+
+    #[error("Buyer can not refer their own purchase")]
+    SelfReferral {},
+
+    #[error("Referrals are only paid on plain sales")]
+    ReferralNotSupported {},
+
+    #[error("No unrated purchase for this offering")]
+    NothingToRate {},
+
+    #[error("Offering is not a raffle")]
+    NotRaffle {},
+
+    #[error("Raffle has ended")]
+    RaffleEnded {},
+
+    #[error("Raffle has not ended yet")]
+    RaffleNotEnded {},
+
+    #[error("Not enough raffle tickets left")]
+    SoldOut {},
+
+    #[error("Raffle tickets have been sold")]
+    TicketsSold {},
+
+    #[error("Not enough raffle tickets sold")]
+    NotEnoughTickets {},
+
+    #[error("Seed does not match the commitment")]
+    InvalidSeed {},
+
+    #[error("Raffle seed has not been revealed")]
+    SeedNotRevealed {},
+
+    #[error("Raffle can not be cancelled")]
+    NotCancellable {},
+
+    #[error("Given address already registered as a hook")]
+    HookAlreadyRegistered {},
+
+    #[error("Given address not registered as a hook")]
+    HookNotRegistered {},
 }
@@ -2,6 +2,7 @@ pub mod contract;
 mod error;
 pub mod msg;
 pub mod package;
+mod simulation;
 pub mod state;
 
 #[cfg(all(target_arch = "wasm32", not(feature = "library")))]
@@ -1,7 +1,8 @@
+use cosmwasm_std::{Binary, Coin, Decimal, HumanAddr, Uint128};
 use cw20::{Cw20CoinHuman, Cw20ReceiveMsg};
 use cw721::Cw721ReceiveMsg;
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InitMsg {
@@ -11,26 +12,272 @@ pub struct InitMsg {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
-    WithdrawNft { offering_id: String },
+    WithdrawNft {
+        offering_id: String,
+    },
     Receive(Cw20ReceiveMsg),
     ReceiveNft(Cw721ReceiveMsg),
+    /// Buyer or seller of an escrowed trade objects to it while the dispute window is open
+    Dispute {
+        offering_id: String,
+    },
+    /// The arbiter settles a disputed trade. With refund set the token goes back
+    /// to the seller and the payment to the buyer, otherwise the trade completes
+    ResolveDispute {
+        offering_id: String,
+        refund: bool,
+    },
+    /// Completes an undisputed escrowed trade once the dispute window has closed
+    Finalize {
+        offering_id: String,
+    },
+    /// Registers a contract to be notified of listings, sales and withdrawals, admin only
+    AddHook {
+        addr: HumanAddr,
+    },
+    /// Unregisters a previously added hook, admin only
+    RemoveHook {
+        addr: HumanAddr,
+    },
+    /// The seller reveals the seed committed to when listing a raffle, once it has ended
+    RevealRaffleSeed {
+        offering_id: String,
+        seed: String,
+    },
+    /// Draws the winner of an ended raffle with a revealed seed, callable by anyone
+    DrawRaffle {
+        offering_id: String,
+    },
+    /// Settles a reservation whose buyer missed the installment deadline, callable by anyone.
+    /// The seller keeps the forfeited share and the buyer gets the rest of the payments back
+    SettleDefault {
+        offering_id: String,
+    },
+    /// Refunds all tickets and returns the token to the seller, callable by anyone once the
+    /// raffle ended with too few tickets sold or the seed was not revealed in time
+    CancelRaffle {
+        offering_id: String,
+    },
+    /// Changes the anti-spam limits for new listings and the fees taken from sales, admin only.
+    /// Left out values are kept, null disables the limit or fee
+    UpdateConfig {
+        #[serde(
+            default,
+            deserialize_with = "patch",
+            skip_serializing_if = "Option::is_none"
+        )]
+        max_listings: Option<Option<u32>>,
+        #[serde(
+            default,
+            deserialize_with = "patch",
+            skip_serializing_if = "Option::is_none"
+        )]
+        deposit: Option<Option<Deposit>>,
+        /// Share of the sale price going to the admin
+        #[serde(
+            default,
+            deserialize_with = "patch",
+            skip_serializing_if = "Option::is_none"
+        )]
+        protocol_fee: Option<Option<Decimal>>,
+        /// Share of the protocol fee going to the referrer of a sale instead
+        #[serde(
+            default,
+            deserialize_with = "patch",
+            skip_serializing_if = "Option::is_none"
+        )]
+        referral_share: Option<Option<Decimal>>,
+    },
+    /// Returns a spam listing to its seller and forfeits its deposit, admin only
+    DelistSpam {
+        offering_id: String,
+    },
+    /// Adds the sent native funds to the sender's balance for listing deposits
+    TopUpDeposits {},
+    /// Pays out the sender's native deposit balance not locked in listings
+    WithdrawDeposits {},
+    /// Pays out all referral fees the sender earned
+    WithdrawReferralEarnings {},
+    /// Adds the offering to the sender's watchlist
+    Watch {
+        offering_id: String,
+    },
+    /// Removes the offering from the sender's watchlist
+    Unwatch {
+        offering_id: String,
+    },
+    /// The buyer of a completed purchase rates its seller, once per purchase
+    RateSeller {
+        offering_id: String,
+        positive: bool,
+    },
+    /// Takes a new snapshot of the offered token's metadata, anybody may call it
+    RefreshMetadata {
+        offering_id: String,
+    },
+}
+
+/// patch tells a null field, which is Some(None), from a left out one, which defaults to None
+fn patch<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Deposit locked for every listing while the offering is active. A cw20 deposit is taken
+/// from the seller's allowance, a native one from the balance paid in with TopUpDeposits
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Deposit {
+    Native(Coin),
+    Cw20(Cw20CoinHuman),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct SellNft {
     pub list_price: Cw20CoinHuman,
+    /// Unset for a plain sale
+    pub kind: Option<OfferingKind>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OfferingKind {
+    /// The token stays with the marketplace and is rented out for `blocks` blocks
+    /// at a time, list_price being the fee for one rental period
+    Rental { blocks: u64 },
+    /// Token and payment are held for `blocks` blocks after the purchase, during which
+    /// either side can dispute the trade and have the arbiter settle it
+    Escrow { arbiter: HumanAddr, blocks: u64 },
+    /// A buyer reserves the token with a down payment and has `blocks` blocks to pay
+    /// off the list_price. On default the seller keeps `forfeit_percent` percent of
+    /// the down payment, the rest is refunded and the token can be reserved again
+    Installment {
+        down_payment: Uint128,
+        blocks: u64,
+        forfeit_percent: u64,
+    },
+    /// Tickets priced at list_price are sold until `end_height`, after which the token is
+    /// raffled among them. `seed_commitment` is the sha256 hash of a secret seed the
+    /// seller reveals to draw the winner. A raffle sells at most 1000 tickets
+    Raffle {
+        tickets: u32,
+        min_tickets: u32,
+        end_height: u64,
+        seed_commitment: Binary,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct BuyNft {
     pub offering_id: String,
+    /// The frontend the purchase was made through, earns a share of the protocol fee
+    pub referrer: Option<HumanAddr>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
-    // GetOfferings returns a list of all offerings
-    GetOfferings {},
+    // GetOfferings returns a list of all offerings. With resolve_prices set, each offering
+    // also carries its price formatted with the decimals and symbol of the cw20 token.
+    // Offerings of sellers with a reputation score below min_reputation are left out.
+    // Pages of limit offerings, 10 unless given and at most 30, start after the start_after
+    // offering_id.
+    GetOfferings {
+        resolve_prices: Option<bool>,
+        min_reputation: Option<i64>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // Offering returns a single offering with its listing height, the number of other active
+    // listings of its seller and the number of watchers
+    Offering {
+        offering_id: String,
+    },
+    // SimulateBuy dry-runs paying amount of the currency token for the offering as buyer.
+    // It returns the resulting transfers or the error the purchase would fail with.
+    SimulateBuy {
+        offering_id: String,
+        buyer: HumanAddr,
+        amount: Uint128,
+        currency: HumanAddr,
+        referrer: Option<HumanAddr>,
+    },
+    // Watchlist returns the active offerings the given address watches
+    Watchlist {
+        watcher: HumanAddr,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // RentalsByRenter returns the active rentals of the given renter
+    RentalsByRenter {
+        renter: HumanAddr,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // RentalsByOwner returns the active rentals of tokens listed by the given owner
+    RentalsByOwner {
+        owner: HumanAddr,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // UserOf returns who may currently use a rented token, if anybody
+    UserOf {
+        contract_addr: HumanAddr,
+        token_id: String,
+    },
+    // Escrow returns the escrowed trade of a sold offering
+    Escrow {
+        offering_id: String,
+    },
+    // Hooks returns all contracts notified of offering changes
+    Hooks {},
+    // Installment returns the reservation of an installment offering
+    Installment {
+        offering_id: String,
+    },
+    // InstallmentsByBuyer returns all reservations of the given buyer
+    InstallmentsByBuyer {
+        buyer: HumanAddr,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // Raffle returns the ticket sales of a raffle offering
+    Raffle {
+        offering_id: String,
+    },
+    // Config returns the anti-spam limits for new listings and the fees taken from sales
+    Config {},
+    // Reputation returns the ratings and completed trades of the given address
+    Reputation {
+        address: HumanAddr,
+    },
+    // FloorPrice returns the lowest of the recent sale prices of a collection in a currency
+    FloorPrice {
+        contract_addr: HumanAddr,
+        currency: HumanAddr,
+    },
+    // MedianPrice returns the median of the recent sale prices of a collection in a currency
+    MedianPrice {
+        contract_addr: HumanAddr,
+        currency: HumanAddr,
+    },
+    // AveragePrice returns the recent sale prices of a collection in a currency, averaged
+    // over the blocks each price was the latest one
+    AveragePrice {
+        contract_addr: HumanAddr,
+        currency: HumanAddr,
+    },
+    // ReferralEarnings returns the referral fees the given referrer has not withdrawn yet
+    ReferralEarnings {
+        referrer: HumanAddr,
+    },
+    // DepositBalance returns the native funds of the given address available for deposits
+    DepositBalance {
+        address: HumanAddr,
+    },
 }
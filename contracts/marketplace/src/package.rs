@@ -1,5 +1,9 @@
-use cosmwasm_std::HumanAddr;
+use crate::msg::{Deposit, OfferingKind};
+use cosmwasm_std::{
+    to_binary, Binary, Coin, CosmosMsg, Decimal, HumanAddr, StdResult, Uint128, WasmMsg,
+};
 use cw20::Cw20CoinHuman;
+use cw721::Expiration;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -15,6 +19,28 @@ pub struct QueryOfferingsResult {
     pub list_price: Cw20CoinHuman,
     pub contract_addr: HumanAddr,
     pub seller: HumanAddr,
+    pub kind: Option<OfferingKind>,
+    /// list_price with decimals and symbol, e.g. "1.5 TKN". Only resolved on request
+    pub display_price: Option<String>,
+    pub metadata: Option<NftMetadata>,
+}
+
+/// NftMetadata is the snapshot of the token's NftInfo taken at listing time.
+/// Level is only reported by Cosmons
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct NftMetadata {
+    pub name: String,
+    pub image: Option<String>,
+    pub level: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OfferingResponse {
+    pub offering: QueryOfferingsResult,
+    pub listed_height: u64,
+    /// Active listings of the seller besides this one
+    pub seller_listings: u32,
+    pub watchers: u32,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -23,3 +49,196 @@ pub struct OfferingsResponse {
 }
 
 // THIS FILE SHOULD BE EXTRACTED TO ITS OWN PACKAGE PROJECT LIKE CW20 OR CW721
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct QueryRentalsResult {
+    pub offering_id: String,
+    pub token_id: String,
+    pub contract_addr: HumanAddr,
+    pub owner: HumanAddr,
+    pub user: HumanAddr,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RentalsResponse {
+    pub rentals: Vec<QueryRentalsResult>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UserOfResponse {
+    /// None if the token is not rented out right now
+    pub user: Option<HumanAddr>,
+    pub expires: Option<Expiration>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EscrowResponse {
+    pub offering_id: String,
+    pub token_id: String,
+    pub contract_addr: HumanAddr,
+    pub seller: HumanAddr,
+    pub buyer: HumanAddr,
+    pub arbiter: HumanAddr,
+    pub payment: Cw20CoinHuman,
+    pub expires: Expiration,
+    pub disputed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstallmentResponse {
+    pub offering_id: String,
+    pub buyer: HumanAddr,
+    pub paid: Uint128,
+    pub outstanding: Uint128,
+    pub deadline: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstallmentsResponse {
+    pub installments: Vec<InstallmentResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub max_listings: Option<u32>,
+    pub deposit: Option<Deposit>,
+    pub protocol_fee: Option<Decimal>,
+    pub referral_share: Option<Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReputationResponse {
+    pub address: HumanAddr,
+    pub positive: u64,
+    pub negative: u64,
+    /// positive minus negative ratings
+    pub score: i64,
+    pub sales: u64,
+    pub purchases: u64,
+}
+
+/// PriceResponse is the answer to all price queries. Price is unset without any sales
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceResponse {
+    pub contract_addr: HumanAddr,
+    pub currency: HumanAddr,
+    pub price: Option<Uint128>,
+    /// Number of sales the price is based on
+    pub sales: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferKind {
+    Proceeds,
+    ProtocolFee,
+    Refund,
+}
+
+/// A cw20 transfer in the currency the purchase is paid with
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulatedTransfer {
+    pub kind: TransferKind,
+    pub recipient: HumanAddr,
+    pub amount: Uint128,
+}
+
+/// SimulateBuyResponse describes what a purchase would do. On failure only error is set
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateBuyResponse {
+    pub offering_id: String,
+    pub transfers: Vec<SimulatedTransfer>,
+    /// Share of the protocol fee credited to the referrer's earnings
+    pub referral_fee: Uint128,
+    /// Address the nft is transferred to, unset if the purchase doesn't release it yet
+    pub nft_recipient: Option<HumanAddr>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReferralEarningsResponse {
+    pub earnings: Vec<Cw20CoinHuman>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DepositBalanceResponse {
+    pub balance: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RaffleResponse {
+    pub offering_id: String,
+    pub tickets_sold: u32,
+    pub tickets: u32,
+    pub min_tickets: u32,
+    pub end_height: u64,
+    pub seed_revealed: bool,
+}
+
+/// SellResponse is returned as data when a token is listed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SellResponse {
+    pub offering_id: String,
+}
+
+/// BuyResponse is returned as data when an offering is bought
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BuyResponse {
+    pub offering_id: String,
+    pub token_id: String,
+    pub contract_addr: HumanAddr,
+    pub seller: HumanAddr,
+    pub buyer: HumanAddr,
+    pub paid_price: Cw20CoinHuman,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HooksResponse {
+    pub hooks: Vec<HumanAddr>,
+}
+
+/// MarketplaceHookMsg is sent to every registered hook when the offerings change.
+/// It should be de/serialized under `MarketplaceHook()` variant in a HandleMsg
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketplaceHookMsg {
+    Listed {
+        offering: QueryOfferingsResult,
+    },
+    Sold {
+        offering: QueryOfferingsResult,
+        buyer: HumanAddr,
+        paid_price: Cw20CoinHuman,
+    },
+    Withdrawn {
+        offering: QueryOfferingsResult,
+    },
+}
+
+impl MarketplaceHookMsg {
+    /// serializes the message
+    pub fn into_binary(self) -> StdResult<Binary> {
+        let msg = MarketplaceHookHandleMsg::MarketplaceHook(self);
+        to_binary(&msg)
+    }
+
+    /// creates a cosmos_msg sending this struct to the named contract
+    pub fn into_cosmos_msg(self, contract_addr: HumanAddr) -> StdResult<CosmosMsg> {
+        let msg = self.into_binary()?;
+        let execute = WasmMsg::Execute {
+            contract_addr,
+            msg,
+            send: vec![],
+        };
+        Ok(execute.into())
+    }
+}
+
+/// This is just a helper to properly serialize the above message.
+/// The actual receiver should include this variant in the larger HandleMsg enum
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+enum MarketplaceHookHandleMsg {
+    MarketplaceHook(MarketplaceHookMsg),
+}
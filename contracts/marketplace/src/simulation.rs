@@ -0,0 +1,111 @@
+use cosmwasm_std::{Api, Extern, Order, Querier, QuerierResult, ReadonlyStorage, Storage, KV};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+/// Storage that reads through to the contract storage but keeps all writes in memory,
+/// so handlers can be dry-run from queries without persisting anything
+pub struct OverlayStorage<'a, S: ReadonlyStorage> {
+    base: &'a S,
+    // None marks a removed key
+    changes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl<'a, S: ReadonlyStorage> OverlayStorage<'a, S> {
+    pub fn new(base: &'a S) -> Self {
+        OverlayStorage {
+            base,
+            changes: BTreeMap::new(),
+        }
+    }
+}
+
+impl<'a, S: ReadonlyStorage> ReadonlyStorage for OverlayStorage<'a, S> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        match self.changes.get(key) {
+            Some(change) => change.clone(),
+            None => self.base.get(key),
+        }
+    }
+
+    fn range<'b>(
+        &'b self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = KV> + 'b> {
+        // BTreeMap::range panics on inverted bounds
+        if let (Some(start), Some(end)) = (start, end) {
+            if start >= end {
+                return Box::new(std::iter::empty());
+            }
+        }
+        let bounds = (
+            start.map_or(Bound::Unbounded, Bound::Included),
+            end.map_or(Bound::Unbounded, Bound::Excluded),
+        );
+        let changes = self.changes.range::<[u8], _>(bounds);
+        let descending = matches!(order, Order::Descending);
+        let mut changes: Box<dyn Iterator<Item = _> + 'b> = if descending {
+            Box::new(changes.rev())
+        } else {
+            Box::new(changes)
+        };
+        let mut changes_peeked = changes.next();
+        let mut base = self.base.range(start, end, order).peekable();
+
+        // walk both in order, a change replaces or removes the base entry with the same key
+        Box::new(std::iter::from_fn(move || loop {
+            let next = match (base.peek(), &changes_peeked) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((key, _)), Some((changed, _))) if descending => {
+                    changed.as_slice().cmp(key.as_slice())
+                }
+                (Some((key, _)), Some((changed, _))) => key.as_slice().cmp(changed.as_slice()),
+            };
+            if next == Ordering::Less {
+                return base.next();
+            }
+            if next == Ordering::Equal {
+                base.next();
+            }
+            let (key, change) = changes_peeked.take()?;
+            changes_peeked = changes.next();
+            if let Some(value) = change {
+                return Some((key.clone(), value.clone()));
+            }
+        }))
+    }
+}
+
+impl<'a, S: ReadonlyStorage> Storage for OverlayStorage<'a, S> {
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.changes.insert(key.to_vec(), Some(value.to_vec()));
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.changes.insert(key.to_vec(), None);
+    }
+}
+
+/// Querier forwarding to a borrowed querier
+pub struct QuerierRef<'a, Q: Querier>(&'a Q);
+
+impl<'a, Q: Querier> Querier for QuerierRef<'a, Q> {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        self.0.raw_query(bin_request)
+    }
+}
+
+/// Dependencies for dry-running a handler on top of the given ones
+pub fn simulation_deps<'a, S: Storage, A: Api, Q: Querier>(
+    deps: &'a Extern<S, A, Q>,
+) -> Extern<OverlayStorage<'a, S>, A, QuerierRef<'a, Q>> {
+    Extern {
+        storage: OverlayStorage::new(&deps.storage),
+        api: deps.api,
+        querier: QuerierRef(&deps.querier),
+    }
+}
@@ -1,10 +1,13 @@
-use crate::package::ContractInfoResponse;
+use crate::msg::{Deposit, OfferingKind};
+use crate::package::{ContractInfoResponse, NftMetadata};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{CanonicalAddr, StdResult, Storage};
+use cosmwasm_std::{CanonicalAddr, Decimal, StdResult, Storage, Uint128};
+use cw0::NativeBalance;
 use cw20::Cw20CoinHuman;
-use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
+use cw721::Expiration;
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex, UniqueIndex};
 
 pub static CONFIG_KEY: &[u8] = b"config";
 
@@ -17,12 +20,51 @@ pub struct Offering {
     pub seller: CanonicalAddr,
 
     pub list_price: Cw20CoinHuman,
+
+    pub kind: Option<OfferingKind>,
+
+    /// The listing deposit locked until the offering is sold or withdrawn
+    pub deposit: Option<Deposit>,
+
+    /// Zero for offerings listed before the height was recorded
+    #[serde(default)]
+    pub listed_height: u64,
+
+    /// Name, image and level of the token, unset if its contract didn't answer NftInfo
+    pub metadata: Option<NftMetadata>,
+}
+
+/// Config holds the anti-spam limits for new listings and the fees taken from sales
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema, Debug)]
+pub struct Config {
+    pub max_listings: Option<u32>,
+    pub deposit: Option<Deposit>,
+    pub protocol_fee: Option<Decimal>,
+    pub referral_share: Option<Decimal>,
+}
+
+/// TokenDisplay is what it takes from a cw20 TokenInfoResponse to format prices
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct TokenDisplay {
+    pub symbol: String,
+    pub decimals: u8,
 }
 
 /// OFFERINGS is a map which maps the offering_id to an offering. Offering_id is derived from OFFERINGS_COUNT.
+/// It shares its namespace with offerings(), which has to be used for writes to keep the indexes in sync.
 pub const OFFERINGS: Map<&str, Offering> = Map::new(b"offerings");
 pub const OFFERINGS_COUNT: Item<u64> = Item::new(b"num_offerings");
 pub const CONTRACT_INFO: Item<ContractInfoResponse> = Item::new(b"marketplace_info");
+pub const ADMIN: Item<CanonicalAddr> = Item::new(b"admin");
+pub const CONFIG: Item<Config> = Item::new(CONFIG_KEY);
+/// DEPOSIT_BALANCES are the native funds of each address available for listing deposits
+pub const DEPOSIT_BALANCES: Map<&[u8], NativeBalance> = Map::new(b"deposit_balances");
+/// TOKEN_DISPLAY caches the symbol and decimals of the cw20 tokens offerings are priced in
+pub const TOKEN_DISPLAY: Map<&[u8], TokenDisplay> = Map::new(b"token_display");
+/// REFERRAL_EARNINGS are the referral fees each referrer can withdraw, one entry per cw20 token
+pub const REFERRAL_EARNINGS: Map<&[u8], Vec<Cw20CoinHuman>> = Map::new(b"referral_earnings");
+/// HOOKS are the contracts receiving a MarketplaceHookMsg on every offering change
+pub const HOOKS: Item<Vec<CanonicalAddr>> = Item::new(b"hooks");
 
 pub fn num_offerings<S: Storage>(storage: &S) -> StdResult<u64> {
     Ok(OFFERINGS_COUNT.may_load(storage)?.unwrap_or_default())
@@ -57,3 +99,204 @@ pub fn offerings<'a, S: Storage>() -> IndexedMap<'a, &'a str, Offering, S, Offer
     };
     IndexedMap::new(b"offerings", indexes)
 }
+
+/// Rental records who may use a rented out token and until when. It is keyed by the
+/// offering_id of the rental listing and kept until the owner withdraws the token.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Rental {
+    pub token_id: String,
+
+    pub contract_addr: CanonicalAddr,
+
+    pub owner: CanonicalAddr,
+
+    pub user: CanonicalAddr,
+
+    pub expires: Expiration,
+}
+
+pub struct RentalIndexes<'a, S: Storage> {
+    pub user: MultiIndex<'a, S, Rental>,
+    pub owner: MultiIndex<'a, S, Rental>,
+    pub token: UniqueIndex<'a, S, Rental>,
+}
+
+impl<'a, S: Storage> IndexList<S, Rental> for RentalIndexes<'a, S> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<S, Rental>> + '_> {
+        let v: Vec<&dyn Index<S, Rental>> = vec![&self.user, &self.owner, &self.token];
+        Box::new(v.into_iter())
+    }
+}
+
+/// token_key identifies a token across all cw721 contracts
+pub fn token_key(contract_addr: &CanonicalAddr, token_id: &str) -> Vec<u8> {
+    let mut key = contract_addr.to_vec();
+    key.extend_from_slice(token_id.as_bytes());
+    key
+}
+
+pub fn rentals<'a, S: Storage>() -> IndexedMap<'a, &'a str, Rental, S, RentalIndexes<'a, S>> {
+    let indexes = RentalIndexes {
+        user: MultiIndex::new(|r| r.user.to_vec(), b"rentals", b"rentals__user"),
+        owner: MultiIndex::new(|r| r.owner.to_vec(), b"rentals", b"rentals__owner"),
+        token: UniqueIndex::new(
+            |r| token_key(&r.contract_addr, &r.token_id),
+            b"rentals__token",
+        ),
+    };
+    IndexedMap::new(b"rentals", indexes)
+}
+
+/// Escrow holds the token and the payment of a sold escrow offering until the
+/// trade is finalized or the arbiter settles a dispute. It is keyed by offering_id.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Escrow {
+    pub token_id: String,
+
+    pub contract_addr: CanonicalAddr,
+
+    pub seller: CanonicalAddr,
+
+    pub buyer: CanonicalAddr,
+
+    pub arbiter: CanonicalAddr,
+
+    pub payment: Cw20CoinHuman,
+
+    /// List price of the offering, which goes into the price window once the trade is final
+    pub list_price: Uint128,
+
+    /// End of the dispute window
+    pub expires: Expiration,
+
+    pub disputed: bool,
+}
+
+pub const ESCROWS: Map<&str, Escrow> = Map::new(b"escrows");
+
+/// Installment is the reservation of an installment offering by a buyer. It is keyed by
+/// offering_id and removed once the token is paid off or the reservation is settled.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Installment {
+    pub buyer: CanonicalAddr,
+
+    /// All payments so far, held by the marketplace
+    pub paid: Uint128,
+
+    /// The token has to be paid off before
+    pub deadline: Expiration,
+}
+
+pub struct InstallmentIndexes<'a, S: Storage> {
+    pub buyer: MultiIndex<'a, S, Installment>,
+}
+
+impl<'a, S: Storage> IndexList<S, Installment> for InstallmentIndexes<'a, S> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<S, Installment>> + '_> {
+        let v: Vec<&dyn Index<S, Installment>> = vec![&self.buyer];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn installments<'a, S: Storage>(
+) -> IndexedMap<'a, &'a str, Installment, S, InstallmentIndexes<'a, S>> {
+    let indexes = InstallmentIndexes {
+        buyer: MultiIndex::new(
+            |i| i.buyer.to_vec(),
+            b"installments",
+            b"installments__buyer",
+        ),
+    };
+    IndexedMap::new(b"installments", indexes)
+}
+
+/// Raffle counts the tickets sold for a raffle offering and holds the seller's seed once
+/// revealed. It is keyed by offering_id.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema, Debug)]
+pub struct Raffle {
+    pub sold: u32,
+    pub seed: Option<String>,
+}
+
+pub const RAFFLES: Map<&str, Raffle> = Map::new(b"raffles");
+/// RAFFLE_TICKETS maps (offering_id, number of the first ticket) of every ticket purchase to
+/// its buyer, who holds all tickets up to the first ticket of the next purchase
+pub const RAFFLE_TICKETS: Map<(&str, &[u8]), CanonicalAddr> = Map::new(b"raffle_tickets");
+
+/// Reputation counts the completed trades of an address and the ratings it got as a seller
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema, Debug)]
+pub struct Reputation {
+    pub positive: u64,
+    pub negative: u64,
+    pub sales: u64,
+    pub purchases: u64,
+}
+
+impl Reputation {
+    /// score is the balance of positive and negative ratings
+    pub fn score(&self) -> i64 {
+        self.positive as i64 - self.negative as i64
+    }
+}
+
+pub const REPUTATIONS: Map<&[u8], Reputation> = Map::new(b"reputations");
+
+/// Trade is a completed purchase the buyer has not rated yet. It is keyed by offering_id.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Trade {
+    pub seller: CanonicalAddr,
+    pub buyer: CanonicalAddr,
+}
+
+pub const UNRATED_TRADES: Map<&str, Trade> = Map::new(b"unrated_trades");
+
+/// PricePoint is a completed sale as seen by the price queries
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PricePoint {
+    pub price: Uint128,
+    pub height: u64,
+}
+
+/// PRICE_WINDOWS keeps the most recent sales per (collection, currency), oldest first
+pub const PRICE_WINDOWS: Map<(&[u8], &[u8]), Vec<PricePoint>> = Map::new(b"price_windows");
+
+/// Watch records that an address follows an offering. Watches are not removed together
+/// with the offering, queries skip those of offerings which are gone.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Watch {
+    pub offering_id: String,
+    pub watcher: CanonicalAddr,
+}
+
+pub struct WatchIndexes<'a, S: Storage> {
+    pub offering: MultiIndex<'a, S, Watch>,
+    pub watcher: MultiIndex<'a, S, Watch>,
+}
+
+impl<'a, S: Storage> IndexList<S, Watch> for WatchIndexes<'a, S> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<S, Watch>> + '_> {
+        let v: Vec<&dyn Index<S, Watch>> = vec![&self.offering, &self.watcher];
+        Box::new(v.into_iter())
+    }
+}
+
+/// watch_key identifies the watch of an offering by an address. The watcher is length
+/// prefixed like the index keys of MultiIndex, so no two (watcher, offering) pairs collide
+pub fn watch_key(watcher: &CanonicalAddr, offering_id: &str) -> Vec<u8> {
+    let mut key = (watcher.len() as u16).to_be_bytes().to_vec();
+    key.extend_from_slice(watcher.as_slice());
+    key.extend_from_slice(offering_id.as_bytes());
+    key
+}
+
+pub fn watches<'a, S: Storage>() -> IndexedMap<'a, &'a [u8], Watch, S, WatchIndexes<'a, S>> {
+    let indexes = WatchIndexes {
+        offering: MultiIndex::new(
+            |w| w.offering_id.as_bytes().to_vec(),
+            b"watches",
+            b"watches__offering",
+        ),
+        watcher: MultiIndex::new(|w| w.watcher.to_vec(), b"watches", b"watches__watcher"),
+    };
+    IndexedMap::new(b"watches", indexes)
+}